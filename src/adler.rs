@@ -0,0 +1,50 @@
+//! Adler-32 checksums that can be combined, for zlib streams put together out
+//! of separately compressed blocks.
+
+// The largest prime smaller than 2^16.
+const BASE: u32 = 65521;
+// The most bytes which can be summed up before the sums could overflow.
+const NMAX: usize = 5552;
+
+/// The Adler-32 checksum of some data, along with its length.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Adler32 {
+    a: u32,
+    b: u32,
+    amt: u64,
+}
+
+impl Adler32 {
+    /// Creates the checksum of no data at all.
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0, amt: 0 }
+    }
+
+    /// Returns the current checksum.
+    pub fn sum(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+
+    /// Updates the checksum with the bytes in `data`.
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(NMAX) {
+            for &byte in chunk {
+                self.a += u32::from(byte);
+                self.b += self.a;
+            }
+            self.a %= BASE;
+            self.b %= BASE;
+        }
+        self.amt += data.len() as u64;
+    }
+
+    /// Combines the checksum with the one of the data following it, as zlib's
+    /// `adler32_combine` does.
+    pub fn combine(&mut self, other: &Adler32) {
+        let rem = (other.amt % u64::from(BASE)) as u32;
+        let shifted = (u64::from(rem) * u64::from(self.a) % u64::from(BASE)) as u32;
+        self.a = (self.a + other.a + BASE - 1) % BASE;
+        self.b = (shifted + self.b + other.b + BASE - rem) % BASE;
+        self.amt += other.amt;
+    }
+}
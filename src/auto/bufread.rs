@@ -0,0 +1,307 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+
+use super::{detect, DetectedFormat};
+use crate::bufread::{DeflateDecoder, GzDecoder, MultiGzDecoder, ZlibDecoder};
+use crate::DecompressLimits;
+
+/// A decoder which detects whether its input is gzip, zlib or raw deflate.
+///
+/// This structure consumes a [`BufRead`] interface, looking at the first two
+/// bytes of the underlying reader to pick the decoder for the rest of the
+/// stream. Gzip is recognised by its magic bytes and zlib by a valid header
+/// checksum; anything else is decoded as raw deflate, or returned unchanged
+/// if [`passthrough`] is enabled.
+///
+/// Detection is a heuristic: uncompressed data which happens to start with a
+/// valid zlib header, such as the text `"x^"`, is treated as zlib.
+///
+/// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+/// [`passthrough`]: #method.passthrough
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::GzEncoder;
+/// use flate2::bufread::AutoDecoder;
+/// use flate2::DetectedFormat;
+///
+/// # fn main() {
+/// # let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// # e.write_all(b"Hello World").unwrap();
+/// # let bytes = e.finish().unwrap();
+/// # println!("{}", decode_bufreader(bytes).unwrap());
+/// # }
+/// #
+/// // Uncompresses a gzip, zlib or deflate encoded vector of bytes and
+/// // returns a string or error
+/// // Here &[u8] implements BufRead
+///
+/// fn decode_bufreader(bytes: Vec<u8>) -> io::Result<String> {
+///     let mut d = AutoDecoder::new(&bytes[..]);
+///     let mut s = String::new();
+///     d.read_to_string(&mut s)?;
+///     assert_eq!(d.format(), Some(DetectedFormat::Gzip));
+///     Ok(s)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<R> {
+    inner: Inner<R>,
+    multi_member: bool,
+    passthrough: bool,
+    limits: DecompressLimits,
+}
+
+#[derive(Debug)]
+enum Inner<R> {
+    // Only `None` while switching over to a decoder.
+    Detecting(Option<Peeked<R>>),
+    Deflate(DeflateDecoder<Peeked<R>>),
+    Zlib(ZlibDecoder<Peeked<R>>),
+    Gzip(GzDecoder<Peeked<R>>),
+    MultiGzip(MultiGzDecoder<Peeked<R>>),
+    Passthrough(Peeked<R>),
+}
+
+/// A reader which hands out the bytes consumed for detection before reading
+/// from the underlying reader again.
+///
+/// A single `fill_buf` call may return fewer bytes than detection needs, so
+/// they are moved into a small buffer of their own.
+#[derive(Debug)]
+struct Peeked<R> {
+    buf: [u8; 2],
+    pos: usize,
+    len: usize,
+    inner: R,
+}
+
+impl<R: BufRead> Peeked<R> {
+    fn peek(&mut self) -> io::Result<&[u8]> {
+        while self.len < self.buf.len() {
+            let n = {
+                let available = self.inner.fill_buf()?;
+                let n = cmp::min(available.len(), self.buf.len() - self.len);
+                self.buf[self.len..self.len + n].copy_from_slice(&available[..n]);
+                n
+            };
+            if n == 0 {
+                break;
+            }
+            self.inner.consume(n);
+            self.len += n;
+        }
+        Ok(&self.buf[..self.len])
+    }
+}
+
+impl<R: BufRead> Read for Peeked<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let mut available = self.fill_buf()?;
+            available.read(into)?
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Peeked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos < self.len {
+            Ok(&self.buf[self.pos..self.len])
+        } else {
+            self.inner.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.len {
+            self.pos += amt;
+        } else {
+            self.inner.consume(amt);
+        }
+    }
+}
+
+impl<R: BufRead> AutoDecoder<R> {
+    /// Creates a new decoder which will detect the format of the given
+    /// stream and decompress it.
+    pub fn new(r: R) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: Inner::Detecting(Some(Peeked {
+                buf: [0; 2],
+                pos: 0,
+                len: 0,
+                inner: r,
+            })),
+            multi_member: false,
+            passthrough: false,
+            limits: DecompressLimits::new(),
+        }
+    }
+
+    fn detect(&mut self) -> io::Result<()> {
+        let peeked = match self.inner {
+            Inner::Detecting(ref mut peeked) => peeked,
+            _ => return Ok(()),
+        };
+        let format = detect(peeked.as_mut().unwrap().peek()?, self.passthrough);
+        let peeked = peeked.take().unwrap();
+        let limits = self.limits;
+        self.inner = match format {
+            DetectedFormat::Deflate => {
+                let mut d = DeflateDecoder::new(peeked);
+                d.set_limits(limits);
+                Inner::Deflate(d)
+            }
+            DetectedFormat::Zlib => {
+                let mut d = ZlibDecoder::new(peeked);
+                d.set_limits(limits);
+                Inner::Zlib(d)
+            }
+            DetectedFormat::Gzip if self.multi_member => {
+                let mut d = MultiGzDecoder::new(peeked);
+                d.set_limits(limits);
+                Inner::MultiGzip(d)
+            }
+            DetectedFormat::Gzip => {
+                let mut d = GzDecoder::new(peeked);
+                d.set_limits(limits);
+                Inner::Gzip(d)
+            }
+            DetectedFormat::Passthrough => Inner::Passthrough(peeked),
+        };
+        Ok(())
+    }
+}
+
+impl<R> AutoDecoder<R> {
+    /// Configures whether every member of a gzip stream is decoded, as with
+    /// [`MultiGzDecoder`], rather than only the first one.
+    ///
+    /// This has no effect once reading has started.
+    ///
+    /// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+    pub fn multi_member(mut self, multi_member: bool) -> AutoDecoder<R> {
+        self.multi_member = multi_member;
+        self
+    }
+
+    /// Configures whether input which is neither gzip nor zlib is returned
+    /// unchanged instead of being decoded as raw deflate.
+    ///
+    /// This has no effect once reading has started.
+    pub fn passthrough(mut self, passthrough: bool) -> AutoDecoder<R> {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Configures limits on the output of the decoder, see
+    /// [`DecompressLimits`]. They don't apply to input which is passed
+    /// through unchanged.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+        match self.inner {
+            Inner::Detecting(_) | Inner::Passthrough(_) => {}
+            Inner::Deflate(ref mut d) => d.set_limits(limits),
+            Inner::Zlib(ref mut d) => d.set_limits(limits),
+            Inner::Gzip(ref mut d) => d.set_limits(limits),
+            Inner::MultiGzip(ref mut d) => d.set_limits(limits),
+        }
+    }
+
+    /// Returns the detected format of the stream, or `None` if nothing has
+    /// been read yet.
+    pub fn format(&self) -> Option<DetectedFormat> {
+        match self.inner {
+            Inner::Detecting(_) => None,
+            Inner::Deflate(_) => Some(DetectedFormat::Deflate),
+            Inner::Zlib(_) => Some(DetectedFormat::Zlib),
+            Inner::Gzip(_) | Inner::MultiGzip(_) => Some(DetectedFormat::Gzip),
+            Inner::Passthrough(_) => Some(DetectedFormat::Passthrough),
+        }
+    }
+
+    fn peeked(&self) -> &Peeked<R> {
+        match self.inner {
+            Inner::Detecting(ref peeked) => peeked.as_ref().unwrap(),
+            Inner::Deflate(ref d) => d.get_ref(),
+            Inner::Zlib(ref d) => d.get_ref(),
+            Inner::Gzip(ref d) => d.get_ref(),
+            Inner::MultiGzip(ref d) => d.get_ref(),
+            Inner::Passthrough(ref peeked) => peeked,
+        }
+    }
+
+    fn peeked_mut(&mut self) -> &mut Peeked<R> {
+        match self.inner {
+            Inner::Detecting(ref mut peeked) => peeked.as_mut().unwrap(),
+            Inner::Deflate(ref mut d) => d.get_mut(),
+            Inner::Zlib(ref mut d) => d.get_mut(),
+            Inner::Gzip(ref mut d) => d.get_mut(),
+            Inner::MultiGzip(ref mut d) => d.get_mut(),
+            Inner::Passthrough(ref mut peeked) => peeked,
+        }
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.peeked().inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.peeked_mut().inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    ///
+    /// Note that the bytes read to detect the format are not returned to the
+    /// reader. It's recommended to only call this function after EOF has been
+    /// reached.
+    pub fn into_inner(self) -> R {
+        match self.inner {
+            Inner::Detecting(peeked) => peeked.unwrap().inner,
+            Inner::Deflate(d) => d.into_inner().inner,
+            Inner::Zlib(d) => d.into_inner().inner,
+            Inner::Gzip(d) => d.into_inner().inner,
+            Inner::MultiGzip(d) => d.into_inner().inner,
+            Inner::Passthrough(peeked) => peeked.inner,
+        }
+    }
+}
+
+impl<R: BufRead> Read for AutoDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.detect()?;
+        match self.inner {
+            Inner::Detecting(_) => unreachable!(),
+            Inner::Deflate(ref mut d) => d.read(into),
+            Inner::Zlib(ref mut d) => d.read(into),
+            Inner::Gzip(ref mut d) => d.read(into),
+            Inner::MultiGzip(ref mut d) => d.read(into),
+            Inner::Passthrough(ref mut peeked) => peeked.read(into),
+        }
+    }
+}
+
+impl<R: BufRead + Write> Write for AutoDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
@@ -0,0 +1,170 @@
+pub mod bufread;
+pub mod read;
+pub mod write;
+
+/// The format of a stream as detected by an `AutoDecoder`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DetectedFormat {
+    /// A raw deflate stream, assumed when no other format matched.
+    Deflate,
+
+    /// A zlib stream.
+    Zlib,
+
+    /// A gzip stream.
+    Gzip,
+
+    /// Data which is not compressed and is passed through unchanged.
+    Passthrough,
+}
+
+/// Determines the format of a stream from its first two bytes, or fewer if
+/// the stream is shorter than that.
+///
+/// A gzip stream is recognised by its magic bytes and a zlib stream by a
+/// valid header checksum. Anything else is taken to be raw deflate, or is
+/// passed through if `passthrough` is set.
+pub(crate) fn detect(header: &[u8], passthrough: bool) -> DetectedFormat {
+    match *header {
+        [0x1f, 0x8b, ..] => DetectedFormat::Gzip,
+        [cmf, flg, ..]
+            if cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 =>
+        {
+            DetectedFormat::Zlib
+        }
+        _ if passthrough => DetectedFormat::Passthrough,
+        _ => DetectedFormat::Deflate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::{detect, read, write, DetectedFormat};
+    use crate::{bufread, Compression};
+
+    fn encode(format: DetectedFormat, data: &[u8]) -> Vec<u8> {
+        match format {
+            DetectedFormat::Deflate => {
+                let mut e = crate::write::DeflateEncoder::new(Vec::new(), Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            DetectedFormat::Zlib => {
+                let mut e = crate::write::ZlibEncoder::new(Vec::new(), Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            DetectedFormat::Gzip => {
+                let mut e = crate::write::GzEncoder::new(Vec::new(), Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            DetectedFormat::Passthrough => data.to_vec(),
+        }
+    }
+
+    const FORMATS: [DetectedFormat; 4] = [
+        DetectedFormat::Deflate,
+        DetectedFormat::Zlib,
+        DetectedFormat::Gzip,
+        DetectedFormat::Passthrough,
+    ];
+
+    #[test]
+    fn detect_headers() {
+        assert_eq!(detect(&[0x1f, 0x8b], false), DetectedFormat::Gzip);
+        assert_eq!(detect(&[0x78, 0x9c], false), DetectedFormat::Zlib);
+        assert_eq!(detect(&[0x78, 0x01], true), DetectedFormat::Zlib);
+        assert_eq!(detect(&[0x78, 0x9d], false), DetectedFormat::Deflate);
+        assert_eq!(detect(&[0x78, 0x9d], true), DetectedFormat::Passthrough);
+        assert_eq!(detect(&[0x1f], false), DetectedFormat::Deflate);
+        assert_eq!(detect(&[], true), DetectedFormat::Passthrough);
+    }
+
+    #[test]
+    fn bufread_roundtrip() {
+        let data = b"hello world, hello world, hello world!".repeat(50);
+        for &format in FORMATS.iter() {
+            let encoded = encode(format, &data);
+            let mut d = bufread::AutoDecoder::new(&encoded[..])
+                .passthrough(format == DetectedFormat::Passthrough);
+            assert_eq!(d.format(), None);
+            let mut decoded = Vec::new();
+            d.read_to_end(&mut decoded).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(d.format(), Some(format));
+        }
+    }
+
+    #[test]
+    fn read_roundtrip_small_buffer() {
+        // A one byte buffer makes the header arrive across two reads.
+        let data = b"hello world, hello world, hello world!".repeat(50);
+        for &format in FORMATS.iter() {
+            let encoded = encode(format, &data);
+            let mut d = read::AutoDecoder::new_with_buf(&encoded[..], vec![0; 1])
+                .passthrough(format == DetectedFormat::Passthrough);
+            let mut decoded = Vec::new();
+            d.read_to_end(&mut decoded).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(d.format(), Some(format));
+        }
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let data = b"hello world, hello world, hello world!".repeat(50);
+        for &format in FORMATS.iter() {
+            let encoded = encode(format, &data);
+            let mut d = write::AutoDecoder::new(Vec::new())
+                .passthrough(format == DetectedFormat::Passthrough);
+            for chunk in encoded.chunks(1) {
+                d.write_all(chunk).unwrap();
+            }
+            assert_eq!(d.format(), Some(format));
+            assert_eq!(d.finish().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn short_input() {
+        let mut decoded = Vec::new();
+        read::AutoDecoder::new(&b"x"[..])
+            .passthrough(true)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"x");
+
+        let mut d = write::AutoDecoder::new(Vec::new()).passthrough(true);
+        d.write_all(b"x").unwrap();
+        assert_eq!(d.format(), None);
+        assert_eq!(d.finish().unwrap(), b"x");
+    }
+
+    #[test]
+    fn multi_member() {
+        let mut encoded = encode(DetectedFormat::Gzip, b"hello ");
+        encoded.extend(encode(DetectedFormat::Gzip, b"world"));
+
+        let mut decoded = String::new();
+        read::AutoDecoder::new(&encoded[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello ");
+
+        let mut decoded = String::new();
+        read::AutoDecoder::new(&encoded[..])
+            .multi_member(true)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello world");
+
+        let mut d = write::AutoDecoder::new(Vec::new()).multi_member(true);
+        d.write_all(&encoded).unwrap();
+        assert_eq!(d.finish().unwrap(), b"hello world");
+    }
+}
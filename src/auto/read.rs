@@ -0,0 +1,144 @@
+use std::io;
+use std::io::prelude::*;
+
+use super::{bufread, DetectedFormat};
+use crate::bufreader::BufReader;
+
+/// A decoder which detects whether its input is gzip, zlib or raw deflate.
+///
+/// This structure implements a [`Read`] interface and will read compressed
+/// data from an underlying stream, looking at its first two bytes to pick the
+/// decoder for the rest of the stream. See [`bufread::AutoDecoder`] for how
+/// the format is detected.
+///
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`bufread::AutoDecoder`]: ../bufread/struct.AutoDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::ZlibEncoder;
+/// use flate2::read::AutoDecoder;
+/// use flate2::DetectedFormat;
+///
+/// # fn main() {
+/// # let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+/// # e.write_all(b"Hello World").unwrap();
+/// # let bytes = e.finish().unwrap();
+/// # println!("{}", decode_reader(bytes).unwrap());
+/// # }
+/// #
+/// // Uncompresses a gzip, zlib or deflate encoded vector of bytes and
+/// // returns a string or error
+/// // Here &[u8] implements Read
+///
+/// fn decode_reader(bytes: Vec<u8>) -> io::Result<String> {
+///     let mut d = AutoDecoder::new(&bytes[..]);
+///     let mut s = String::new();
+///     d.read_to_string(&mut s)?;
+///     assert_eq!(d.format(), Some(DetectedFormat::Zlib));
+///     Ok(s)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<R> {
+    inner: bufread::AutoDecoder<BufReader<R>>,
+}
+
+impl<R: Read> AutoDecoder<R> {
+    /// Creates a new decoder which will detect the format of the given
+    /// stream and decompress it.
+    pub fn new(r: R) -> AutoDecoder<R> {
+        AutoDecoder::new_with_buf(r, vec![0; 32 * 1024])
+    }
+
+    /// Same as `new`, but the intermediate buffer for data is specified.
+    ///
+    /// Note that the specified buffer will only be used up to its current
+    /// length. The buffer's capacity will also not grow over time.
+    pub fn new_with_buf(r: R, buf: Vec<u8>) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: bufread::AutoDecoder::new(BufReader::with_buf(buf, r)),
+        }
+    }
+}
+
+impl<R> AutoDecoder<R> {
+    /// Configures whether every member of a gzip stream is decoded, as with
+    /// [`MultiGzDecoder`], rather than only the first one.
+    ///
+    /// This has no effect once reading has started.
+    ///
+    /// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+    pub fn multi_member(self, multi_member: bool) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: self.inner.multi_member(multi_member),
+        }
+    }
+
+    /// Configures whether input which is neither gzip nor zlib is returned
+    /// unchanged instead of being decoded as raw deflate.
+    ///
+    /// This has no effect once reading has started.
+    pub fn passthrough(self, passthrough: bool) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: self.inner.passthrough(passthrough),
+        }
+    }
+
+    /// Configures limits on the output of the decoder, see
+    /// [`DecompressLimits`]. They don't apply to input which is passed
+    /// through unchanged.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: crate::DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
+    /// Returns the detected format of the stream, or `None` if nothing has
+    /// been read yet.
+    pub fn format(&self) -> Option<DetectedFormat> {
+        self.inner.format()
+    }
+
+    /// Acquires a reference to the underlying stream
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    ///
+    /// Note that there may be buffered bytes which are not re-acquired as part
+    /// of this transition. It's recommended to only call this function after
+    /// EOF has been reached.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().into_inner()
+    }
+}
+
+impl<R: Read> Read for AutoDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
+
+impl<R: Read + Write> Write for AutoDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
@@ -0,0 +1,308 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use super::{detect, DetectedFormat};
+use crate::write::{DeflateDecoder, GzDecoder, MultiGzDecoder, ZlibDecoder};
+use crate::DecompressLimits;
+
+/// A decoder which detects whether its input is gzip, zlib or raw deflate.
+///
+/// This structure implements a [`Write`] and will emit a stream of
+/// decompressed data when fed a stream of compressed data. The first two
+/// bytes written are held back to pick the decoder for the rest of the
+/// stream. See [`bufread::AutoDecoder`] for how the format is detected.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`bufread::AutoDecoder`]: ../bufread/struct.AutoDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::DeflateEncoder;
+/// use flate2::write::AutoDecoder;
+/// use flate2::DetectedFormat;
+///
+/// # fn main() {
+/// #    let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+/// #    e.write_all(b"Hello World").unwrap();
+/// #    let bytes = e.finish().unwrap();
+/// #    println!("{}", decode_writer(bytes).unwrap());
+/// # }
+/// #
+/// // Uncompresses a gzip, zlib or deflate encoded vector of bytes and
+/// // returns a string or error
+/// // Here Vec<u8> implements Write
+///
+/// fn decode_writer(bytes: Vec<u8>) -> io::Result<String> {
+///    let mut writer = Vec::new();
+///    let mut d = AutoDecoder::new(writer);
+///    d.write_all(&bytes[..])?;
+///    assert_eq!(d.format(), Some(DetectedFormat::Deflate));
+///    writer = d.finish()?;
+///    let return_string = String::from_utf8(writer).expect("String parsing error");
+///    Ok(return_string)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<W: Write> {
+    inner: Inner<W>,
+    header: [u8; 2],
+    header_len: usize,
+    passthrough: bool,
+    multi_member: bool,
+    limits: DecompressLimits,
+}
+
+#[derive(Debug)]
+enum Inner<W: Write> {
+    // Only `None` while switching over to a decoder or once finished.
+    Detecting(Option<W>),
+    Deflate(DeflateDecoder<W>),
+    Zlib(ZlibDecoder<W>),
+    Gzip(GzDecoder<W>),
+    MultiGzip(MultiGzDecoder<W>),
+    Passthrough(W),
+}
+
+impl<W: Write> AutoDecoder<W> {
+    /// Creates a new decoder which will detect the format of the data written
+    /// to it and write the decompressed data to the stream.
+    ///
+    /// When this decoder is dropped or unwrapped the final pieces of data will
+    /// be flushed.
+    pub fn new(w: W) -> AutoDecoder<W> {
+        AutoDecoder {
+            inner: Inner::Detecting(Some(w)),
+            header: [0; 2],
+            header_len: 0,
+            passthrough: false,
+            multi_member: false,
+            limits: DecompressLimits::new(),
+        }
+    }
+
+    /// Configures whether input which is neither gzip nor zlib is written out
+    /// unchanged instead of being decoded as raw deflate.
+    ///
+    /// This has no effect once writing has started.
+    pub fn passthrough(mut self, passthrough: bool) -> AutoDecoder<W> {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Configures whether every member of a gzip stream is decoded, as with
+    /// [`MultiGzDecoder`], rather than only the first one.
+    ///
+    /// This has no effect once writing has started.
+    ///
+    /// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+    pub fn multi_member(mut self, multi_member: bool) -> AutoDecoder<W> {
+        self.multi_member = multi_member;
+        self
+    }
+
+    /// Configures limits on the output of the decoder, see
+    /// [`DecompressLimits`]. They don't apply to input which is passed
+    /// through unchanged.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+        match self.inner {
+            Inner::Detecting(_) | Inner::Passthrough(_) => {}
+            Inner::Deflate(ref mut d) => d.set_limits(limits),
+            Inner::Zlib(ref mut d) => d.set_limits(limits),
+            Inner::Gzip(ref mut d) => d.set_limits(limits),
+            Inner::MultiGzip(ref mut d) => d.set_limits(limits),
+        }
+    }
+
+    /// Returns the detected format of the stream, or `None` if fewer than two
+    /// bytes have been written yet.
+    pub fn format(&self) -> Option<DetectedFormat> {
+        match self.inner {
+            Inner::Detecting(_) => None,
+            Inner::Deflate(_) => Some(DetectedFormat::Deflate),
+            Inner::Zlib(_) => Some(DetectedFormat::Zlib),
+            Inner::Gzip(_) | Inner::MultiGzip(_) => Some(DetectedFormat::Gzip),
+            Inner::Passthrough(_) => Some(DetectedFormat::Passthrough),
+        }
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        match self.inner {
+            Inner::Detecting(ref w) => w.as_ref().unwrap(),
+            Inner::Deflate(ref d) => d.get_ref(),
+            Inner::Zlib(ref d) => d.get_ref(),
+            Inner::Gzip(ref d) => d.get_ref(),
+            Inner::MultiGzip(ref d) => d.get_ref(),
+            Inner::Passthrough(ref w) => w,
+        }
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt this
+    /// object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        match self.inner {
+            Inner::Detecting(ref mut w) => w.as_mut().unwrap(),
+            Inner::Deflate(ref mut d) => d.get_mut(),
+            Inner::Zlib(ref mut d) => d.get_mut(),
+            Inner::Gzip(ref mut d) => d.get_mut(),
+            Inner::MultiGzip(ref mut d) => d.get_mut(),
+            Inner::Passthrough(ref mut w) => w,
+        }
+    }
+
+    /// Switches over to the decoder for the detected format and hands it the
+    /// bytes held back so far.
+    fn detect(&mut self) -> io::Result<()> {
+        let w = match self.inner {
+            Inner::Detecting(ref mut w) => w.take().unwrap(),
+            _ => return Ok(()),
+        };
+        let header = &self.header[..self.header_len];
+        let limits = self.limits;
+        self.inner = match detect(header, self.passthrough) {
+            DetectedFormat::Deflate => {
+                let mut d = DeflateDecoder::new(w);
+                d.set_limits(limits);
+                Inner::Deflate(d)
+            }
+            DetectedFormat::Zlib => {
+                let mut d = ZlibDecoder::new(w);
+                d.set_limits(limits);
+                Inner::Zlib(d)
+            }
+            DetectedFormat::Gzip if self.multi_member => {
+                let mut d = MultiGzDecoder::new(w);
+                d.set_limits(limits);
+                Inner::MultiGzip(d)
+            }
+            DetectedFormat::Gzip => {
+                let mut d = GzDecoder::new(w);
+                d.set_limits(limits);
+                Inner::Gzip(d)
+            }
+            DetectedFormat::Passthrough => Inner::Passthrough(w),
+        };
+        match self.inner {
+            Inner::Detecting(_) => unreachable!(),
+            Inner::Deflate(ref mut d) => d.write_all(header),
+            Inner::Zlib(ref mut d) => d.write_all(header),
+            Inner::Gzip(ref mut d) => d.write_all(header),
+            Inner::MultiGzip(ref mut d) => d.write_all(header),
+            Inner::Passthrough(ref mut w) => w.write_all(header),
+        }
+    }
+
+    /// Attempt to finish this output stream, writing out final chunks of data.
+    ///
+    /// Note that this function can only be used once data has finished being
+    /// written to the output stream. After this function is called then further
+    /// calls to `write` may result in a panic.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to finish the stream, returning any
+    /// errors which happen.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.detect()?;
+        match self.inner {
+            Inner::Detecting(_) => unreachable!(),
+            Inner::Deflate(ref mut d) => d.try_finish(),
+            Inner::Zlib(ref mut d) => d.try_finish(),
+            Inner::Gzip(ref mut d) => d.try_finish(),
+            Inner::MultiGzip(ref mut d) => d.try_finish(),
+            Inner::Passthrough(ref mut w) => w.flush(),
+        }
+    }
+
+    /// Consumes this decoder, flushing the output stream.
+    ///
+    /// This will flush the underlying data stream and then return the contained
+    /// writer if the flush succeeded.
+    ///
+    /// Note that this function may not be suitable to call in a situation where
+    /// the underlying stream is an asynchronous I/O stream. To finish a stream
+    /// the `try_finish` (or `shutdown`) method should be used instead. To
+    /// re-acquire ownership of a stream it is safe to call this method after
+    /// `try_finish` or `shutdown` has returned `Ok`.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        match mem::replace(&mut self.inner, Inner::Detecting(None)) {
+            Inner::Detecting(_) => unreachable!(),
+            Inner::Deflate(d) => d.finish(),
+            Inner::Zlib(d) => d.finish(),
+            Inner::Gzip(d) => d.finish(),
+            Inner::MultiGzip(d) => d.finish(),
+            Inner::Passthrough(w) => Ok(w),
+        }
+    }
+}
+
+impl<W: Write> Write for AutoDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Inner::Detecting(_) = self.inner {
+            let n = cmp::min(buf.len(), self.header.len() - self.header_len);
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&buf[..n]);
+            self.header_len += n;
+            if self.header_len == self.header.len() {
+                self.detect()?;
+            }
+            return Ok(n);
+        }
+        match self.inner {
+            Inner::Detecting(_) => unreachable!(),
+            Inner::Deflate(ref mut d) => d.write(buf),
+            Inner::Zlib(ref mut d) => d.write(buf),
+            Inner::Gzip(ref mut d) => d.write(buf),
+            Inner::MultiGzip(ref mut d) => d.write(buf),
+            Inner::Passthrough(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner {
+            Inner::Detecting(ref mut w) => w.as_mut().unwrap().flush(),
+            Inner::Deflate(ref mut d) => d.flush(),
+            Inner::Zlib(ref mut d) => d.flush(),
+            Inner::Gzip(ref mut d) => d.flush(),
+            Inner::MultiGzip(ref mut d) => d.flush(),
+            Inner::Passthrough(ref mut w) => w.flush(),
+        }
+    }
+}
+
+impl<W: Read + Write> Read for AutoDecoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+impl<W: Write> Drop for AutoDecoder<W> {
+    fn drop(&mut self) {
+        // The decoders finish themselves when dropped, but the bytes held back
+        // for detection still have to reach one of them.
+        if let Inner::Detecting(Some(_)) = self.inner {
+            let _ = self.detect();
+        }
+    }
+}
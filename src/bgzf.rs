@@ -0,0 +1,482 @@
+//! The BGZF format, gzip with random access.
+//!
+//! BGZF files, used throughout bioinformatics for formats such as BAM and
+//! tabix-indexed VCF, are gzip multistreams whose members each hold at most
+//! 64 KiB of data and record their own compressed size in a `BC` subfield of
+//! the header's extra field. Any gzip decoder, such as [`MultiGzDecoder`],
+//! can read them, but knowing where members start also allows seeking.
+//!
+//! Positions in a BGZF file are given as 64-bit virtual offsets: the offset of
+//! a block in the compressed file shifted left by 16 bits, combined with an
+//! offset into the data of that block. [`BgzfWriter`] hands these out as data
+//! is written, and [`BgzfReader`] seeks to them.
+//!
+//! [`MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+//! [`BgzfWriter`]: struct.BgzfWriter.html
+//! [`BgzfReader`]: struct.BgzfReader.html
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::prelude::*;
+//! use std::io::{self, Cursor, SeekFrom};
+//! use flate2::bgzf::{BgzfReader, BgzfWriter};
+//! use flate2::Compression;
+//!
+//! # fn main() { run().unwrap(); }
+//! # fn run() -> io::Result<()> {
+//! let mut w = BgzfWriter::new(Vec::new(), Compression::default());
+//! w.write_all(b"first record\n")?;
+//! let second = w.virtual_offset();
+//! w.write_all(b"second record\n")?;
+//! let bytes = w.finish()?;
+//!
+//! let mut r = BgzfReader::new(Cursor::new(bytes));
+//! r.seek(SeekFrom::Start(second))?;
+//! let mut s = String::new();
+//! r.read_to_string(&mut s)?;
+//! assert_eq!(s, "second record\n");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::bufread::MultiGzDecoder;
+use crate::bufreader::BufReader;
+use crate::{Compress, CompressError, Compression, Crc, FlushCompress, GzBuilder, Status};
+
+/// The most data a block holds, leaving room for the block to be larger when
+/// it doesn't compress, as htslib does.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+/// The largest a block can be, as its size is stored in 16 bits.
+const MAX_BLOCK_SIZE: usize = 0x10000;
+/// The size of the header and trailer of a block.
+const BLOCK_OVERHEAD: usize = 18 + 8;
+
+/// The empty block every BGZF file ends with, which tells readers the file
+/// isn't truncated.
+const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+    0, 0,
+];
+
+/// Returns the size of a block from the extra field of its header, or `None`
+/// if it has no `BC` subfield.
+pub(crate) fn block_size(mut extra: &[u8]) -> Option<usize> {
+    while extra.len() >= 4 {
+        let len = usize::from(extra[2]) | usize::from(extra[3]) << 8;
+        if extra[..2] == *b"BC" && len == 2 && extra.len() >= 6 {
+            return Some((usize::from(extra[4]) | usize::from(extra[5]) << 8) + 1);
+        }
+        extra = &extra[cmp::min(4 + len, extra.len())..];
+    }
+    None
+}
+
+/// Appends `data` compressed as a single block to `out`.
+fn compress_block(
+    compress: &mut Compress,
+    lvl: Compression,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), CompressError> {
+    let mut body = Vec::with_capacity(MAX_BLOCK_SIZE - BLOCK_OVERHEAD);
+    compress.reset();
+    let status = compress.compress_vec(data, &mut body, FlushCompress::Finish)?;
+    if status != Status::StreamEnd {
+        // Data which doesn't compress is kept in a stored block instead.
+        let len = data.len() as u16;
+        body.clear();
+        body.push(1);
+        body.extend_from_slice(&len.to_le_bytes());
+        body.extend_from_slice(&(!len).to_le_bytes());
+        body.extend_from_slice(data);
+    }
+
+    let bsize = ((BLOCK_OVERHEAD + body.len() - 1) as u16).to_le_bytes();
+    let header = GzBuilder::new()
+        .extra(vec![b'B', b'C', 2, 0, bsize[0], bsize[1]])
+        .into_header(lvl);
+    let mut crc = Crc::new();
+    crc.update(data);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc.sum().to_le_bytes());
+    out.extend_from_slice(&crc.amount().to_le_bytes());
+    Ok(())
+}
+
+/// A BGZF encoder, or compressor
+///
+/// This structure implements a [`Write`] interface, compressing the data
+/// written to it into blocks of just under 64 KiB, each written out as a
+/// gzip member once it is full. Finishing the stream adds the empty block
+/// marking the end of a BGZF file.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::bgzf::BgzfWriter;
+/// use flate2::Compression;
+///
+/// let mut w = BgzfWriter::new(Vec::new(), Compression::default());
+/// w.write_all(b"Hello World").unwrap();
+/// let bytes = w.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BgzfWriter<W: Write> {
+    obj: Option<W>,
+    compress: Compress,
+    lvl: Compression,
+    // Data for the next block.
+    data: Vec<u8>,
+    // Compressed blocks which have yet to be written out.
+    buf: Vec<u8>,
+    // The offset the next block starts at.
+    offset: u64,
+    finished: bool,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    /// Creates a new encoder which will write compressed data to the stream
+    /// given at the given compression level.
+    pub fn new(w: W, level: Compression) -> BgzfWriter<W> {
+        BgzfWriter {
+            obj: Some(w),
+            compress: Compress::new(level, false),
+            lvl: level,
+            data: Vec::with_capacity(BLOCK_DATA_SIZE),
+            buf: Vec::new(),
+            offset: 0,
+            finished: false,
+        }
+    }
+
+    /// Returns the virtual offset of the next byte written, for seeking back
+    /// to it with a [`BgzfReader`].
+    ///
+    /// [`BgzfReader`]: struct.BgzfReader.html
+    pub fn virtual_offset(&self) -> u64 {
+        (self.offset << 16) | self.data.len() as u64
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.obj.as_ref().unwrap()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt
+    /// this object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.obj.as_mut().unwrap()
+    }
+
+    /// Attempt to finish this output stream, writing out the last block and
+    /// the end of file marker.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.end_block()?;
+        if !self.finished {
+            self.buf.extend_from_slice(&EOF_MARKER);
+            self.offset += EOF_MARKER.len() as u64;
+            self.finished = true;
+        }
+        self.dump()?;
+        self.obj.as_mut().unwrap().flush()
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.obj.take().unwrap())
+    }
+
+    /// Compresses the data written since the last block into a block.
+    fn end_block(&mut self) -> io::Result<()> {
+        if !self.data.is_empty() {
+            let len = self.buf.len();
+            compress_block(&mut self.compress, self.lvl, &self.data, &mut self.buf)?;
+            self.offset += (self.buf.len() - len) as u64;
+            self.data.clear();
+        }
+        Ok(())
+    }
+
+    fn dump(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let n = self.obj.as_mut().unwrap().write(&self.buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished, "cannot write to a finished BGZF stream");
+        if self.data.len() == BLOCK_DATA_SIZE {
+            self.end_block()?;
+        }
+        self.dump()?;
+        let n = cmp::min(buf.len(), BLOCK_DATA_SIZE - self.data.len());
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Ends the current block, so that the data written so far can be read
+    /// back and the next byte written starts a new block.
+    fn flush(&mut self) -> io::Result<()> {
+        self.end_block()?;
+        self.dump()?;
+        self.obj.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Read + Write> Read for BgzfWriter<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        if self.obj.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A BGZF decoder, or decompressor
+///
+/// This structure implements a [`Read`] interface, decoding all blocks of a
+/// BGZF file like [`MultiGzDecoder`] does, while keeping track of the
+/// virtual offset it is at. When the underlying reader implements [`Seek`],
+/// so does this decoder, seeking to the virtual offsets given by
+/// [`virtual_offset`] or [`BgzfWriter::virtual_offset`]. Seeking to
+/// `SeekFrom::Current(0)` returns the virtual offset, while other relative
+/// seeks aren't supported.
+///
+/// Every gzip member read has to be a BGZF block.
+///
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+/// [`MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+/// [`virtual_offset`]: #method.virtual_offset
+/// [`BgzfWriter::virtual_offset`]: struct.BgzfWriter.html#method.virtual_offset
+#[derive(Debug)]
+pub struct BgzfReader<R> {
+    // Only taken while seeking.
+    inner: Option<MultiGzDecoder<BufReader<R>>>,
+    // The offset of the block data was last read from, and how much of its
+    // data has been read.
+    block: u64,
+    pos: u64,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Creates a new decoder reading from the start of the given reader.
+    pub fn new(r: R) -> BgzfReader<R> {
+        BgzfReader {
+            inner: Some(MultiGzDecoder::starting_at(BufReader::new(r), 0)),
+            block: 0,
+            pos: 0,
+        }
+    }
+}
+
+impl<R> BgzfReader<R> {
+    /// Returns the virtual offset of the next byte read.
+    pub fn virtual_offset(&self) -> u64 {
+        (self.block << 16) | self.pos
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.as_ref().unwrap().get_ref().get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.as_mut().unwrap().get_mut().get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.unwrap().into_inner().into_inner()
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let inner = self.inner.as_mut().unwrap();
+        let n = inner.read(into)?;
+        if n == 0 {
+            return Ok(0);
+        }
+        let start = inner.member_start();
+        if start != self.block || self.pos == 0 {
+            let extra = inner.header().and_then(|header| header.extra());
+            if extra.and_then(block_size).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "gzip member is not a BGZF block",
+                ));
+            }
+            self.block = start;
+            self.pos = 0;
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for BgzfReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(0) => return Ok(self.virtual_offset()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "BGZF streams can only seek to a virtual offset",
+                ))
+            }
+        };
+        let (block, pos) = (offset >> 16, offset & 0xffff);
+        self.get_mut().seek(SeekFrom::Start(block))?;
+        let r = self.inner.take().unwrap().into_inner().into_inner();
+        self.inner = Some(MultiGzDecoder::starting_at(BufReader::new(r), block));
+        self.block = block;
+        self.pos = 0;
+
+        io::copy(&mut self.by_ref().take(pos), &mut io::sink())?;
+        if self.virtual_offset() != offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "virtual offset is past the end of its block",
+            ));
+        }
+        Ok(offset)
+    }
+}
+
+impl<R: Read + Write> Write for BgzfReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use std::io::{Cursor, SeekFrom};
+
+    use super::{BgzfReader, BgzfWriter, EOF_MARKER};
+    use crate::read::MultiGzDecoder;
+    use crate::{Compression, GzBuilder};
+
+    fn records() -> Vec<Vec<u8>> {
+        (0..5000)
+            .map(|i| format!("record {} {}\n", i, "x".repeat(i % 97)).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip_and_seek() {
+        let records = records();
+        let mut w = BgzfWriter::new(Vec::new(), Compression::default());
+        let mut offsets = Vec::new();
+        for record in &records {
+            offsets.push(w.virtual_offset());
+            w.write_all(record).unwrap();
+        }
+        let bytes = w.finish().unwrap();
+        assert!(bytes.ends_with(&EOF_MARKER));
+        assert!(offsets.last().unwrap() >> 16 > 0);
+
+        let mut out = Vec::new();
+        MultiGzDecoder::new(&bytes[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out == records.concat());
+
+        let mut r = BgzfReader::new(Cursor::new(bytes));
+        for (record, &offset) in records.iter().zip(&offsets).take(1000) {
+            assert_eq!(r.seek(SeekFrom::Current(0)).unwrap(), offset);
+            let mut buf = vec![0; record.len()];
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, record);
+        }
+        for &i in [4999, 3, 2500, 0, 1234].iter() {
+            r.seek(SeekFrom::Start(offsets[i])).unwrap();
+            let mut buf = vec![0; records[i].len()];
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, records[i]);
+        }
+        assert!(r.seek(SeekFrom::End(0)).is_err());
+        assert!(r.seek(SeekFrom::Start(0xffff)).is_err());
+    }
+
+    #[test]
+    fn incompressible_and_flush() {
+        let data = crate::random_bytes().take(200_000).collect::<Vec<_>>();
+        let mut w = BgzfWriter::new(Vec::new(), Compression::best());
+        w.write_all(b"start").unwrap();
+        w.flush().unwrap();
+        let offset = w.virtual_offset();
+        assert_eq!(offset & 0xffff, 0);
+        assert_eq!(offset >> 16, w.get_ref().len() as u64);
+        w.write_all(&data).unwrap();
+        let bytes = w.finish().unwrap();
+
+        let mut r = BgzfReader::new(Cursor::new(&bytes[..]));
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        assert!(out == [&b"start"[..], &data].concat());
+        r.seek(SeekFrom::Start(offset)).unwrap();
+        out.clear();
+        r.read_to_end(&mut out).unwrap();
+        assert!(out == data);
+    }
+
+    #[test]
+    fn not_bgzf() {
+        let mut e = GzBuilder::new()
+            .extra(&b"XY\x02\x00ab"[..])
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"hello").unwrap();
+        let bytes = e.finish().unwrap();
+        let mut r = BgzfReader::new(&bytes[..]);
+        let mut out = Vec::new();
+        assert!(r.read_to_end(&mut out).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::io::prelude::*;
 use std::mem;
 
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{Compress, Decompress, DecompressLimits};
 
 /// A DEFLATE encoder, or compressor.
 ///
@@ -51,6 +51,33 @@ impl<R: BufRead> DeflateEncoder<R> {
             data: Compress::new(level, false),
         }
     }
+
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: crate::Compression) -> io::Result<DeflateEncoder<R>> {
+        Ok(DeflateEncoder {
+            obj: r,
+            data: Compress::try_new(level, false)?,
+        })
+    }
+
+    /// Creates a new encoder which will read uncompressed data from the given
+    /// stream and emit the compressed stream using the given `Compress`.
+    ///
+    /// This allows the compression strategy and other parameters of the
+    /// underlying [`Compress`] to be configured. The `Compress` must have been
+    /// created for a raw deflate stream, with `zlib_header` set to `false`.
+    ///
+    /// [`Compress`]: ../struct.Compress.html
+    pub fn new_with_compress(r: R, compression: Compress) -> DeflateEncoder<R> {
+        DeflateEncoder {
+            obj: r,
+            data: compression,
+        }
+    }
 }
 
 pub fn reset_encoder_data<R>(zlib: &mut DeflateEncoder<R>) {
@@ -156,6 +183,8 @@ impl<W: BufRead + Write> Write for DeflateEncoder<W> {
 pub struct DeflateDecoder<R> {
     obj: R,
     data: Decompress,
+    recovering: bool,
+    limits: DecompressLimits,
 }
 
 pub fn reset_decoder_data<R>(zlib: &mut DeflateDecoder<R>) {
@@ -166,9 +195,23 @@ impl<R: BufRead> DeflateDecoder<R> {
     /// Creates a new decoder which will decompress data read from the given
     /// stream.
     pub fn new(r: R) -> DeflateDecoder<R> {
+        DeflateDecoder::new_with_decompress(r, Decompress::new(false))
+    }
+
+    /// Creates a new decoder which will decompress data read from the given
+    /// stream using the given `Decompress`.
+    ///
+    /// The `Decompress` must have been created for a raw deflate stream, for
+    /// example with [`Decompress::try_new`] to handle setup failures without
+    /// panicking.
+    ///
+    /// [`Decompress::try_new`]: ../struct.Decompress.html#method.try_new
+    pub fn new_with_decompress(r: R, decompression: Decompress) -> DeflateDecoder<R> {
         DeflateDecoder {
             obj: r,
-            data: Decompress::new(false),
+            data: decompression,
+            recovering: false,
+            limits: DecompressLimits::new(),
         }
     }
 }
@@ -194,6 +237,34 @@ impl<R> DeflateDecoder<R> {
         reset_decoder_data(self);
     }
 
+    /// Returns whether the end of the deflate stream has been reached.
+    pub(crate) fn is_finished(&mut self) -> bool {
+        self.data.is_finished()
+    }
+
+    /// Configures whether corrupt data is skipped rather than reported as an
+    /// error.
+    ///
+    /// When enabled, a damaged region of the stream is skipped up to the next
+    /// point where it was flushed with [`FlushCompress::Full`], and decoding
+    /// resumes from there as described for [`Decompress::sync`]. The data in
+    /// the skipped region is lost. An error is still returned if no such point
+    /// follows the damage.
+    ///
+    /// [`FlushCompress::Full`]: ../enum.FlushCompress.html#variant.Full
+    /// [`Decompress::sync`]: ../struct.Decompress.html#method.sync
+    pub fn set_recovering(&mut self, recovering: bool) {
+        self.recovering = recovering;
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+
     /// Acquires a reference to the underlying stream
     pub fn get_ref(&self) -> &R {
         &self.obj
@@ -228,7 +299,13 @@ impl<R> DeflateDecoder<R> {
 
 impl<R: BufRead> Read for DeflateDecoder<R> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
-        zio::read(&mut self.obj, &mut self.data, into)
+        zio::read_limited(
+            &mut self.obj,
+            &mut self.data,
+            into,
+            &self.limits,
+            self.recovering,
+        )
     }
 }
 
@@ -9,7 +9,7 @@ mod tests {
     use rand::{thread_rng, Rng};
 
     use super::{read, write};
-    use crate::Compression;
+    use crate::{Compression, FlushCompress};
 
     #[test]
     fn roundtrip() {
@@ -190,4 +190,152 @@ mod tests {
             v == w.finish().unwrap().finish().unwrap()
         }
     }
+
+    #[test]
+    fn recovering() {
+        // Three parts separated by full flush points, with the middle one
+        // destroyed.
+        let mut c = crate::Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        let mut ends = Vec::new();
+        let parts = [
+            (&b"first part "[..], FlushCompress::Full),
+            (&b"second part "[..], FlushCompress::Full),
+            (&b"third part"[..], FlushCompress::Finish),
+        ];
+        for &(part, flush) in parts.iter() {
+            c.compress_vec(part, &mut encoded, flush).unwrap();
+            ends.push(encoded.len());
+        }
+        for b in &mut encoded[ends[0]..ends[1] - 4] {
+            *b = 0xff;
+        }
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_recovering(true);
+        let mut decoded = Vec::new();
+        r.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"first part third part");
+
+        // Without a later flush point the damage is still an error.
+        let mut r = read::DeflateDecoder::new(&encoded[..ends[1] - 1]);
+        r.set_recovering(true);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        use crate::{DecompressError, DecompressErrorKind};
+        use std::io;
+
+        let mut w = write::DeflateEncoder::new(Vec::new(), Compression::default());
+        w.write_all(b"hello world, hello world").unwrap();
+        let encoded = w.finish().unwrap();
+        let truncated = &encoded[..encoded.len() - 2];
+
+        // A raw deflate stream which ends early just ends the output.
+        let mut decoded = Vec::new();
+        read::DeflateDecoder::new(truncated)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert!(b"hello world, hello world".starts_with(&decoded));
+        let mut w = write::DeflateDecoder::new(Vec::new());
+        w.write_all(truncated).unwrap();
+        w.finish().unwrap();
+
+        // Unless it ends while corrupt data is skipped.
+        let mut corrupt = encoded.clone();
+        corrupt[0] |= 6;
+        let mut r = read::DeflateDecoder::new(&corrupt[..]);
+        r.set_recovering(true);
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+        assert_eq!(err.unwrap().kind(), DecompressErrorKind::Truncated);
+        assert_eq!(err.unwrap().offset(), corrupt.len() as u64);
+    }
+
+    #[test]
+    fn try_new() {
+        use crate::Decompress;
+        use std::io;
+
+        let level = Compression::new(42);
+        let err = write::DeflateEncoder::try_new(Vec::new(), level).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(read::DeflateEncoder::try_new(&b""[..], level).is_err());
+        assert!(super::bufread::DeflateEncoder::try_new(&b""[..], level).is_err());
+
+        let mut w = write::DeflateEncoder::try_new(Vec::new(), Compression::fast()).unwrap();
+        w.write_all(b"hello world").unwrap();
+        let encoded = w.finish().unwrap();
+
+        let mut data = Vec::new();
+        read::DeflateDecoder::new_with_decompress(
+            &encoded[..],
+            Decompress::try_new(false).unwrap(),
+        )
+        .read_to_end(&mut data)
+        .unwrap();
+        assert_eq!(data, b"hello world");
+    }
+
+    #[test]
+    fn limits() {
+        use crate::{DecompressError, DecompressLimit, DecompressLimits};
+        use std::io;
+
+        fn limit(err: io::Error) -> Option<DecompressLimit> {
+            let err = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+            err.unwrap().limit()
+        }
+
+        let data = vec![0; 100_000];
+        let mut w = write::DeflateEncoder::new(Vec::new(), Compression::default());
+        w.write_all(&data).unwrap();
+        let encoded = w.finish().unwrap();
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_limits(DecompressLimits::new().max_output(data.len() as u64));
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_limits(DecompressLimits::new().max_output(1000));
+        let mut out = Vec::new();
+        let err = r.read_to_end(&mut out).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+        assert!(out.len() <= 1000);
+
+        // A read which crosses the limit returns what's within it first.
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_limits(DecompressLimits::new().max_output(1000));
+        let mut buf = [1; 600];
+        assert_eq!(r.read(&mut buf).unwrap(), 600);
+        assert_eq!(r.read(&mut buf).unwrap(), 400);
+        assert_eq!(buf[..400], data[..400]);
+        let err = r.read(&mut buf).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_limits(DecompressLimits::new().max_output(1000));
+        let mut out = Vec::new();
+        r.by_ref().take(2000).read_to_end(&mut out).unwrap_err();
+        assert_eq!(out, data[..1000]);
+
+        let mut r = read::DeflateDecoder::new(&encoded[..]);
+        r.set_limits(DecompressLimits::new().max_ratio(10));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Ratio));
+
+        let mut w = write::DeflateDecoder::new(Vec::new());
+        w.set_limits(DecompressLimits::new().max_output(1000));
+        let err = w.write_all(&encoded).and(w.try_finish()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+        assert!(w.get_ref().len() <= 1000);
+    }
 }
@@ -45,6 +45,31 @@ impl<R: Read> DeflateEncoder<R> {
             inner: bufread::DeflateEncoder::new(BufReader::new(r), level),
         }
     }
+
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: crate::Compression) -> io::Result<DeflateEncoder<R>> {
+        Ok(DeflateEncoder {
+            inner: bufread::DeflateEncoder::try_new(BufReader::new(r), level)?,
+        })
+    }
+
+    /// Creates a new encoder which will read uncompressed data from the given
+    /// stream and emit the compressed stream using the given `Compress`.
+    ///
+    /// This allows the compression strategy and other parameters of the
+    /// underlying [`Compress`] to be configured. The `Compress` must have been
+    /// created for a raw deflate stream, with `zlib_header` set to `false`.
+    ///
+    /// [`Compress`]: ../struct.Compress.html
+    pub fn new_with_compress(r: R, compression: crate::Compress) -> DeflateEncoder<R> {
+        DeflateEncoder {
+            inner: bufread::DeflateEncoder::new_with_compress(BufReader::new(r), compression),
+        }
+    }
 }
 
 impl<R> DeflateEncoder<R> {
@@ -170,6 +195,23 @@ impl<R: Read> DeflateDecoder<R> {
             inner: bufread::DeflateDecoder::new(BufReader::with_buf(buf, r)),
         }
     }
+
+    /// Creates a new decoder which will decompress data read from the given
+    /// stream using the given `Decompress`.
+    ///
+    /// The `Decompress` must have been created for a raw deflate stream, for
+    /// example with [`Decompress::try_new`] to handle setup failures without
+    /// panicking.
+    ///
+    /// [`Decompress::try_new`]: ../struct.Decompress.html#method.try_new
+    pub fn new_with_decompress(r: R, decompression: crate::Decompress) -> DeflateDecoder<R> {
+        DeflateDecoder {
+            inner: bufread::DeflateDecoder::new_with_decompress(
+                BufReader::with_buf(vec![0; 32 * 1024], r),
+                decompression,
+            ),
+        }
+    }
 }
 
 impl<R> DeflateDecoder<R> {
@@ -188,6 +230,29 @@ impl<R> DeflateDecoder<R> {
         self.inner.get_mut().reset(r)
     }
 
+    /// Configures whether corrupt data is skipped rather than reported as an
+    /// error.
+    ///
+    /// When enabled, a damaged region of the stream is skipped up to the next
+    /// point where it was flushed with [`FlushCompress::Full`], and decoding
+    /// resumes from there as described for [`Decompress::sync`]. The data in
+    /// the skipped region is lost. An error is still returned if no such point
+    /// follows the damage.
+    ///
+    /// [`FlushCompress::Full`]: ../enum.FlushCompress.html#variant.Full
+    /// [`Decompress::sync`]: ../struct.Decompress.html#method.sync
+    pub fn set_recovering(&mut self, recovering: bool) {
+        self.inner.set_recovering(recovering);
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: crate::DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
     /// Acquires a reference to the underlying stream
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
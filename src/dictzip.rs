@@ -0,0 +1,639 @@
+//! The dictzip format, gzip with random access.
+//!
+//! Dictzip files, made by the `dictzip` tool for the dictionaries served by
+//! `dictd`, are single gzip members whose data is compressed in chunks of a
+//! fixed size, with a full flush after each chunk so that every chunk can be
+//! decompressed on its own. An `RA` subfield of the header's extra field lists
+//! the compressed size of each chunk. Any gzip decoder, such as
+//! [`GzDecoder`], can read them, while [`DictzipReader`] uses the chunk sizes
+//! to seek.
+//!
+//! [`GzDecoder`]: ../read/struct.GzDecoder.html
+//! [`DictzipReader`]: struct.DictzipReader.html
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::prelude::*;
+//! use std::io::{self, Cursor, SeekFrom};
+//! use flate2::dictzip::{DictzipReader, DictzipWriter};
+//! use flate2::Compression;
+//!
+//! # fn main() { run().unwrap(); }
+//! # fn run() -> io::Result<()> {
+//! let data = b"Hello World";
+//! let mut w = DictzipWriter::new(Cursor::new(Vec::new()), 11, Compression::default());
+//! w.write_all(data)?;
+//! let bytes = w.finish()?.into_inner();
+//!
+//! let mut r = DictzipReader::new(Cursor::new(bytes))?;
+//! r.seek(SeekFrom::Start(6))?;
+//! let mut s = String::new();
+//! r.read_to_string(&mut s)?;
+//! assert_eq!(s, "World");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::gz::bufread::read_gz_header;
+use crate::{
+    Compress, Compression, Crc, Decompress, FlushCompress, FlushDecompress, GzBuilder, GzHeader,
+    GzHeaderLimits, Status,
+};
+
+/// The size of the chunks written, which `dictzip` picks so that a chunk
+/// which doesn't compress still fits in 64 KiB.
+const CHUNK_SIZE: usize = 58315;
+/// The most chunks the sizes of which fit in the extra field.
+const MAX_CHUNKS: u64 = (0xffff - 10) / 2;
+/// The offset of the chunk sizes in the header, after the fixed part of the
+/// header, the length of the extra field and the first 10 bytes of the `RA`
+/// subfield.
+const SIZES_OFFSET: u64 = 10 + 2 + 10;
+
+fn not_dictzip() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "gzip stream is not a dictzip file",
+    )
+}
+
+/// Returns the chunk size and the sizes of the compressed chunks from the
+/// extra field of a header, or `None` if it has no valid `RA` subfield.
+fn chunk_sizes(mut extra: &[u8]) -> Option<(u64, Vec<u64>)> {
+    fn u16_at(bytes: &[u8], i: usize) -> u16 {
+        u16::from_le_bytes([bytes[i], bytes[i + 1]])
+    }
+
+    while extra.len() >= 4 {
+        let len = usize::from(u16_at(extra, 2));
+        let field = &extra[4..cmp::min(4 + len, extra.len())];
+        if extra[..2] == *b"RA" && field.len() == len && len >= 6 {
+            let count = usize::from(u16_at(field, 4));
+            if u16_at(field, 0) != 1 || u16_at(field, 2) == 0 || len != 6 + 2 * count {
+                return None;
+            }
+            let sizes = (0..count)
+                .map(|i| u64::from(u16_at(field, 6 + 2 * i)))
+                .collect();
+            return Some((u64::from(u16_at(field, 2)), sizes));
+        }
+        extra = &extra[4 + field.len()..];
+    }
+    None
+}
+
+/// A dictzip encoder, or compressor
+///
+/// This structure implements a [`Write`] interface, compressing the data
+/// written to it in chunks the way `dictzip` does. As the header lists the
+/// sizes of all chunks ahead of them, the length of the data has to be given
+/// up front, and the header is filled in by seeking back to it once the
+/// stream is finished.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io::Cursor;
+/// use flate2::dictzip::DictzipWriter;
+/// use flate2::Compression;
+///
+/// let mut w = DictzipWriter::new(Cursor::new(Vec::new()), 11, Compression::default());
+/// w.write_all(b"Hello World").unwrap();
+/// let bytes = w.finish().unwrap().into_inner();
+/// ```
+#[derive(Debug)]
+pub struct DictzipWriter<W: Write + Seek> {
+    obj: Option<W>,
+    // Taken once the header has been written.
+    builder: Option<GzBuilder>,
+    compress: Compress,
+    lvl: Compression,
+    len: u64,
+    crc: Crc,
+    // Data for the next chunk.
+    data: Vec<u8>,
+    // Compressed data which has yet to be written out.
+    buf: Vec<u8>,
+    // The offset the header was written at, and the sizes of the chunks
+    // compressed so far.
+    start: u64,
+    sizes: Vec<u16>,
+    finished: bool,
+}
+
+impl<W: Write + Seek> DictzipWriter<W> {
+    /// Creates a new encoder which will write `len` bytes of data compressed
+    /// at the given level to the stream given, starting at its current
+    /// position.
+    pub fn new(w: W, len: u64, level: Compression) -> DictzipWriter<W> {
+        DictzipWriter::with_builder(GzBuilder::new(), w, len, level)
+    }
+
+    /// Creates a new encoder like [`new`], with the rest of the header taken
+    /// from `builder`.
+    ///
+    /// The extra field of the header is replaced by the one listing the
+    /// chunks.
+    ///
+    /// [`new`]: #method.new
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor can't be set up with the builder's strategy.
+    pub fn with_builder(
+        builder: GzBuilder,
+        w: W,
+        len: u64,
+        level: Compression,
+    ) -> DictzipWriter<W> {
+        DictzipWriter {
+            obj: Some(w),
+            compress: builder.compress(level).unwrap(),
+            builder: Some(builder),
+            lvl: level,
+            len,
+            crc: Crc::new(),
+            data: Vec::with_capacity(CHUNK_SIZE),
+            buf: Vec::new(),
+            start: 0,
+            sizes: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.obj.as_ref().unwrap()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt
+    /// this object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.obj.as_mut().unwrap()
+    }
+
+    /// Attempt to finish this output stream, writing out the last chunk and
+    /// the trailer, and filling in the sizes of the chunks in the header.
+    ///
+    /// # Errors
+    ///
+    /// Fails if less data was written than the length given, and otherwise
+    /// performs I/O to complete this stream, any errors of which are returned
+    /// from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_header()?;
+        if self.total() < self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "less data written to dictzip stream than its length",
+            ));
+        }
+        if !self.data.is_empty() {
+            self.end_chunk()?;
+        }
+        while self.compress(&[], FlushCompress::Finish)? != Status::StreamEnd {}
+        self.buf.extend_from_slice(&self.crc.sum().to_le_bytes());
+        self.buf.extend_from_slice(&self.crc.amount().to_le_bytes());
+        self.dump()?;
+
+        let mut sizes = Vec::with_capacity(2 * self.sizes.len());
+        for size in &self.sizes {
+            sizes.extend_from_slice(&size.to_le_bytes());
+        }
+        let start = self.start;
+        let w = self.obj.as_mut().unwrap();
+        let end = w.stream_position()?;
+        w.seek(SeekFrom::Start(start + SIZES_OFFSET))?;
+        w.write_all(&sizes)?;
+        w.seek(SeekFrom::Start(end))?;
+        self.finished = true;
+        w.flush()
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// See [`try_finish`].
+    ///
+    /// [`try_finish`]: #method.try_finish
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.obj.take().unwrap())
+    }
+
+    /// Returns how much data has been written.
+    fn total(&self) -> u64 {
+        self.sizes.len() as u64 * CHUNK_SIZE as u64 + self.data.len() as u64
+    }
+
+    /// Writes the header, with room for the sizes of all chunks, unless it
+    /// has been already.
+    fn write_header(&mut self) -> io::Result<()> {
+        let builder = match self.builder.take() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+        let count = match self.len {
+            0 => 0,
+            len => (len - 1) / CHUNK_SIZE as u64 + 1,
+        };
+        if count > MAX_CHUNKS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too much data for a dictzip file",
+            ));
+        }
+        let mut extra = b"RA".to_vec();
+        extra.extend_from_slice(&(6 + 2 * count as u16).to_le_bytes());
+        extra.extend_from_slice(&1u16.to_le_bytes());
+        extra.extend_from_slice(&(CHUNK_SIZE as u16).to_le_bytes());
+        extra.extend_from_slice(&(count as u16).to_le_bytes());
+        extra.resize(extra.len() + 2 * count as usize, 0);
+
+        self.start = self.obj.as_mut().unwrap().stream_position()?;
+        self.buf
+            .extend_from_slice(&builder.extra(extra).into_header(self.lvl));
+        Ok(())
+    }
+
+    /// Compresses the data written since the last chunk into a chunk.
+    fn end_chunk(&mut self) -> io::Result<()> {
+        let start = self.buf.len();
+        let data = std::mem::take(&mut self.data);
+        let mut input = &data[..];
+        loop {
+            let before = self.compress.total_in();
+            self.compress(input, FlushCompress::Full)?;
+            input = &input[(self.compress.total_in() - before) as usize..];
+            // The flush is done once there is output space left over.
+            if input.is_empty() && self.buf.len() < self.buf.capacity() {
+                break;
+            }
+        }
+        let size = u16::try_from(self.buf.len() - start)
+            .map_err(|_| io::Error::other("compressed dictzip chunk too large"))?;
+        self.sizes.push(size);
+        self.crc.update(&data);
+        self.data = data;
+        self.data.clear();
+        Ok(())
+    }
+
+    fn compress(&mut self, input: &[u8], flush: FlushCompress) -> io::Result<Status> {
+        self.buf.reserve(CHUNK_SIZE);
+        Ok(self.compress.compress_vec(input, &mut self.buf, flush)?)
+    }
+
+    fn dump(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let n = self.obj.as_mut().unwrap().write(&self.buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for DictzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished, "cannot write to a finished dictzip stream");
+        self.write_header()?;
+        if self.data.len() == CHUNK_SIZE {
+            self.end_chunk()?;
+        }
+        self.dump()?;
+        if self.total() == self.len && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more data written to dictzip stream than its length",
+            ));
+        }
+        let left = cmp::min(self.len - self.total(), CHUNK_SIZE as u64) as usize;
+        let n = cmp::min(buf.len(), cmp::min(left, CHUNK_SIZE - self.data.len()));
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dump()?;
+        self.obj.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Read + Write + Seek> Read for DictzipWriter<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+impl<W: Write + Seek> Drop for DictzipWriter<W> {
+    fn drop(&mut self) {
+        if self.obj.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A dictzip decoder, or decompressor
+///
+/// This structure implements a [`Read`] and [`Seek`] interface over the
+/// decompressed data of a dictzip file, only decompressing the chunks data
+/// is read from. The checksum in the trailer isn't verified, as the data is
+/// hardly ever read in full.
+///
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use std::io::{self, SeekFrom};
+/// use flate2::dictzip::DictzipReader;
+///
+/// # fn run() -> io::Result<()> {
+/// let mut r = DictzipReader::new(File::open("words.dict.dz")?)?;
+/// r.seek(SeekFrom::Start(1_000_000))?;
+/// let mut entry = vec![0; 200];
+/// r.read_exact(&mut entry)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DictzipReader<R> {
+    inner: R,
+    header: GzHeader,
+    chunk_size: u64,
+    // Where each chunk starts in the compressed stream, followed by where
+    // the last one ends.
+    offsets: Vec<u64>,
+    size: u64,
+    decompress: Decompress,
+    input: Vec<u8>,
+    // The index of the chunk held, and its data.
+    chunk: Option<usize>,
+    data: Vec<u8>,
+    offset: u64,
+}
+
+impl<R: Read + Seek> DictzipReader<R> {
+    /// Creates a new decoder for the dictzip file starting at the current
+    /// position of `r`, reading its header and the length of its data.
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading fails, or if the file isn't a valid dictzip file.
+    pub fn new(mut r: R) -> io::Result<DictzipReader<R>> {
+        let start = r.stream_position()?;
+        let header = read_gz_header(&mut r, 0, start, GzHeaderLimits::new())?;
+        let (chunk_size, sizes) = header
+            .extra()
+            .and_then(chunk_sizes)
+            .ok_or_else(not_dictzip)?;
+        let mut offsets = vec![r.stream_position()?];
+        for size in sizes {
+            let last = offsets[offsets.len() - 1];
+            offsets.push(last + size);
+        }
+
+        let mut isize = [0; 4];
+        r.seek(SeekFrom::End(-4))?;
+        r.read_exact(&mut isize)?;
+        let size = u64::from(u32::from_le_bytes(isize));
+        let count = offsets.len() as u64 - 1;
+        if size > count * chunk_size || (count > 0 && size <= (count - 1) * chunk_size) {
+            return Err(not_dictzip());
+        }
+
+        Ok(DictzipReader {
+            inner: r,
+            header,
+            chunk_size,
+            offsets,
+            size,
+            decompress: Decompress::new(false),
+            input: Vec::new(),
+            chunk: None,
+            data: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    /// Decompresses the chunk with the given index.
+    fn load(&mut self, index: usize) -> io::Result<()> {
+        self.chunk = None;
+        let (start, end) = (self.offsets[index], self.offsets[index + 1]);
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.input.resize((end - start) as usize, 0);
+        self.inner.read_exact(&mut self.input)?;
+
+        let chunk_start = index as u64 * self.chunk_size;
+        let len = cmp::min(self.chunk_size, self.size - chunk_start) as usize;
+        self.data.clear();
+        self.data.reserve(len);
+        self.decompress.reset(false);
+        self.decompress
+            .decompress_vec(&self.input, &mut self.data, FlushDecompress::Sync)?;
+        if self.data.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "dictzip chunk doesn't hold the data it should",
+            ));
+        }
+        self.chunk = Some(index);
+        Ok(())
+    }
+}
+
+impl<R> DictzipReader<R> {
+    /// Returns the header of the dictzip file.
+    pub fn header(&self) -> &GzHeader {
+        &self.header
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for DictzipReader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.size || into.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.offset / self.chunk_size) as usize;
+        if self.chunk != Some(index) {
+            self.load(index)?;
+        }
+        let start = (self.offset - index as u64 * self.chunk_size) as usize;
+        let n = cmp::min(into.len(), self.data.len() - start);
+        into[..n].copy_from_slice(&self.data[start..start + n]);
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for DictzipReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.offset, delta),
+            SeekFrom::End(delta) => (self.size, delta),
+        };
+        let offset = if delta < 0 {
+            base.checked_sub(delta.wrapping_neg() as u64)
+        } else {
+            base.checked_add(delta as u64)
+        };
+        self.offset = offset.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.offset)
+    }
+}
+
+impl<R: Read + Write> Write for DictzipReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use std::io::{Cursor, SeekFrom};
+
+    use super::{DictzipReader, DictzipWriter, CHUNK_SIZE};
+    use crate::read::GzDecoder;
+    use crate::write::GzEncoder;
+    use crate::{Compression, GzBuilder};
+
+    fn sample(len: usize) -> Vec<u8> {
+        crate::random_bytes()
+            .take(len)
+            .map(|b| b % 16 + b'a')
+            .collect()
+    }
+
+    fn dictzip(data: &[u8]) -> Vec<u8> {
+        let builder = GzBuilder::new().filename("sample.dict");
+        let len = data.len() as u64;
+        let mut w = DictzipWriter::with_builder(
+            builder,
+            Cursor::new(Vec::new()),
+            len,
+            Compression::default(),
+        );
+        w.write_all(data).unwrap();
+        w.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn roundtrip_and_seek() {
+        let data = sample(5 * CHUNK_SIZE + 1000);
+        let bytes = dictzip(&data);
+
+        let mut out = Vec::new();
+        GzDecoder::new(&bytes[..]).read_to_end(&mut out).unwrap();
+        assert!(out == data);
+
+        let mut r = DictzipReader::new(Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(r.header().filename(), Some(&b"sample.dict"[..]));
+        for &offset in [0, 3 * CHUNK_SIZE - 10, 1, 5 * CHUNK_SIZE + 999, 400_000].iter() {
+            assert_eq!(
+                r.seek(SeekFrom::Start(offset as u64)).unwrap(),
+                offset as u64
+            );
+            let mut buf = vec![0; 100];
+            let n = r.read(&mut buf).unwrap();
+            let expected = data.get(offset..).unwrap_or(&[]);
+            assert_eq!(&buf[..n], &expected[..n]);
+            assert!(n > 0 || expected.is_empty());
+        }
+        r.seek(SeekFrom::End(-(CHUNK_SIZE as i64) - 5)).unwrap();
+        out.clear();
+        r.read_to_end(&mut out).unwrap();
+        assert!(out == &data[data.len() - CHUNK_SIZE - 5..]);
+        assert!(r.seek(SeekFrom::Current(-1_000_000)).is_err());
+    }
+
+    #[test]
+    fn empty_and_exact_chunks() {
+        for &len in [0, CHUNK_SIZE, 2 * CHUNK_SIZE].iter() {
+            let data = sample(len);
+            let bytes = dictzip(&data);
+            let mut out = Vec::new();
+            GzDecoder::new(&bytes[..]).read_to_end(&mut out).unwrap();
+            assert!(out == data);
+            out.clear();
+            let mut r = DictzipReader::new(Cursor::new(&bytes[..])).unwrap();
+            r.read_to_end(&mut out).unwrap();
+            assert!(out == data);
+        }
+    }
+
+    #[test]
+    fn wrong_length() {
+        let mut w = DictzipWriter::new(Cursor::new(Vec::new()), 10, Compression::default());
+        assert!(w.write_all(b"01234567890").is_err());
+        let mut w = DictzipWriter::new(Cursor::new(Vec::new()), 10, Compression::default());
+        w.write_all(b"012345678").unwrap();
+        assert!(w.try_finish().is_err());
+    }
+
+    #[test]
+    fn not_dictzip() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"foo").unwrap();
+        let bytes = e.finish().unwrap();
+        assert!(DictzipReader::new(Cursor::new(&bytes[..])).is_err());
+
+        let data = sample(3 * CHUNK_SIZE);
+        let mut bytes = dictzip(&data);
+        // Claim the last chunk is full while it's in fact empty.
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&(4 * CHUNK_SIZE as u32).to_le_bytes());
+        assert!(DictzipReader::new(Cursor::new(&bytes[..])).is_err());
+    }
+}
@@ -9,15 +9,51 @@ use std::os::raw::{c_int, c_uint, c_void};
 use std::ptr;
 
 use super::*;
-use crate::mem::{self, FlushDecompress, Status};
+use crate::mem::{self, CompressOption, DecompressErrorKind, FlushDecompress, Format, Status};
 
 #[derive(Default)]
-pub struct ErrorMessage(Option<&'static str>);
+pub struct ErrorMessage(pub(crate) Option<&'static str>);
 
 impl ErrorMessage {
     pub fn get(&self) -> Option<&str> {
         self.0
     }
+
+    /// Describes a return code for which zlib doesn't set a message.
+    fn from_code(code: c_int) -> ErrorMessage {
+        ErrorMessage(Some(match code {
+            MZ_MEM_ERROR => "insufficient memory",
+            MZ_VERSION_ERROR => "incompatible zlib version",
+            MZ_STREAM_ERROR => "invalid stream state or parameters",
+            _ => "unknown return code",
+        }))
+    }
+
+    /// Tells what is wrong with corrupt data from zlib's message, which is
+    /// the only place it says so.
+    fn data_error_kind(&self) -> DecompressErrorKind {
+        match self.0 {
+            Some("incorrect header check")
+            | Some("unknown compression method")
+            | Some("invalid window size")
+            | Some("unknown header flags set")
+            | Some("header crc mismatch") => DecompressErrorKind::InvalidHeader,
+            Some("invalid block type") => DecompressErrorKind::InvalidBlockType,
+            Some("too many length or distance symbols")
+            | Some("invalid code lengths set")
+            | Some("invalid bit length repeat")
+            | Some("invalid code -- missing end-of-block")
+            | Some("invalid literal/lengths set")
+            | Some("invalid distances set") => DecompressErrorKind::InvalidHuffmanTable,
+            Some("invalid distance code") | Some("invalid distance too far back") => {
+                DecompressErrorKind::InvalidDistance
+            }
+            Some("incorrect data check") | Some("incorrect length check") => {
+                DecompressErrorKind::ChecksumMismatch
+            }
+            _ => DecompressErrorKind::InvalidData,
+        }
+    }
 }
 
 pub struct StreamWrapper {
@@ -128,9 +164,10 @@ unsafe impl<D: Direction> Send for Stream<D> {}
 unsafe impl<D: Direction> Sync for Stream<D> {}
 
 /// Trait used to call the right destroy/end function on the inner
-/// stream object on drop.
+/// stream object on drop, and the right copy function when cloning it.
 pub trait Direction {
     unsafe fn destroy(stream: *mut mz_stream) -> c_int;
+    unsafe fn copy(dest: *mut mz_stream, source: *mut mz_stream) -> c_int;
 }
 
 #[derive(Debug)]
@@ -156,6 +193,30 @@ impl<D: Direction> Stream<D> {
             std::str::from_utf8(s.to_bytes()).ok()
         })
     }
+
+    /// Copies the zlib state, returning the code of a failed copy.
+    pub fn try_clone(&self) -> Result<Self, c_int> {
+        let mut state = StreamWrapper::default();
+        let source = &*self.stream_wrapper as *const mz_stream as *mut mz_stream;
+        match unsafe { D::copy(&mut *state, source) } {
+            MZ_OK => Ok(Stream {
+                stream_wrapper: state,
+                total_in: self.total_in,
+                total_out: self.total_out,
+                _marker: marker::PhantomData,
+            }),
+            c => Err(c),
+        }
+    }
+
+    /// Returns the message for a failed call, falling back to a description
+    /// of its return code.
+    pub fn error(&self, code: c_int) -> ErrorMessage {
+        match self.msg() {
+            ErrorMessage(None) => ErrorMessage::from_code(code),
+            msg => msg,
+        }
+    }
 }
 
 impl<D: Direction> Drop for Stream<D> {
@@ -170,11 +231,17 @@ impl Direction for DirCompress {
     unsafe fn destroy(stream: *mut mz_stream) -> c_int {
         mz_deflateEnd(stream)
     }
+    unsafe fn copy(dest: *mut mz_stream, source: *mut mz_stream) -> c_int {
+        deflateCopy(dest, source)
+    }
 }
 impl Direction for DirDecompress {
     unsafe fn destroy(stream: *mut mz_stream) -> c_int {
         mz_inflateEnd(stream)
     }
+    unsafe fn copy(dest: *mut mz_stream, source: *mut mz_stream) -> c_int {
+        inflateCopy(dest, source)
+    }
 }
 
 #[derive(Debug)]
@@ -183,25 +250,28 @@ pub struct Inflate {
 }
 
 impl InflateBackend for Inflate {
-    fn make(zlib_header: bool, window_bits: u8) -> Self {
+    fn make(format: Option<Format>, window_bits: u8) -> Result<Self, DecompressError> {
         unsafe {
             let mut state = StreamWrapper::default();
-            let ret = mz_inflateInit2(
-                &mut *state,
-                if zlib_header {
-                    window_bits as c_int
-                } else {
-                    -(window_bits as c_int)
-                },
-            );
-            assert_eq!(ret, 0);
-            Inflate {
-                inner: Stream {
-                    stream_wrapper: state,
-                    total_in: 0,
-                    total_out: 0,
-                    _marker: marker::PhantomData,
-                },
+            let ret = mz_inflateInit2(&mut *state, zlib_inflate_window_bits(format, window_bits));
+            let inner = Stream {
+                stream_wrapper: state,
+                total_in: 0,
+                total_out: 0,
+                _marker: marker::PhantomData,
+            };
+            match ret {
+                MZ_OK => Ok(Inflate { inner }),
+                c => mem::decompress_failed(DecompressErrorKind::Other, inner.error(c)),
+            }
+        }
+    }
+
+    fn try_clone(&self) -> Result<Self, DecompressError> {
+        match self.inner.try_clone() {
+            Ok(inner) => Ok(Inflate { inner }),
+            Err(c) => {
+                mem::decompress_failed(DecompressErrorKind::Other, ErrorMessage::from_code(c))
             }
         }
     }
@@ -227,24 +297,122 @@ impl InflateBackend for Inflate {
         self.inner.total_out += (raw.next_out as usize - output.as_ptr() as usize) as u64;
 
         match rc {
-            MZ_DATA_ERROR | MZ_STREAM_ERROR => mem::decompress_failed(self.inner.msg()),
+            MZ_DATA_ERROR => {
+                let msg = self.inner.msg();
+                mem::decompress_failed(msg.data_error_kind(), msg)
+            }
+            MZ_STREAM_ERROR => mem::decompress_failed(DecompressErrorKind::Other, self.inner.msg()),
             MZ_OK => Ok(Status::Ok),
             MZ_BUF_ERROR => Ok(Status::BufError),
             MZ_STREAM_END => Ok(Status::StreamEnd),
             MZ_NEED_DICT => mem::decompress_need_dict(raw.adler as u32),
-            c => panic!("unknown return code: {}", c),
+            c => mem::decompress_failed(DecompressErrorKind::Other, self.inner.error(c)),
         }
     }
 
-    fn reset(&mut self, zlib_header: bool) {
-        let bits = if zlib_header {
-            MZ_DEFAULT_WINDOW_BITS
-        } else {
-            -MZ_DEFAULT_WINDOW_BITS
-        };
-        unsafe {
-            inflateReset2(&mut *self.inner.stream_wrapper, bits);
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
+        if dictionary.len() > uInt::MAX as usize {
+            return mem::decompress_failed(
+                DecompressErrorKind::Other,
+                ErrorMessage(Some("dictionary too long")),
+            );
         }
+        let stream = &mut *self.inner.stream_wrapper;
+        stream.msg = ptr::null_mut();
+        let rc =
+            unsafe { inflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as uInt) };
+
+        match rc {
+            MZ_STREAM_ERROR => mem::decompress_failed(DecompressErrorKind::Other, self.inner.msg()),
+            MZ_DATA_ERROR => mem::decompress_need_dict(stream.adler as u32),
+            MZ_OK => Ok(stream.adler as u32),
+            c => mem::decompress_failed(DecompressErrorKind::Other, self.inner.error(c)),
+        }
+    }
+
+    fn get_dictionary(&self) -> Result<Vec<u8>, DecompressError> {
+        // zlib copies at most the window, which is never more than 32KiB.
+        let stream = &*self.inner.stream_wrapper as *const mz_stream as *mut mz_stream;
+        let mut dictionary = vec![0; 1 << MZ_DEFAULT_WINDOW_BITS];
+        let mut len: uInt = 0;
+        let rc = unsafe { inflateGetDictionary(stream, dictionary.as_mut_ptr(), &mut len) };
+
+        match rc {
+            MZ_OK => {
+                dictionary.truncate(len as usize);
+                Ok(dictionary)
+            }
+            c => mem::decompress_failed(DecompressErrorKind::Other, ErrorMessage::from_code(c)),
+        }
+    }
+
+    fn sync(&mut self, input: &[u8]) -> Result<Status, DecompressError> {
+        let raw = &mut *self.inner.stream_wrapper;
+        raw.msg = ptr::null_mut();
+        raw.next_in = input.as_ptr() as *mut u8;
+        raw.avail_in = cmp::min(input.len(), c_uint::max_value() as usize) as c_uint;
+
+        let rc = unsafe { inflateSync(raw) };
+        self.inner.total_in += (raw.next_in as usize - input.as_ptr() as usize) as u64;
+
+        match rc {
+            MZ_OK => Ok(Status::Ok),
+            // zlib reports a marker which was not found as a data error.
+            MZ_BUF_ERROR | MZ_DATA_ERROR => Ok(Status::BufError),
+            MZ_STREAM_ERROR => mem::decompress_failed(DecompressErrorKind::Other, self.inner.msg()),
+            c => mem::decompress_failed(DecompressErrorKind::Other, self.inner.error(c)),
+        }
+    }
+
+    // zlib reports these in `data_type` after each call to `inflate`.
+    fn at_block_boundary(&self) -> bool {
+        self.inner.stream_wrapper.data_type & 128 != 0
+    }
+
+    fn last_block(&self) -> bool {
+        self.inner.stream_wrapper.data_type & 64 != 0
+    }
+
+    fn unused_bits(&self) -> u8 {
+        (self.inner.stream_wrapper.data_type & 63) as u8
+    }
+
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), DecompressError> {
+        let stream = &mut *self.inner.stream_wrapper;
+        stream.msg = ptr::null_mut();
+        let rc = unsafe { inflatePrime(stream, bits as c_int, value as c_int) };
+
+        match rc {
+            MZ_OK => Ok(()),
+            MZ_STREAM_ERROR => mem::decompress_failed(DecompressErrorKind::Other, self.inner.msg()),
+            c => mem::decompress_failed(DecompressErrorKind::Other, self.inner.error(c)),
+        }
+    }
+
+    fn mark(&self) -> DecompressMark {
+        // The upper half holds the bits back, or -1 outside of a code, and the
+        // lower 16 bits the bytes emitted or left in a stored block.
+        let stream = &*self.inner.stream_wrapper as *const mz_stream as *mut mz_stream;
+        let mark = unsafe { inflateMark(stream) };
+        let back = mark >> 16;
+        let len = (mark & 0xffff) as usize;
+        match back {
+            -1 if len == 0 => DecompressMark::OutsideBlock,
+            -1 => DecompressMark::Stored { remaining: len },
+            _ if back < 0 => DecompressMark::OutsideBlock,
+            _ => DecompressMark::Code {
+                bits_back: back as u32,
+                emitted: len,
+            },
+        }
+    }
+
+    fn reset(&mut self, format: Option<Format>) {
+        let bits = zlib_inflate_window_bits(format, MZ_DEFAULT_WINDOW_BITS as u8);
+        // inflateReset2 only fails for a stream which wasn't set up or window
+        // bits out of range, both of which `make` made sure of.
+        let rc = unsafe { inflateReset2(&mut *self.inner.stream_wrapper, bits) };
+        debug_assert_eq!(rc, MZ_OK);
         self.inner.total_out = 0;
         self.inner.total_in = 0;
     }
@@ -262,38 +430,73 @@ impl Backend for Inflate {
     }
 }
 
+/// Encodes the container format into the window bits passed to zlib, which
+/// negates them for raw streams and adds 16 for gzip.
+fn zlib_window_bits(format: Format, window_bits: u8) -> c_int {
+    let window_bits = window_bits as c_int;
+    match format {
+        Format::Raw => -window_bits,
+        Format::Zlib => window_bits,
+        Format::Gzip => window_bits + 16,
+    }
+}
+
+/// Like `zlib_window_bits`, but adds 32 to detect the format without one.
+fn zlib_inflate_window_bits(format: Option<Format>, window_bits: u8) -> c_int {
+    match format {
+        Some(format) => zlib_window_bits(format, window_bits),
+        None => window_bits as c_int + 32,
+    }
+}
+
 #[derive(Debug)]
 pub struct Deflate {
     pub inner: Stream<DirCompress>,
+    pub strategy: c_int,
 }
 
 impl DeflateBackend for Deflate {
-    fn make(level: Compression, zlib_header: bool, window_bits: u8) -> Self {
+    fn make(options: &CompressOptions) -> Result<Self, CompressError> {
+        if options.level.0 > 9 {
+            return mem::compress_unsupported(CompressOption::Level);
+        }
+        let window_bits = zlib_window_bits(options.format, options.window_bits);
         unsafe {
             let mut state = StreamWrapper::default();
             let ret = mz_deflateInit2(
                 &mut *state,
-                level.0 as c_int,
+                options.level.0 as c_int,
                 MZ_DEFLATED,
-                if zlib_header {
-                    window_bits as c_int
-                } else {
-                    -(window_bits as c_int)
-                },
-                8,
-                MZ_DEFAULT_STRATEGY,
+                window_bits,
+                options.mem_level as c_int,
+                options.strategy as c_int,
             );
-            assert_eq!(ret, 0);
-            Deflate {
-                inner: Stream {
-                    stream_wrapper: state,
-                    total_in: 0,
-                    total_out: 0,
-                    _marker: marker::PhantomData,
-                },
+            let inner = Stream {
+                stream_wrapper: state,
+                total_in: 0,
+                total_out: 0,
+                _marker: marker::PhantomData,
+            };
+            match ret {
+                MZ_OK => Ok(Deflate {
+                    inner,
+                    strategy: options.strategy as c_int,
+                }),
+                c => mem::compress_failed(inner.error(c)),
             }
         }
     }
+
+    fn try_clone(&self) -> Result<Self, CompressError> {
+        match self.inner.try_clone() {
+            Ok(inner) => Ok(Deflate {
+                inner,
+                strategy: self.strategy,
+            }),
+            Err(c) => mem::compress_failed(ErrorMessage::from_code(c)),
+        }
+    }
+
     fn compress(
         &mut self,
         input: &[u8],
@@ -319,15 +522,60 @@ impl DeflateBackend for Deflate {
             MZ_BUF_ERROR => Ok(Status::BufError),
             MZ_STREAM_END => Ok(Status::StreamEnd),
             MZ_STREAM_ERROR => mem::compress_failed(self.inner.msg()),
-            c => panic!("unknown return code: {}", c),
+            c => mem::compress_failed(self.inner.error(c)),
+        }
+    }
+
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
+        if dictionary.len() > uInt::MAX as usize {
+            return mem::compress_failed(ErrorMessage(Some("dictionary too long")));
+        }
+        let stream = &mut *self.inner.stream_wrapper;
+        stream.msg = ptr::null_mut();
+        let rc =
+            unsafe { deflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as uInt) };
+
+        match rc {
+            MZ_STREAM_ERROR => mem::compress_failed(self.inner.msg()),
+            MZ_OK => Ok(stream.adler as u32),
+            c => mem::compress_failed(self.inner.error(c)),
+        }
+    }
+
+    fn get_dictionary(&self) -> Result<Vec<u8>, CompressError> {
+        let stream = &*self.inner.stream_wrapper as *const mz_stream as *mut mz_stream;
+        let mut dictionary = vec![0; 1 << MZ_DEFAULT_WINDOW_BITS];
+        let mut len: uInt = 0;
+        let rc = unsafe { deflateGetDictionary(stream, dictionary.as_mut_ptr(), &mut len) };
+
+        match rc {
+            MZ_OK => {
+                dictionary.truncate(len as usize);
+                Ok(dictionary)
+            }
+            c => mem::compress_failed(ErrorMessage::from_code(c)),
+        }
+    }
+
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), CompressError> {
+        let stream = &mut *self.inner.stream_wrapper;
+        stream.msg = ptr::null_mut();
+        let rc = unsafe { deflatePrime(stream, bits as c_int, value as c_int) };
+
+        match rc {
+            MZ_OK => Ok(()),
+            MZ_BUF_ERROR | MZ_STREAM_ERROR => mem::compress_failed(self.inner.msg()),
+            c => mem::compress_failed(self.inner.error(c)),
         }
     }
 
     fn reset(&mut self) {
         self.inner.total_in = 0;
         self.inner.total_out = 0;
+        // deflateReset only fails for a stream which wasn't set up, which
+        // `make` made sure of.
         let rc = unsafe { mz_deflateReset(&mut *self.inner.stream_wrapper) };
-        assert_eq!(rc, MZ_OK);
+        debug_assert_eq!(rc, MZ_OK);
     }
 }
 
@@ -373,19 +621,42 @@ mod c_backend {
     pub use libz::Z_DATA_ERROR as MZ_DATA_ERROR;
     pub use libz::Z_DEFAULT_STRATEGY as MZ_DEFAULT_STRATEGY;
     pub use libz::Z_DEFLATED as MZ_DEFLATED;
+    pub use libz::Z_FILTERED as MZ_FILTERED;
     pub use libz::Z_FINISH as MZ_FINISH;
+    pub use libz::Z_FIXED as MZ_FIXED;
     pub use libz::Z_FULL_FLUSH as MZ_FULL_FLUSH;
+    pub use libz::Z_HUFFMAN_ONLY as MZ_HUFFMAN_ONLY;
+    pub use libz::Z_MEM_ERROR as MZ_MEM_ERROR;
     pub use libz::Z_NEED_DICT as MZ_NEED_DICT;
     pub use libz::Z_NO_FLUSH as MZ_NO_FLUSH;
     pub use libz::Z_OK as MZ_OK;
     pub use libz::Z_PARTIAL_FLUSH as MZ_PARTIAL_FLUSH;
+    pub use libz::Z_RLE as MZ_RLE;
     pub use libz::Z_STREAM_END as MZ_STREAM_END;
     pub use libz::Z_STREAM_ERROR as MZ_STREAM_ERROR;
     pub use libz::Z_SYNC_FLUSH as MZ_SYNC_FLUSH;
+    pub use libz::Z_VERSION_ERROR as MZ_VERSION_ERROR;
     pub type AllocSize = libz::uInt;
 
     pub const MZ_DEFAULT_WINDOW_BITS: c_int = 15;
 
+    // These are missing from the -sys crates, which only declare the API of
+    // older zlib versions.
+    extern "C" {
+        #[cfg_attr(feature = "zlib-ng", link_name = "zng_inflateGetDictionary")]
+        pub fn inflateGetDictionary(
+            strm: *mut mz_stream,
+            dictionary: *mut libz::Bytef,
+            dict_length: *mut libz::uInt,
+        ) -> c_int;
+        #[cfg_attr(feature = "zlib-ng", link_name = "zng_deflateGetDictionary")]
+        pub fn deflateGetDictionary(
+            strm: *mut mz_stream,
+            dictionary: *mut libz::Bytef,
+            dict_length: *mut libz::uInt,
+        ) -> c_int;
+    }
+
     #[cfg(feature = "zlib-ng")]
     const ZLIB_VERSION: &'static str = "2.1.0.devel\0";
     #[cfg(not(feature = "zlib-ng"))]
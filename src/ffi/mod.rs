@@ -1,7 +1,9 @@
 //! This module contains backend-specific code.
 
-use crate::mem::{CompressError, DecompressError, FlushCompress, FlushDecompress, Status};
-use crate::Compression;
+use crate::mem::{
+    CompressError, CompressOptions, DecompressError, DecompressMark, FlushCompress,
+    FlushDecompress, Format, Status,
+};
 
 /// Traits specifying the interface of the backends.
 ///
@@ -12,25 +14,48 @@ pub trait Backend: Sync + Send {
     fn total_out(&self) -> u64;
 }
 
+// A format of `None` has the inflate backends detect a zlib or gzip header
+// from the first byte of the input.
 pub trait InflateBackend: Backend {
-    fn make(zlib_header: bool, window_bits: u8) -> Self;
+    fn make(format: Option<Format>, window_bits: u8) -> Result<Self, DecompressError>
+    where
+        Self: Sized;
+    fn try_clone(&self) -> Result<Self, DecompressError>
+    where
+        Self: Sized;
     fn decompress(
         &mut self,
         input: &[u8],
         output: &mut [u8],
         flush: FlushDecompress,
     ) -> Result<Status, DecompressError>;
-    fn reset(&mut self, zlib_header: bool);
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError>;
+    fn get_dictionary(&self) -> Result<Vec<u8>, DecompressError>;
+    fn sync(&mut self, input: &[u8]) -> Result<Status, DecompressError>;
+    fn at_block_boundary(&self) -> bool;
+    fn last_block(&self) -> bool;
+    fn unused_bits(&self) -> u8;
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), DecompressError>;
+    fn mark(&self) -> DecompressMark;
+    fn reset(&mut self, format: Option<Format>);
 }
 
 pub trait DeflateBackend: Backend {
-    fn make(level: Compression, zlib_header: bool, window_bits: u8) -> Self;
+    fn make(options: &CompressOptions) -> Result<Self, CompressError>
+    where
+        Self: Sized;
+    fn try_clone(&self) -> Result<Self, CompressError>
+    where
+        Self: Sized;
     fn compress(
         &mut self,
         input: &[u8],
         output: &mut [u8],
         flush: FlushCompress,
     ) -> Result<Status, CompressError>;
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError>;
+    fn get_dictionary(&self) -> Result<Vec<u8>, CompressError>;
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), CompressError>;
     fn reset(&mut self);
 }
 
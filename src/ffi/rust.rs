@@ -1,10 +1,18 @@
 //! Implementation for miniz_oxide rust backend.
+//!
+//! miniz_oxide is only used for raw deflate streams here, the zlib and gzip
+//! wrappers around them are handled by this module. That allows honouring
+//! window sizes other than the 32KiB miniz_oxide is built around. Raw deflate
+//! streams are decompressed by the decoder in `crate::inflate`, which unlike
+//! the one of miniz_oxide can tell where it is in the bit stream.
 
+use std::cmp;
 use std::convert::TryInto;
 use std::fmt;
 
-use miniz_oxide::deflate::core::CompressorOxide;
-use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::deflate::core::{
+    create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
 pub use miniz_oxide::*;
 
 pub const MZ_NO_FLUSH: isize = MZFlush::None as isize;
@@ -12,30 +20,143 @@ pub const MZ_PARTIAL_FLUSH: isize = MZFlush::Partial as isize;
 pub const MZ_SYNC_FLUSH: isize = MZFlush::Sync as isize;
 pub const MZ_FULL_FLUSH: isize = MZFlush::Full as isize;
 pub const MZ_FINISH: isize = MZFlush::Finish as isize;
+pub const MZ_BLOCK: isize = MZFlush::Block as isize;
+
+pub const MZ_DEFAULT_STRATEGY: isize = deflate::core::CompressionStrategy::Default as isize;
+pub const MZ_FILTERED: isize = deflate::core::CompressionStrategy::Filtered as isize;
+pub const MZ_HUFFMAN_ONLY: isize = deflate::core::CompressionStrategy::HuffmanOnly as isize;
+pub const MZ_RLE: isize = deflate::core::CompressionStrategy::RLE as isize;
+pub const MZ_FIXED: isize = deflate::core::CompressionStrategy::Fixed as isize;
 
 use super::*;
-use crate::mem;
+use crate::crc::Crc;
+use crate::inflate::{InflateError, InflateStatus, Inflater, WINDOW_SIZE};
+use crate::mem::{self, CompressOption, DecompressErrorKind, Format};
+use crate::Compression;
 
-// miniz_oxide doesn't provide any error messages (yet?)
+// Decompression errors are described with the messages zlib uses, while
+// miniz_oxide doesn't provide any for compression.
 #[derive(Default)]
-pub struct ErrorMessage;
+pub struct ErrorMessage(pub(crate) Option<&'static str>);
 
 impl ErrorMessage {
     pub fn get(&self) -> Option<&str> {
-        None
+        self.0
+    }
+}
+
+impl From<InflateError> for ErrorMessage {
+    fn from(err: InflateError) -> ErrorMessage {
+        ErrorMessage(Some(match err {
+            InflateError::ReservedBlockType => "invalid block type",
+            InflateError::StoredLengthMismatch => "invalid stored block lengths",
+            InflateError::InvalidTable => "invalid code lengths set",
+            InflateError::InvalidCode => "invalid literal/length code",
+            InflateError::InvalidDistanceCode => "invalid distance code",
+            InflateError::DistanceTooFarBack => "invalid distance too far back",
+        }))
     }
 }
 
-fn format_from_bool(zlib_header: bool) -> DataFormat {
-    if zlib_header {
-        DataFormat::Zlib
-    } else {
-        DataFormat::Raw
+/// Sorts the failures of the deflate decoder into the kinds of errors.
+fn error_kind(err: InflateError) -> DecompressErrorKind {
+    match err {
+        InflateError::ReservedBlockType => DecompressErrorKind::InvalidBlockType,
+        InflateError::InvalidTable => DecompressErrorKind::InvalidHuffmanTable,
+        InflateError::InvalidDistanceCode | InflateError::DistanceTooFarBack => {
+            DecompressErrorKind::InvalidDistance
+        }
+        InflateError::StoredLengthMismatch | InflateError::InvalidCode => {
+            DecompressErrorKind::InvalidData
+        }
     }
 }
 
+const GZ_FTEXT: u8 = 1 << 0;
+const GZ_FHCRC: u8 = 1 << 1;
+const GZ_FEXTRA: u8 = 1 << 2;
+const GZ_FNAME: u8 = 1 << 3;
+const GZ_FCOMMENT: u8 = 1 << 4;
+
+/// The check value stored in the trailer of a zlib or gzip stream.
+#[derive(Clone)]
+enum Check {
+    None,
+    Adler32(u32),
+    Crc32(Crc),
+}
+
+impl Check {
+    fn new(format: Format) -> Check {
+        match format {
+            Format::Raw => Check::None,
+            Format::Zlib => Check::Adler32(MZ_ADLER32_INIT),
+            Format::Gzip => Check::Crc32(Crc::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Check::None => {}
+            Check::Adler32(adler) => *adler = mz_adler32_oxide(*adler, data),
+            Check::Crc32(crc) => crc.update(data),
+        }
+    }
+
+    /// Writes the trailer for the data seen so far, returning its length.
+    fn trailer(&self, buf: &mut [u8; 8]) -> usize {
+        match self {
+            Check::None => 0,
+            Check::Adler32(adler) => {
+                buf[..4].copy_from_slice(&adler.to_be_bytes());
+                4
+            }
+            Check::Crc32(crc) => {
+                buf[..4].copy_from_slice(&crc.sum().to_le_bytes());
+                buf[4..].copy_from_slice(&crc.amount().to_le_bytes());
+                8
+            }
+        }
+    }
+}
+
+/// Where an inflate stream is in its wrapper.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum InflateStage {
+    Detect,
+    ZlibHeader,
+    ZlibDictId,
+    NeedsDictionary(u32),
+    GzipHeader,
+    GzipExtraLen,
+    GzipExtra(usize),
+    GzipName,
+    GzipComment,
+    GzipHeaderCrc,
+    Body,
+    Trailer,
+    Done,
+}
+
+#[derive(Clone)]
 pub struct Inflate {
-    inner: Box<InflateState>,
+    inner: Box<Inflater>,
+    window: Box<[u8]>,
+    window_pos: usize,
+    window_avail: usize,
+    window_bits: u8,
+    format: Format,
+    stage: InflateStage,
+    // Bytes of a fixed size part of the header or trailer read so far.
+    buf: [u8; 10],
+    buf_len: usize,
+    header_flags: u8,
+    header_crc: Crc,
+    check: Check,
+    // Cleared once `sync` skips data, as the check can no longer match.
+    verify_check: bool,
+    // Bytes of the `00 00 FF FF` sync marker matched so far.
+    sync_matched: usize,
     total_in: u64,
     total_out: u64,
 }
@@ -44,21 +165,318 @@ impl fmt::Debug for Inflate {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
             f,
-            "miniz_oxide inflate internal state. total_in: {}, total_out: {}",
+            "rust inflate internal state. total_in: {}, total_out: {}",
             self.total_in, self.total_out,
         )
     }
 }
 
-impl InflateBackend for Inflate {
-    fn make(zlib_header: bool, _window_bits: u8) -> Self {
-        let format = format_from_bool(zlib_header);
+impl Inflate {
+    fn start(&mut self, format: Option<Format>) {
+        self.inner.reset();
+        self.inner.set_window_size(1 << self.window_bits);
+        self.window_pos = 0;
+        self.window_avail = 0;
+        // Until the header is detected there is no trailer to expect.
+        self.format = format.unwrap_or(Format::Raw);
+        self.stage = match format {
+            Some(Format::Raw) => InflateStage::Body,
+            Some(Format::Zlib) => InflateStage::ZlibHeader,
+            Some(Format::Gzip) => InflateStage::GzipHeader,
+            None => InflateStage::Detect,
+        };
+        self.buf_len = 0;
+        self.header_flags = 0;
+        self.header_crc.reset();
+        self.check = Check::new(self.format);
+        self.verify_check = true;
+        self.sync_matched = 0;
+        self.total_in = 0;
+        self.total_out = 0;
+    }
+
+    /// Switches an auto-detecting stream over to the wrapper found in its
+    /// first byte.
+    fn start_detected(&mut self, format: Format) {
+        self.format = format;
+        self.stage = match format {
+            Format::Gzip => InflateStage::GzipHeader,
+            _ => InflateStage::ZlibHeader,
+        };
+        self.check = Check::new(format);
+    }
+
+    /// Collects `len` bytes of input into `buf`, returning whether all of them
+    /// are available.
+    fn fill_buf(&mut self, input: &[u8], consumed: &mut usize, len: usize) -> bool {
+        let n = cmp::min(len - self.buf_len, input.len() - *consumed);
+        self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&input[*consumed..*consumed + n]);
+        if !matches!(
+            self.stage,
+            InflateStage::GzipHeaderCrc | InflateStage::Trailer
+        ) {
+            self.header_crc.update(&input[*consumed..*consumed + n]);
+        }
+        self.buf_len += n;
+        *consumed += n;
+        if self.buf_len == len {
+            self.buf_len = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses as much of the zlib or gzip header as is available.
+    fn read_header(&mut self, input: &[u8], consumed: &mut usize) -> Result<(), DecompressError> {
+        loop {
+            match self.stage {
+                InflateStage::Detect => {
+                    // A zlib header can never start with the first gzip magic
+                    // byte, as its compression method would not be deflate.
+                    let format = match input.get(*consumed) {
+                        Some(&0x1f) => Format::Gzip,
+                        Some(_) => Format::Zlib,
+                        None => return Ok(()),
+                    };
+                    self.start_detected(format);
+                }
+                InflateStage::ZlibHeader => {
+                    if !self.fill_buf(input, consumed, 2) {
+                        return Ok(());
+                    }
+                    let (cmf, flg) = (self.buf[0], self.buf[1]);
+                    if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("incorrect header check")),
+                        );
+                    }
+                    if cmf & 0x0f != 8 {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("unknown compression method")),
+                        );
+                    }
+                    if (cmf >> 4) + 8 > self.window_bits {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("invalid window size")),
+                        );
+                    }
+                    self.inner.set_window_size(1 << ((cmf >> 4) + 8));
+                    self.stage = if flg & 0x20 != 0 {
+                        InflateStage::ZlibDictId
+                    } else {
+                        InflateStage::Body
+                    };
+                }
+                InflateStage::ZlibDictId => {
+                    if !self.fill_buf(input, consumed, 4) {
+                        return Ok(());
+                    }
+                    let id = u32::from_be_bytes(self.buf[..4].try_into().unwrap());
+                    self.stage = InflateStage::NeedsDictionary(id);
+                }
+                InflateStage::NeedsDictionary(id) => return mem::decompress_need_dict(id),
+                InflateStage::GzipHeader => {
+                    if !self.fill_buf(input, consumed, 10) {
+                        return Ok(());
+                    }
+                    if self.buf[0] != 0x1f || self.buf[1] != 0x8b {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("incorrect header check")),
+                        );
+                    }
+                    if self.buf[2] != 8 {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("unknown compression method")),
+                        );
+                    }
+                    let reserved = !(GZ_FTEXT | GZ_FHCRC | GZ_FEXTRA | GZ_FNAME | GZ_FCOMMENT);
+                    if self.buf[3] & reserved != 0 {
+                        return mem::decompress_failed(
+                            DecompressErrorKind::InvalidHeader,
+                            ErrorMessage(Some("unknown header flags set")),
+                        );
+                    }
+                    self.header_flags = self.buf[3];
+                    self.stage = InflateStage::GzipExtraLen;
+                }
+                InflateStage::GzipExtraLen => {
+                    if self.header_flags & GZ_FEXTRA == 0 {
+                        self.stage = InflateStage::GzipName;
+                    } else if self.fill_buf(input, consumed, 2) {
+                        let len = u16::from_le_bytes([self.buf[0], self.buf[1]]);
+                        self.stage = InflateStage::GzipExtra(len.into());
+                    } else {
+                        return Ok(());
+                    }
+                }
+                InflateStage::GzipExtra(remaining) => {
+                    let n = cmp::min(remaining, input.len() - *consumed);
+                    self.header_crc.update(&input[*consumed..*consumed + n]);
+                    *consumed += n;
+                    if n < remaining {
+                        self.stage = InflateStage::GzipExtra(remaining - n);
+                        return Ok(());
+                    }
+                    self.stage = InflateStage::GzipName;
+                }
+                InflateStage::GzipName | InflateStage::GzipComment => {
+                    let (flag, next) = if self.stage == InflateStage::GzipName {
+                        (GZ_FNAME, InflateStage::GzipComment)
+                    } else {
+                        (GZ_FCOMMENT, InflateStage::GzipHeaderCrc)
+                    };
+                    if self.header_flags & flag != 0 {
+                        let rest = &input[*consumed..];
+                        let (n, found) = match rest.iter().position(|b| *b == 0) {
+                            Some(i) => (i + 1, true),
+                            None => (rest.len(), false),
+                        };
+                        self.header_crc.update(&rest[..n]);
+                        *consumed += n;
+                        if !found {
+                            return Ok(());
+                        }
+                    }
+                    self.stage = next;
+                }
+                InflateStage::GzipHeaderCrc => {
+                    if self.header_flags & GZ_FHCRC != 0 {
+                        let expected = self.header_crc.sum() as u16;
+                        if !self.fill_buf(input, consumed, 2) {
+                            return Ok(());
+                        }
+                        if u16::from_le_bytes([self.buf[0], self.buf[1]]) != expected {
+                            return mem::decompress_failed(
+                                DecompressErrorKind::InvalidHeader,
+                                ErrorMessage(Some("header crc mismatch")),
+                            );
+                        }
+                    }
+                    self.stage = InflateStage::Body;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Copies decompressed data out of the window into `output`.
+    fn push_window(&mut self, output: &mut [u8], written: &mut usize) {
+        let n = cmp::min(self.window_avail, output.len() - *written);
+        output[*written..*written + n]
+            .copy_from_slice(&self.window[self.window_pos..self.window_pos + n]);
+        self.window_avail -= n;
+        self.window_pos = (self.window_pos + n) & (WINDOW_SIZE - 1);
+        *written += n;
+    }
+
+    /// Runs the deflate decoder until it runs out of input or output space, or
+    /// reaches the end of a block if `stop_at_block` is set.
+    fn read_body(
+        &mut self,
+        input: &[u8],
+        consumed: &mut usize,
+        output: &mut [u8],
+        written: &mut usize,
+        stop_at_block: bool,
+    ) -> Result<(), DecompressError> {
+        loop {
+            self.push_window(output, written);
+            if self.window_avail != 0 || self.stage != InflateStage::Body {
+                return Ok(());
+            }
+
+            // Only decode as much as fits into `output`, which makes the
+            // decoder stop in the same places as zlib does.
+            let (in_bytes, out_bytes, status) = self.inner.inflate(
+                &input[*consumed..],
+                &mut self.window,
+                self.window_pos,
+                output.len() - *written,
+                stop_at_block,
+            );
+            *consumed += in_bytes;
+            self.window_avail = out_bytes;
+            self.check
+                .update(&self.window[self.window_pos..self.window_pos + out_bytes]);
+
+            match status {
+                Ok(InflateStatus::Done) => {
+                    self.stage = match self.format {
+                        Format::Raw => InflateStage::Done,
+                        _ => InflateStage::Trailer,
+                    };
+                }
+                Ok(InflateStatus::OutputFull) => {
+                    self.push_window(output, written);
+                    if *written == output.len() {
+                        return Ok(());
+                    }
+                }
+                Ok(InflateStatus::NeedsInput) | Ok(InflateStatus::BlockEnd) => {
+                    self.push_window(output, written);
+                    return Ok(());
+                }
+                Err(err) => return mem::decompress_failed(error_kind(err), err.into()),
+            }
+        }
+    }
+
+    /// Reads and verifies the zlib or gzip trailer.
+    fn read_trailer(&mut self, input: &[u8], consumed: &mut usize) -> Result<(), DecompressError> {
+        let mut expected = [0; 8];
+        let len = self.check.trailer(&mut expected);
+        if !self.fill_buf(input, consumed, len) {
+            return Ok(());
+        }
+        if self.verify_check && self.buf[..4] != expected[..4] {
+            return mem::decompress_failed(
+                DecompressErrorKind::ChecksumMismatch,
+                ErrorMessage(Some("incorrect data check")),
+            );
+        }
+        if self.verify_check && self.buf[4..len] != expected[4..len] {
+            return mem::decompress_failed(
+                DecompressErrorKind::ChecksumMismatch,
+                ErrorMessage(Some("incorrect length check")),
+            );
+        }
+        self.stage = InflateStage::Done;
+        Ok(())
+    }
+}
 
-        Inflate {
-            inner: InflateState::new_boxed(format),
+impl InflateBackend for Inflate {
+    fn make(format: Option<Format>, window_bits: u8) -> Result<Self, DecompressError> {
+        let mut inflate = Inflate {
+            inner: Box::new(Inflater::new()),
+            window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            window_pos: 0,
+            window_avail: 0,
+            window_bits,
+            format: Format::Raw,
+            stage: InflateStage::Body,
+            buf: [0; 10],
+            buf_len: 0,
+            header_flags: 0,
+            header_crc: Crc::new(),
+            check: Check::None,
+            verify_check: true,
+            sync_matched: 0,
             total_in: 0,
             total_out: 0,
-        }
+        };
+        inflate.start(format);
+        Ok(inflate)
+    }
+
+    fn try_clone(&self) -> Result<Self, DecompressError> {
+        Ok(self.clone())
     }
 
     fn decompress(
@@ -67,31 +485,147 @@ impl InflateBackend for Inflate {
         output: &mut [u8],
         flush: FlushDecompress,
     ) -> Result<Status, DecompressError> {
-        let flush = MZFlush::new(flush as i32).unwrap();
-
-        let res = inflate::stream::inflate(&mut self.inner, input, output, flush);
-        self.total_in += res.bytes_consumed as u64;
-        self.total_out += res.bytes_written as u64;
-
-        match res.status {
-            Ok(status) => match status {
-                MZStatus::Ok => Ok(Status::Ok),
-                MZStatus::StreamEnd => Ok(Status::StreamEnd),
-                MZStatus::NeedDict => {
-                    mem::decompress_need_dict(self.inner.decompressor().adler32().unwrap_or(0))
-                }
-            },
-            Err(status) => match status {
-                MZError::Buf => Ok(Status::BufError),
-                _ => mem::decompress_failed(ErrorMessage),
-            },
+        let mut consumed = 0;
+        let mut written = 0;
+        let stop_at_block = flush == FlushDecompress::Block;
+
+        // Like zlib, stopping at blocks also stops right after a header.
+        let in_header = self.stage != InflateStage::Body;
+        let mut res = self.read_header(input, &mut consumed);
+        if res.is_ok() && !(stop_at_block && in_header && self.stage == InflateStage::Body) {
+            res = self.read_body(input, &mut consumed, output, &mut written, stop_at_block);
+        }
+        if res.is_ok() && self.stage == InflateStage::Trailer {
+            res = self.read_trailer(input, &mut consumed);
+        }
+        self.total_in += consumed as u64;
+        self.total_out += written as u64;
+        res?;
+
+        if self.stage == InflateStage::Done && self.window_avail == 0 {
+            Ok(Status::StreamEnd)
+        } else if consumed == 0 && written == 0 {
+            Ok(Status::BufError)
+        } else {
+            Ok(Status::Ok)
         }
     }
 
-    fn reset(&mut self, zlib_header: bool) {
-        self.inner.reset(format_from_bool(zlib_header));
-        self.total_in = 0;
-        self.total_out = 0;
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
+        let adler = mz_adler32_oxide(MZ_ADLER32_INIT, dictionary);
+        match self.stage {
+            InflateStage::NeedsDictionary(id) if id != adler => {
+                return mem::decompress_need_dict(id)
+            }
+            InflateStage::NeedsDictionary(_) => self.stage = InflateStage::Body,
+            InflateStage::Body if self.format == Format::Raw && self.window_avail == 0 => {}
+            _ => return mem::decompress_failed(DecompressErrorKind::Other, ErrorMessage(None)),
+        }
+
+        // Place the dictionary right before the current position in the
+        // window so that the following data can refer back into it.
+        let mask = WINDOW_SIZE - 1;
+        let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+        let start = self.window_pos.wrapping_sub(dictionary.len()) & mask;
+        let (head, tail) = dictionary.split_at(cmp::min(dictionary.len(), WINDOW_SIZE - start));
+        self.window[start..start + head.len()].copy_from_slice(head);
+        self.window[..tail.len()].copy_from_slice(tail);
+        self.inner.add_history(dictionary.len());
+        Ok(adler)
+    }
+
+    fn get_dictionary(&self) -> Result<Vec<u8>, DecompressError> {
+        // Output still waiting in the window hasn't been returned yet, so it
+        // isn't part of the dictionary.
+        let len = cmp::min(
+            self.inner.history() - self.window_avail,
+            1 << self.window_bits,
+        );
+        let start = self.window_pos.wrapping_sub(len) & (WINDOW_SIZE - 1);
+        let mut dictionary = Vec::with_capacity(len);
+        if start + len > WINDOW_SIZE {
+            dictionary.extend_from_slice(&self.window[start..]);
+            dictionary.extend_from_slice(&self.window[..self.window_pos]);
+        } else {
+            dictionary.extend_from_slice(&self.window[start..start + len]);
+        }
+        Ok(dictionary)
+    }
+
+    fn sync(&mut self, input: &[u8]) -> Result<Status, DecompressError> {
+        // Search for the marker the same way zlib does, carrying a partial
+        // match over to the next call.
+        let mut matched = self.sync_matched;
+        let mut consumed = 0;
+        while consumed < input.len() && matched < 4 {
+            let byte = input[consumed];
+            if byte == if matched < 2 { 0 } else { 0xff } {
+                matched += 1;
+            } else if byte != 0 {
+                matched = 0;
+            } else {
+                matched = 4 - matched;
+            }
+            consumed += 1;
+        }
+        self.total_in += consumed as u64;
+        if matched < 4 {
+            self.sync_matched = matched;
+            return Ok(Status::BufError);
+        }
+
+        // A full flush point is followed by a new block which does not refer
+        // back to earlier data, so decoding can start over from scratch. Any
+        // output decoded before the damage is still handed out first.
+        self.sync_matched = 0;
+        self.inner.reset();
+        self.buf_len = 0;
+        match self.stage {
+            InflateStage::Body | InflateStage::Trailer | InflateStage::Done => {}
+            // Without a header there is no trailer to expect either.
+            _ => self.format = Format::Raw,
+        }
+        self.stage = InflateStage::Body;
+        self.verify_check = false;
+        Ok(Status::Ok)
+    }
+
+    fn at_block_boundary(&self) -> bool {
+        self.stage == InflateStage::Body && self.inner.at_block_boundary()
+    }
+
+    fn last_block(&self) -> bool {
+        self.inner.last_block()
+    }
+
+    fn unused_bits(&self) -> u8 {
+        self.inner.unused_bits() as u8
+    }
+
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), DecompressError> {
+        if bits > 16
+            || self.stage != InflateStage::Body
+            || !self.inner.prime(bits.into(), value.into())
+        {
+            return mem::decompress_failed(DecompressErrorKind::Other, ErrorMessage(None));
+        }
+        Ok(())
+    }
+
+    fn mark(&self) -> DecompressMark {
+        if self.stage != InflateStage::Body {
+            return DecompressMark::OutsideBlock;
+        }
+        match self.inner.mark() {
+            (Some(bits_back), emitted) => DecompressMark::Code { bits_back, emitted },
+            (None, 0) => DecompressMark::OutsideBlock,
+            (None, remaining) => DecompressMark::Stored { remaining },
+        }
+    }
+
+    fn reset(&mut self, format: Option<Format>) {
+        self.window_bits = MZ_DEFAULT_WINDOW_BITS as u8;
+        self.start(format);
     }
 }
 
@@ -107,8 +641,52 @@ impl Backend for Inflate {
     }
 }
 
+/// Where a deflate stream is in its wrapper.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum DeflateStage {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
 pub struct Deflate {
     inner: Box<CompressorOxide>,
+    flags: u32,
+    format: Format,
+    stage: DeflateStage,
+    header: [u8; 10],
+    header_len: usize,
+    // The header or trailer bytes currently being written.
+    pending: [u8; 16],
+    pending_pos: usize,
+    pending_len: usize,
+    // Bits inserted by `prime` which don't make up a whole byte yet.
+    prime_value: u32,
+    prime_bits: u8,
+    check: Check,
+    // Whether miniz_oxide may still hold output from the last call which
+    // filled up the output buffer.
+    output_pending: bool,
+    finishing: bool,
+    // Windows smaller than the 32KiB of miniz_oxide are emulated by
+    // restarting it with the second half of the window whenever it could
+    // refer back further. Without a smaller window this is `usize::MAX`.
+    window_size: usize,
+    // The last input given to miniz_oxide, of which the last `history_len`
+    // bytes are part of the stream since it was started or fully flushed, and
+    // the last `window_len` bytes can be referred back to by miniz_oxide.
+    history: Box<[u8]>,
+    history_pos: usize,
+    history_len: usize,
+    window_len: usize,
+    // Whether miniz_oxide is restarted once the sync flush ending the current
+    // window has been written out.
+    sliding: bool,
+    // Whether all input has been flushed, so that miniz_oxide holds nothing
+    // but its window. Its state can't be copied, but then compressing the
+    // window recreates it.
+    flushed: bool,
     total_in: u64,
     total_out: u64,
 }
@@ -123,20 +701,302 @@ impl fmt::Debug for Deflate {
     }
 }
 
+impl Deflate {
+    fn start(&mut self) {
+        self.stage = DeflateStage::Header;
+        self.pending[..self.header_len].copy_from_slice(&self.header[..self.header_len]);
+        self.pending_pos = 0;
+        self.pending_len = self.header_len;
+        self.prime_value = 0;
+        self.prime_bits = 0;
+        self.check = Check::new(self.format);
+        self.output_pending = false;
+        self.finishing = false;
+        self.history_pos = 0;
+        self.history_len = 0;
+        self.window_len = 0;
+        self.sliding = false;
+        self.flushed = true;
+        self.total_in = 0;
+        self.total_out = 0;
+    }
+
+    /// Adds data given to miniz_oxide to the history.
+    fn remember(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(WINDOW_SIZE)..];
+        let head = cmp::min(data.len(), WINDOW_SIZE - self.history_pos);
+        self.history[self.history_pos..self.history_pos + head].copy_from_slice(&data[..head]);
+        self.history[..data.len() - head].copy_from_slice(&data[head..]);
+        self.history_pos = (self.history_pos + data.len()) & (WINDOW_SIZE - 1);
+        self.history_len = cmp::min(self.history_len + data.len(), WINDOW_SIZE);
+    }
+
+    /// Returns up to the last `len` bytes miniz_oxide can refer back to.
+    fn history(&self, len: usize) -> Vec<u8> {
+        let len = cmp::min(len, self.history_len);
+        let start = self.history_pos.wrapping_sub(len) & (WINDOW_SIZE - 1);
+        let mut history = Vec::with_capacity(len);
+        if start + len > WINDOW_SIZE {
+            history.extend_from_slice(&self.history[start..]);
+            history.extend_from_slice(&self.history[..self.history_pos]);
+        } else {
+            history.extend_from_slice(&self.history[start..start + len]);
+        }
+        history
+    }
+
+    /// Restarts miniz_oxide with the second half of the window as its
+    /// dictionary, so that it can't refer back further than the window while
+    /// still finding matches in the data just before.
+    fn slide(&mut self) {
+        let keep = self.history(self.window_size / 2);
+        self.inner.reset();
+        deflate::core::compress_to_output(&mut self.inner, &keep, TDEFLFlush::Sync, |_| true);
+        self.window_len = keep.len();
+        self.sliding = false;
+    }
+
+    /// Follows the bits inserted by `prime` with an empty stored block, which
+    /// gets the output back to the byte boundary miniz_oxide starts at.
+    fn finish_prime(&mut self) {
+        if self.prime_bits == 0 {
+            return;
+        }
+        // The stored block header is three zero bits padded to a whole byte,
+        // which takes up the rest of the byte or spills into the next one.
+        let len = if self.prime_bits + 3 > 8 { 2 } else { 1 };
+        for i in 0..len {
+            self.pending[self.pending_len + i] = (self.prime_value >> (8 * i)) as u8;
+        }
+        self.pending_len += len;
+        self.pending[self.pending_len..self.pending_len + 4].copy_from_slice(&[0, 0, 0xff, 0xff]);
+        self.pending_len += 4;
+        self.prime_value = 0;
+        self.prime_bits = 0;
+    }
+
+    fn write_pending(&mut self, output: &mut [u8], written: &mut usize) -> bool {
+        let n = cmp::min(self.pending_len - self.pending_pos, output.len() - *written);
+        output[*written..*written + n]
+            .copy_from_slice(&self.pending[self.pending_pos..self.pending_pos + n]);
+        self.pending_pos += n;
+        *written += n;
+        self.pending_pos == self.pending_len
+    }
+
+    /// Runs miniz_oxide, keeping track of whether it could have kept back
+    /// some of its output.
+    fn run(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        written: &mut usize,
+        flush: TDEFLFlush,
+    ) -> Result<usize, CompressError> {
+        if flush == TDEFLFlush::Finish {
+            self.finishing = true;
+        }
+        let (status, in_bytes, out_bytes) =
+            deflate::core::compress(&mut self.inner, input, &mut output[*written..], flush);
+        *written += out_bytes;
+        self.output_pending = *written == output.len();
+        self.check.update(&input[..in_bytes]);
+        self.remember(&input[..in_bytes]);
+        self.window_len = cmp::min(self.window_len + in_bytes, WINDOW_SIZE);
+        match flush {
+            // A full flush makes miniz_oxide forget about earlier data.
+            TDEFLFlush::Full if in_bytes == input.len() => {
+                self.history_len = 0;
+                self.window_len = 0;
+                self.flushed = true;
+            }
+            TDEFLFlush::Sync if in_bytes == input.len() => self.flushed = true,
+            _ if in_bytes > 0 => self.flushed = false,
+            _ => {}
+        }
+        match status {
+            TDEFLStatus::Okay => Ok(in_bytes),
+            TDEFLStatus::Done => {
+                let mut trailer = [0; 8];
+                self.stage = DeflateStage::Trailer;
+                self.pending_len = self.check.trailer(&mut trailer);
+                self.pending[..8].copy_from_slice(&trailer);
+                self.pending_pos = 0;
+                Ok(in_bytes)
+            }
+            _ => mem::compress_failed(ErrorMessage(None)),
+        }
+    }
+
+    fn write_body(
+        &mut self,
+        input: &[u8],
+        consumed: &mut usize,
+        output: &mut [u8],
+        written: &mut usize,
+        flush: TDEFLFlush,
+    ) -> Result<(), CompressError> {
+        while self.stage == DeflateStage::Body && *written < output.len() {
+            // If miniz_oxide is holding back output a call would only drain
+            // it, so drain it first to make sure the next call does its job.
+            // Once finishing the call is repeated until the stream is done
+            // anyway.
+            if self.output_pending && !self.finishing {
+                self.run(&[], output, written, TDEFLFlush::None)?;
+                continue;
+            }
+            if self.sliding {
+                self.slide();
+            }
+
+            // With a smaller window only hand miniz_oxide as much input as it
+            // can take before having to be restarted, which a sync flush
+            // prepares for by ending the output on a byte boundary.
+            let room = self.window_size - self.window_len;
+            if room == 0 && *consumed < input.len() {
+                self.run(&[], output, written, TDEFLFlush::Sync)?;
+                self.sliding = true;
+                continue;
+            }
+            let end = *consumed + cmp::min(input.len() - *consumed, room);
+            let flush = if end == input.len() {
+                flush
+            } else {
+                TDEFLFlush::None
+            };
+            *consumed += self.run(&input[*consumed..end], output, written, flush)?;
+            if !self.output_pending && *consumed == input.len() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl DeflateBackend for Deflate {
-    fn make(level: Compression, zlib_header: bool, _window_bits: u8) -> Self {
-        // Check in case the integer value changes at some point.
-        debug_assert!(level.level() <= 10);
+    fn make(options: &CompressOptions) -> Result<Self, CompressError> {
+        // miniz_oxide supports one level above zlib's maximum.
+        let level: u8 = match options.level.level().try_into() {
+            Ok(level) if level <= 10 => level,
+            _ => return mem::compress_unsupported(CompressOption::Level),
+        };
+        if options.mem_level != 8 {
+            return mem::compress_unsupported(CompressOption::MemLevel);
+        }
+        let flags = create_comp_flags_from_zip_params(
+            level.into(),
+            -MZ_DEFAULT_WINDOW_BITS,
+            options.strategy as i32,
+        );
+        let window_size = if options.window_bits < MZ_DEFAULT_WINDOW_BITS as u8 {
+            1 << options.window_bits
+        } else {
+            usize::MAX
+        };
 
-        let mut inner: Box<CompressorOxide> = Box::default();
-        let format = format_from_bool(zlib_header);
-        inner.set_format_and_level(format, level.level().try_into().unwrap_or(1));
+        let mut header = [0; 10];
+        let header_len = match options.format {
+            Format::Raw => 0,
+            Format::Zlib => {
+                let cmf = (options.window_bits - 8) << 4 | 8;
+                let level_flags: u16 = if options.strategy as isize >= MZ_HUFFMAN_ONLY || level < 2
+                {
+                    0
+                } else if level < 6 {
+                    1
+                } else if level == 6 {
+                    2
+                } else {
+                    3
+                };
+                let check = (u16::from(cmf) << 8 | level_flags << 6) % 31;
+                header[0] = cmf;
+                header[1] = (level_flags << 6 | (31 - check)) as u8;
+                2
+            }
+            Format::Gzip => {
+                header[..4].copy_from_slice(&[0x1f, 0x8b, 8, 0]);
+                header[8] = if u32::from(level) >= Compression::best().level() {
+                    2
+                } else if u32::from(level) <= Compression::fast().level() {
+                    4
+                } else {
+                    0
+                };
+                header[9] = 255;
+                10
+            }
+        };
 
-        Deflate {
-            inner,
+        let mut deflate = Deflate {
+            inner: Box::new(CompressorOxide::new(flags)),
+            flags,
+            format: options.format,
+            stage: DeflateStage::Header,
+            header,
+            header_len,
+            pending: [0; 16],
+            pending_pos: 0,
+            pending_len: 0,
+            prime_value: 0,
+            prime_bits: 0,
+            check: Check::None,
+            output_pending: false,
+            finishing: false,
+            window_size,
+            history: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            history_pos: 0,
+            history_len: 0,
+            window_len: 0,
+            sliding: false,
+            flushed: true,
             total_in: 0,
             total_out: 0,
+        };
+        deflate.start();
+        Ok(deflate)
+    }
+
+    fn try_clone(&self) -> Result<Self, CompressError> {
+        // Compressing the window with a sync flush leaves a new compressor in
+        // the same state as one which has just been flushed. Once the stream
+        // is finished miniz_oxide isn't needed anymore.
+        let mut inner = Box::new(CompressorOxide::new(self.flags));
+        if self.stage == DeflateStage::Header || self.stage == DeflateStage::Body {
+            if !self.flushed || self.output_pending {
+                return mem::compress_unavailable();
+            }
+            let window = self.history(self.window_len);
+            if !window.is_empty() {
+                deflate::core::compress_to_output(&mut inner, &window, TDEFLFlush::Sync, |_| true);
+            }
         }
+
+        Ok(Deflate {
+            inner,
+            flags: self.flags,
+            format: self.format,
+            stage: self.stage,
+            header: self.header,
+            header_len: self.header_len,
+            pending: self.pending,
+            pending_pos: self.pending_pos,
+            pending_len: self.pending_len,
+            prime_value: self.prime_value,
+            prime_bits: self.prime_bits,
+            check: self.check.clone(),
+            output_pending: self.output_pending,
+            finishing: self.finishing,
+            window_size: self.window_size,
+            history: self.history.clone(),
+            history_pos: self.history_pos,
+            history_len: self.history_len,
+            window_len: self.window_len,
+            sliding: self.sliding,
+            flushed: self.flushed,
+            total_in: self.total_in,
+            total_out: self.total_out,
+        })
     }
 
     fn compress(
@@ -145,28 +1005,112 @@ impl DeflateBackend for Deflate {
         output: &mut [u8],
         flush: FlushCompress,
     ) -> Result<Status, CompressError> {
-        let flush = MZFlush::new(flush as i32).unwrap();
-        let res = deflate::stream::deflate(&mut self.inner, input, output, flush);
-        self.total_in += res.bytes_consumed as u64;
-        self.total_out += res.bytes_written as u64;
+        if output.is_empty() {
+            return Ok(Status::BufError);
+        }
+        // miniz_oxide can only end a block with a sync flush, which follows
+        // it with an empty stored block to get to a byte boundary.
+        let flush = match flush {
+            FlushCompress::Block => MZFlush::Sync,
+            _ => MZFlush::new(flush as i32).unwrap(),
+        };
+        let mut consumed = 0;
+        let mut written = 0;
 
-        match res.status {
-            Ok(status) => match status {
-                MZStatus::Ok => Ok(Status::Ok),
-                MZStatus::StreamEnd => Ok(Status::StreamEnd),
-                MZStatus::NeedDict => mem::compress_failed(ErrorMessage),
-            },
-            Err(status) => match status {
-                MZError::Buf => Ok(Status::BufError),
-                _ => mem::compress_failed(ErrorMessage),
-            },
+        if self.stage == DeflateStage::Header {
+            self.finish_prime();
+            if self.write_pending(output, &mut written) {
+                self.stage = DeflateStage::Body;
+            }
         }
+        let res = self.write_body(input, &mut consumed, output, &mut written, flush.into());
+        if self.stage == DeflateStage::Trailer && self.write_pending(output, &mut written) {
+            self.stage = DeflateStage::Done;
+        }
+        self.total_in += consumed as u64;
+        self.total_out += written as u64;
+        res?;
+
+        if self.stage == DeflateStage::Done {
+            Ok(Status::StreamEnd)
+        } else if flush == MZFlush::None && consumed == 0 && written == 0 {
+            Ok(Status::BufError)
+        } else {
+            Ok(Status::Ok)
+        }
+    }
+
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
+        if self.format == Format::Gzip
+            || self.stage != DeflateStage::Header
+            || self.pending_pos != 0
+            || self.history_len != 0
+        {
+            return mem::compress_failed(ErrorMessage(None));
+        }
+
+        // Compress the dictionary and throw away the output, which leaves it
+        // in the window of miniz_oxide. The sync flush byte aligns the end of
+        // the discarded output so the actual data starts on a fresh block.
+        let max_len = cmp::min(self.window_size, WINDOW_SIZE);
+        let tail = &dictionary[dictionary.len().saturating_sub(max_len)..];
+        self.remember(tail);
+        self.window_len = tail.len();
+        let (status, _) =
+            deflate::core::compress_to_output(&mut self.inner, tail, TDEFLFlush::Sync, |_| true);
+        if status != TDEFLStatus::Okay {
+            return mem::compress_failed(ErrorMessage(None));
+        }
+
+        // Announce the dictionary in the zlib header.
+        let adler = mz_adler32_oxide(MZ_ADLER32_INIT, dictionary);
+        if self.format == Format::Zlib {
+            let flg = u16::from(self.pending[1] & 0xc0 | 0x20);
+            let check = (u16::from(self.pending[0]) << 8 | flg) % 31;
+            self.pending[1] = (flg | (31 - check)) as u8;
+            self.pending[2..6].copy_from_slice(&adler.to_be_bytes());
+            self.pending_len = 6;
+        }
+        Ok(adler)
+    }
+
+    fn get_dictionary(&self) -> Result<Vec<u8>, CompressError> {
+        Ok(self.history(cmp::min(self.history_len, self.window_size)))
+    }
+
+    fn prime(&mut self, bits: u8, value: u16) -> Result<(), CompressError> {
+        // Only raw streams can start with the inserted bits, and miniz_oxide
+        // can't continue from a partial byte once it has started.
+        if bits > 16
+            || self.format != Format::Raw
+            || self.stage != DeflateStage::Header
+            || self.pending_pos != 0
+            || self.total_in != 0
+        {
+            return mem::compress_failed(ErrorMessage(None));
+        }
+
+        // Leave room for `finish_prime` to end the bits.
+        let mut value =
+            self.prime_value | (u32::from(value) & ((1 << bits) - 1)) << self.prime_bits;
+        let mut bits = self.prime_bits + bits;
+        if self.pending_len + usize::from(bits / 8) > self.pending.len() - 6 {
+            return mem::compress_failed(ErrorMessage(None));
+        }
+        while bits >= 8 {
+            self.pending[self.pending_len] = value as u8;
+            self.pending_len += 1;
+            value >>= 8;
+            bits -= 8;
+        }
+        self.prime_value = value;
+        self.prime_bits = bits;
+        Ok(())
     }
 
     fn reset(&mut self) {
-        self.total_in = 0;
-        self.total_out = 0;
         self.inner.reset();
+        self.start();
     }
 }
 
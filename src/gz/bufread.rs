@@ -3,11 +3,11 @@ use std::io;
 use std::io::prelude::*;
 use std::mem;
 
-use super::{GzBuilder, GzHeader};
+use super::{GzBuilder, GzDecoderOptions, GzError, GzErrorKind, GzHeader, GzHeaderLimits};
 use super::{FCOMMENT, FEXTRA, FHCRC, FNAME};
 use crate::crc::{Crc, CrcReader};
 use crate::deflate;
-use crate::Compression;
+use crate::{Compress, Compression, DecompressLimits};
 
 fn copy(into: &mut [u8], from: &[u8], pos: &mut usize) -> usize {
     let min = cmp::min(into.len(), from.len() - *pos);
@@ -18,15 +18,24 @@ fn copy(into: &mut [u8], from: &[u8], pos: &mut usize) -> usize {
     min
 }
 
-pub(crate) fn corrupt() -> io::Error {
-    io::Error::new(
-        io::ErrorKind::InvalidInput,
-        "corrupt gzip stream does not have a matching checksum",
-    )
-}
-
-fn bad_header() -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidInput, "invalid gzip header")
+/// Checks the trailer of a member against the data decompressed from it.
+pub(crate) fn check_trailer(buf: &[u8; 8], crc: &Crc, member: u64, offset: u64) -> io::Result<()> {
+    let (stored, amount) = finish(buf);
+    if stored != crc.sum() {
+        let kind = GzErrorKind::CrcMismatch {
+            stored,
+            computed: crc.sum(),
+        };
+        return Err(GzError::new(kind, member, offset).into());
+    }
+    if amount != crc.amount() {
+        let kind = GzErrorKind::SizeMismatch {
+            stored: amount,
+            computed: crc.amount(),
+        };
+        return Err(GzError::new(kind, member, offset + 4).into());
+    }
+    Ok(())
 }
 
 fn read_le_u16<R: Read>(r: &mut Buffer<R>) -> io::Result<u16> {
@@ -35,6 +44,12 @@ fn read_le_u16<R: Read>(r: &mut Buffer<R>) -> io::Result<u16> {
     Ok((b[0] as u16) | ((b[1] as u16) << 8))
 }
 
+fn read_byte<R: Read>(r: &mut Buffer<R>) -> io::Result<u8> {
+    let mut b = [0];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
 fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()> {
     loop {
         match r.part.state {
@@ -43,10 +58,12 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
                 r.read_and_forget(&mut header)?;
 
                 if header[0] != 0x1f || header[1] != 0x8b {
-                    return Err(bad_header());
+                    let kind = GzErrorKind::InvalidMagic([header[0], header[1]]);
+                    return Err(GzError::new(kind, r.part.member, r.part.start).into());
                 }
                 if header[2] != 8 {
-                    return Err(bad_header());
+                    let kind = GzErrorKind::UnknownMethod(header[2]);
+                    return Err(GzError::new(kind, r.part.member, r.part.start + 2).into());
                 }
 
                 r.part.flg = header[3];
@@ -61,6 +78,10 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
             GzHeaderParsingState::Xlen => {
                 if r.part.flg & FEXTRA != 0 {
                     r.part.xlen = read_le_u16(r)?;
+                    if usize::from(r.part.xlen) > r.part.limits.extra {
+                        let kind = GzErrorKind::ExtraTooLong;
+                        return Err(GzError::new(kind, r.part.member, r.part.start + 10).into());
+                    }
                 }
                 r.part.state = GzHeaderParsingState::Extra;
             }
@@ -77,10 +98,12 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
                     if None == r.part.header.filename {
                         r.part.header.filename = Some(Vec::new());
                     };
-                    for byte in r.bytes() {
-                        let byte = byte?;
-                        if byte == 0 {
-                            break;
+                    let limit = r.part.limits.filename;
+                    while read_byte(r)? != 0 {
+                        if r.part.header.filename.as_ref().unwrap().len() > limit {
+                            let kind = GzErrorKind::FilenameTooLong;
+                            let offset = r.part.field_start();
+                            return Err(GzError::new(kind, r.part.member, offset).into());
                         }
                     }
                 }
@@ -91,10 +114,12 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
                     if None == r.part.header.comment {
                         r.part.header.comment = Some(Vec::new());
                     };
-                    for byte in r.bytes() {
-                        let byte = byte?;
-                        if byte == 0 {
-                            break;
+                    let limit = r.part.limits.comment;
+                    while read_byte(r)? != 0 {
+                        if r.part.header.comment.as_ref().unwrap().len() > limit {
+                            let kind = GzErrorKind::CommentTooLong;
+                            let offset = r.part.field_start();
+                            return Err(GzError::new(kind, r.part.member, offset).into());
                         }
                     }
                 }
@@ -105,7 +130,12 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
                     let stored_crc = read_le_u16(r)?;
                     let calced_crc = r.part.crc.sum() as u16;
                     if stored_crc != calced_crc {
-                        return Err(corrupt());
+                        let kind = GzErrorKind::HeaderCrcMismatch {
+                            stored: stored_crc,
+                            computed: calced_crc,
+                        };
+                        let offset = r.part.start + r.part.len() - 2;
+                        return Err(GzError::new(kind, r.part.member, offset).into());
                     }
                 }
                 return Ok(());
@@ -114,8 +144,29 @@ fn read_gz_header_part<'a, R: Read>(r: &'a mut Buffer<'a, R>) -> io::Result<()>
     }
 }
 
-pub(crate) fn read_gz_header<R: Read>(r: &mut R) -> io::Result<GzHeader> {
-    let mut part = GzHeaderPartial::new();
+/// Tells whether the header of a member failed to parse because the data
+/// following the previous member isn't a member at all, and that is to be
+/// ignored.
+pub(crate) fn is_garbage(err: &io::Error, options: &GzDecoderOptions) -> bool {
+    if !options.ignore_trailing_garbage {
+        return false;
+    }
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        return true;
+    }
+    match err.get_ref().and_then(|err| err.downcast_ref::<GzError>()) {
+        Some(err) => matches!(err.kind(), GzErrorKind::InvalidMagic(_)),
+        None => false,
+    }
+}
+
+pub(crate) fn read_gz_header<R: Read>(
+    r: &mut R,
+    member: u64,
+    start: u64,
+    limits: GzHeaderLimits,
+) -> io::Result<GzHeader> {
+    let mut part = GzHeaderPartial::for_member(member, start, limits);
 
     let result = {
         let mut reader = Buffer::new(&mut part, r);
@@ -162,10 +213,10 @@ pub struct GzEncoder<R> {
     eof: bool,
 }
 
-pub fn gz_encoder<R: BufRead>(header: Vec<u8>, r: R, lvl: Compression) -> GzEncoder<R> {
+pub fn gz_encoder<R: BufRead>(header: Vec<u8>, r: R, compress: Compress) -> GzEncoder<R> {
     let crc = CrcReader::new(r);
     GzEncoder {
-        inner: deflate::bufread::DeflateEncoder::new(crc, lvl),
+        inner: deflate::bufread::DeflateEncoder::new_with_compress(crc, compress),
         header,
         pos: 0,
         eof: false,
@@ -184,6 +235,15 @@ impl<R: BufRead> GzEncoder<R> {
         GzBuilder::new().buf_read(r, level)
     }
 
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: Compression) -> io::Result<GzEncoder<R>> {
+        GzBuilder::new().try_buf_read(r, level)
+    }
+
     fn read_footer(&mut self, into: &mut [u8]) -> io::Result<usize> {
         if self.pos == 8 {
             return Ok(0);
@@ -309,6 +369,26 @@ pub struct GzDecoder<R> {
     header: Option<GzHeader>,
     reader: CrcReader<deflate::bufread::DeflateDecoder<R>>,
     multi: bool,
+    // Whether to stop at the end of each member, see `GzMembers`.
+    split: bool,
+    // The index of the current member and the offsets it and its body start
+    // at.
+    member: u64,
+    start: u64,
+    body_start: u64,
+    limits: DecompressLimits,
+    // The output of the members before the current one.
+    out_base: u64,
+    options: GzDecoderOptions,
+    // Set once the current member has been read to its end.
+    ended: Option<MemberEnd>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MemberEnd {
+    end: u64,
+    size: u64,
+    crc: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -328,17 +408,24 @@ pub struct GzHeaderPartial {
     flg: u8,
     xlen: u16,
     crc: Crc,
+    // The index of the member and where it starts, for errors.
+    member: u64,
+    start: u64,
+    limits: GzHeaderLimits,
     header: GzHeader,
 }
 
 impl GzHeaderPartial {
-    fn new() -> GzHeaderPartial {
+    fn for_member(member: u64, start: u64, limits: GzHeaderLimits) -> GzHeaderPartial {
         GzHeaderPartial {
             buf: Vec::with_capacity(10), // minimum header length
             state: GzHeaderParsingState::Start,
             flg: 0,
             xlen: 0,
             crc: Crc::new(),
+            member,
+            start,
+            limits,
             header: GzHeader {
                 extra: None,
                 filename: None,
@@ -349,6 +436,38 @@ impl GzHeaderPartial {
         }
     }
 
+    /// Returns the length of the header once it has been parsed.
+    fn len(&self) -> u64 {
+        let mut len = 10;
+        if self.flg & FEXTRA != 0 {
+            len += 2 + u64::from(self.xlen);
+        }
+        if let Some(filename) = &self.header.filename {
+            len += filename.len() as u64 + 1;
+        }
+        if let Some(comment) = &self.header.comment {
+            len += comment.len() as u64 + 1;
+        }
+        if self.flg & FHCRC != 0 {
+            len += 2;
+        }
+        len
+    }
+
+    /// Returns the offset of the filename or comment field being parsed.
+    fn field_start(&self) -> u64 {
+        let mut start = self.start + 10;
+        if self.flg & FEXTRA != 0 {
+            start += 2 + u64::from(self.xlen);
+        }
+        if let GzHeaderParsingState::Comment = self.state {
+            if let Some(filename) = &self.header.filename {
+                start += filename.len() as u64 + 1;
+            }
+        }
+        start
+    }
+
     pub fn take_header(self) -> GzHeader {
         self.header
     }
@@ -406,7 +525,10 @@ impl<'a, T: Read> Read for Buffer<'a, T> {
             // we read new bytes and also save them in self.part.buf
             let len = self.reader.read(buf)?;
             self.part.buf.extend_from_slice(&buf[..len]);
-            self.part.crc.update(&buf[..len]);
+            // the header CRC doesn't cover itself
+            if !matches!(self.part.state, GzHeaderParsingState::Crc) {
+                self.part.crc.update(&buf[..len]);
+            }
             Ok(len)
         } else {
             // we first read the previously saved bytes
@@ -437,9 +559,22 @@ where
 impl<R: BufRead> GzDecoder<R> {
     /// Creates a new decoder from the given reader, immediately parsing the
     /// gzip header.
-    pub fn new(mut r: R) -> GzDecoder<R> {
-        let mut part = GzHeaderPartial::new();
+    pub fn new(r: R) -> GzDecoder<R> {
+        GzDecoder::new_with_options(r, GzDecoderOptions::default())
+    }
+
+    /// Creates a new decoder from the given reader, immediately parsing the
+    /// gzip header, which must stay within the given limits.
+    pub fn new_with_header_limits(r: R, header_limits: GzHeaderLimits) -> GzDecoder<R> {
+        GzDecoder::new_with_options(r, GzDecoderOptions::new().header_limits(header_limits))
+    }
+
+    /// Creates a new decoder from the given reader with the given options,
+    /// immediately parsing the gzip header.
+    pub fn new_with_options(mut r: R, options: GzDecoderOptions) -> GzDecoder<R> {
+        let mut part = GzHeaderPartial::for_member(0, 0, options.header_limits);
         let mut header = None;
+        let mut body_start = 0;
 
         let result = {
             let mut reader = Buffer::new(&mut part, &mut r);
@@ -448,6 +583,7 @@ impl<R: BufRead> GzDecoder<R> {
 
         let state = match result {
             Ok(()) => {
+                body_start = part.len();
                 header = Some(part.take_header());
                 GzState::Body
             }
@@ -459,7 +595,15 @@ impl<R: BufRead> GzDecoder<R> {
             inner: state,
             reader: CrcReader::new(deflate::bufread::DeflateDecoder::new(r)),
             multi: false,
+            split: false,
             header,
+            member: 0,
+            start: 0,
+            body_start,
+            limits: DecompressLimits::new(),
+            out_base: 0,
+            options,
+            ended: None,
         }
     }
 
@@ -467,6 +611,75 @@ impl<R: BufRead> GzDecoder<R> {
         self.multi = flag;
         self
     }
+
+    /// Creates a decoder for a single member, which is the given one of a
+    /// larger stream and starts at the given offset in it. Its header isn't
+    /// parsed until the first read.
+    pub(crate) fn for_member(
+        r: R,
+        member: u64,
+        start: u64,
+        options: GzDecoderOptions,
+    ) -> GzDecoder<R> {
+        let part = GzHeaderPartial::for_member(member, start, options.header_limits);
+        GzDecoder {
+            inner: GzState::Header(part),
+            reader: CrcReader::new(deflate::bufread::DeflateDecoder::new(r)),
+            multi: false,
+            split: false,
+            header: None,
+            member,
+            start,
+            body_start: start,
+            limits: DecompressLimits::new(),
+            out_base: 0,
+            options,
+            ended: None,
+        }
+    }
+
+    /// Creates a decoder which stops at the end of every member and doesn't
+    /// parse the first header until asked to.
+    fn split(r: R, options: GzDecoderOptions) -> GzDecoder<R> {
+        let mut decoder = GzDecoder::for_member(r, 0, 0, options).multi(true);
+        decoder.split = true;
+        decoder
+    }
+
+    /// Reads whatever is left of the current member and parses the header of
+    /// the next one, returning whether there is one.
+    fn next_member(&mut self) -> io::Result<bool> {
+        if let GzState::Body | GzState::Finished(..) = self.inner {
+            io::copy(self, &mut io::sink())?;
+        }
+        if let GzState::End = self.inner {
+            let end = match self.ended {
+                Some(ended) => ended.end,
+                None => return Ok(false),
+            };
+            if self.get_mut().fill_buf()?.is_empty() {
+                self.ended = None;
+                return Ok(false);
+            }
+            self.out_base += self.reader.get_ref().total_out();
+            self.reader.reset();
+            self.reader.get_mut().reset_data();
+            self.header = None;
+            self.ended = None;
+            self.member += 1;
+            let part = GzHeaderPartial::for_member(self.member, end, self.options.header_limits);
+            self.inner = GzState::Header(part);
+        }
+        self.read_header()?;
+        Ok(self.header.is_some())
+    }
+
+    /// Parses a pending header without reading any data past it.
+    fn read_header(&mut self) -> io::Result<()> {
+        let n = self.read(&mut [])?;
+        debug_assert_eq!(n, 0);
+        Ok(())
+    }
 }
 
 impl<R> GzDecoder<R> {
@@ -475,6 +688,14 @@ impl<R> GzDecoder<R> {
         self.header.as_ref()
     }
 
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.reader.get_ref().get_ref()
@@ -494,6 +715,51 @@ impl<R> GzDecoder<R> {
     }
 }
 
+// Reads from the body of a member, skipping the CRC unless it's verified.
+fn read_body<R: BufRead>(
+    reader: &mut CrcReader<deflate::bufread::DeflateDecoder<R>>,
+    into: &mut [u8],
+    options: &GzDecoderOptions,
+) -> io::Result<usize> {
+    if options.verify_checksums {
+        reader.read(into)
+    } else {
+        reader.get_mut().read(into)
+    }
+}
+
+// The end of a member whose input ran out `pos` bytes into the trailer.
+fn truncated_end<R: BufRead>(
+    reader: &CrcReader<deflate::bufread::DeflateDecoder<R>>,
+    body_start: u64,
+    pos: usize,
+) -> MemberEnd {
+    MemberEnd {
+        end: body_start + reader.get_ref().total_in() + pos as u64,
+        size: reader.get_ref().total_out(),
+        crc: None,
+    }
+}
+
+// Checks the trailer at `trailer` if checksums are verified and returns the
+// end of the member.
+fn member_end<R: BufRead>(
+    reader: &CrcReader<deflate::bufread::DeflateDecoder<R>>,
+    buf: &[u8; 8],
+    member: u64,
+    trailer: u64,
+    options: &GzDecoderOptions,
+) -> io::Result<MemberEnd> {
+    if options.verify_checksums {
+        check_trailer(buf, reader.crc(), member, trailer)?;
+    }
+    Ok(MemberEnd {
+        end: trailer + 8,
+        size: reader.get_ref().total_out(),
+        crc: Some(finish(buf).0),
+    })
+}
+
 impl<R: BufRead> Read for GzDecoder<R> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
         let GzDecoder {
@@ -501,6 +767,14 @@ impl<R: BufRead> Read for GzDecoder<R> {
             header,
             reader,
             multi,
+            split,
+            member,
+            start,
+            body_start,
+            limits,
+            out_base,
+            options,
+            ended,
         } = self;
 
         loop {
@@ -512,6 +786,9 @@ impl<R: BufRead> Read for GzDecoder<R> {
                     };
                     match result {
                         Ok(()) => {
+                            limits.check_members(*member + 1, part.start)?;
+                            *start = part.start;
+                            *body_start = part.start + part.len();
                             *header = Some(part.take_header());
                             GzState::Body
                         }
@@ -519,6 +796,7 @@ impl<R: BufRead> Read for GzDecoder<R> {
                             *inner = GzState::Header(part);
                             return Err(err);
                         }
+                        Err(ref err) if *member > 0 && is_garbage(err, options) => GzState::End,
                         Err(err) => return Err(err),
                     }
                 }
@@ -528,13 +806,17 @@ impl<R: BufRead> Read for GzDecoder<R> {
                         return Ok(0);
                     }
 
-                    let n = reader.read(into).map_err(|err| {
+                    let into = limits.clamp(*out_base + reader.get_ref().total_out(), into);
+                    let n = read_body(reader, into, options).inspect_err(|err| {
                         if io::ErrorKind::WouldBlock == err.kind() {
                             *inner = GzState::Body;
                         }
-
-                        err
                     })?;
+                    let n = limits.check_read(
+                        *body_start + reader.get_ref().total_in(),
+                        *out_base + reader.get_ref().total_out(),
+                        n,
+                    )?;
 
                     match n {
                         0 => GzState::Finished(0, [0; 8]),
@@ -551,47 +833,58 @@ impl<R: BufRead> Read for GzDecoder<R> {
                             .get_mut()
                             .read(&mut buf[pos..])
                             .and_then(|n| {
-                                if n == 0 {
+                                // Only the trailer may be missing, the body
+                                // itself has to be complete.
+                                if n == 0
+                                    && (!options.allow_truncated_trailer
+                                        || !reader.get_mut().is_finished())
+                                {
                                     Err(io::ErrorKind::UnexpectedEof.into())
                                 } else {
                                     Ok(n)
                                 }
                             })
-                            .map_err(|err| {
+                            .inspect_err(|err| {
                                 if io::ErrorKind::WouldBlock == err.kind() {
                                     *inner = GzState::Finished(pos, buf);
                                 }
-
-                                err
                             })?;
 
-                        GzState::Finished(pos + n, buf)
+                        if n == 0 {
+                            *ended = Some(truncated_end(reader, *body_start, pos));
+                            GzState::End
+                        } else {
+                            GzState::Finished(pos + n, buf)
+                        }
                     } else {
-                        let (crc, amt) = finish(&buf);
+                        let trailer = *body_start + reader.get_ref().total_in();
+                        *ended = Some(member_end(reader, &buf, *member, trailer, options)?);
 
-                        if crc != reader.crc().sum() || amt != reader.crc().amount() {
-                            return Err(corrupt());
-                        } else if *multi {
+                        if *multi && !*split {
                             let is_eof = reader
                                 .get_mut()
                                 .get_mut()
                                 .fill_buf()
                                 .map(|buf| buf.is_empty())
-                                .map_err(|err| {
+                                .inspect_err(|err| {
                                     if io::ErrorKind::WouldBlock == err.kind() {
                                         *inner = GzState::Finished(pos, buf);
                                     }
-
-                                    err
                                 })?;
 
                             if is_eof {
                                 GzState::End
                             } else {
+                                *out_base += reader.get_ref().total_out();
                                 reader.reset();
                                 reader.get_mut().reset_data();
                                 header.take();
-                                GzState::Header(GzHeaderPartial::new())
+                                *member += 1;
+                                GzState::Header(GzHeaderPartial::for_member(
+                                    *member,
+                                    trailer + 8,
+                                    options.header_limits,
+                                ))
                             }
                         } else {
                             GzState::End
@@ -622,11 +915,13 @@ impl<R: BufRead + Write> Write for GzDecoder<R> {
 /// gzip members to be joined in a single stream. `MultiGzDecoder` will
 /// decode all consecutive members while `GzDecoder` will only decompress
 /// the first gzip member. The multistream format is commonly used in
-/// bioinformatics, for example when using the BGZF compressed data.
+/// bioinformatics, for example when using the BGZF compressed data, which
+/// [`BgzfReader`] can also seek in.
 ///
 /// This structure exposes a [`BufRead`] interface that will consume all gzip members
 /// from the underlying reader and emit uncompressed data.
 ///
+/// [`BgzfReader`]: ../bgzf/struct.BgzfReader.html
 /// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
 ///
 /// # Examples
@@ -665,6 +960,26 @@ impl<R: BufRead> MultiGzDecoder<R> {
     pub fn new(r: R) -> MultiGzDecoder<R> {
         MultiGzDecoder(GzDecoder::new(r).multi(true))
     }
+
+    /// Creates a new decoder from the given reader, immediately parsing the
+    /// (first) gzip header. The headers of all members must stay within the
+    /// given limits.
+    pub fn new_with_header_limits(r: R, header_limits: GzHeaderLimits) -> MultiGzDecoder<R> {
+        MultiGzDecoder(GzDecoder::new_with_header_limits(r, header_limits).multi(true))
+    }
+
+    /// Creates a new decoder from the given reader with the given options,
+    /// immediately parsing the (first) gzip header.
+    pub fn new_with_options(r: R, options: GzDecoderOptions) -> MultiGzDecoder<R> {
+        MultiGzDecoder(GzDecoder::new_with_options(r, options).multi(true))
+    }
+
+    /// Creates a decoder for the members from the one at the given offset of
+    /// a stream on, which the reader is at.
+    pub(crate) fn starting_at(r: R, start: u64) -> MultiGzDecoder<R> {
+        let options = GzDecoderOptions::new();
+        MultiGzDecoder(GzDecoder::for_member(r, 0, start, options).multi(true))
+    }
 }
 
 impl<R> MultiGzDecoder<R> {
@@ -673,6 +988,19 @@ impl<R> MultiGzDecoder<R> {
         self.0.header()
     }
 
+    /// Returns the offset in the stream of the member being decoded.
+    pub(crate) fn member_start(&self) -> u64 {
+        self.0.start
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.0.set_limits(limits);
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.0.get_ref()
@@ -698,6 +1026,198 @@ impl<R: BufRead> Read for MultiGzDecoder<R> {
     }
 }
 
+/// An iterator over the members of a gzip multistream
+///
+/// Unlike [`MultiGzDecoder`], which joins the data of all members, this
+/// yields the members one at a time as [`GzMember`]s, each with its own
+/// header, offsets and a reader for its data. Since a member borrows the
+/// underlying reader, members are obtained with [`next_member`] rather than
+/// through the `Iterator` trait. Whatever is left unread of a member is
+/// skipped when moving on to the next one.
+///
+/// An empty stream has no members.
+///
+/// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+/// [`GzMember`]: struct.GzMember.html
+/// [`next_member`]: #method.next_member
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::GzBuilder;
+/// use flate2::bufread::GzMembers;
+///
+/// # fn main() {
+/// #   let mut bytes = Vec::new();
+/// #   for name in &["a.txt", "b.txt"] {
+/// #       let mut e = GzBuilder::new().filename(*name).write(Vec::new(), Compression::default());
+/// #       e.write_all(b"Hello World").unwrap();
+/// #       bytes.extend(e.finish().unwrap());
+/// #   }
+/// #   list_members(&bytes).unwrap();
+/// # }
+/// #
+/// // Prints the name and extent of every member of a multistream
+/// // Here &[u8] implements BufRead
+///
+/// fn list_members(bytes: &[u8]) -> io::Result<()> {
+///    let mut members = GzMembers::new(bytes);
+///    while let Some(mut member) = members.next_member()? {
+///        let name = member.header().filename().unwrap_or_default().to_vec();
+///        io::copy(&mut member, &mut io::sink())?;
+///        println!(
+///            "{}: {}..{}, {} bytes",
+///            String::from_utf8_lossy(&name),
+///            member.start(),
+///            member.end().unwrap(),
+///            member.size().unwrap(),
+///        );
+///    }
+///    Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct GzMembers<R> {
+    inner: GzDecoder<R>,
+    first: bool,
+}
+
+impl<R: BufRead> GzMembers<R> {
+    /// Creates a new iterator over the members read from the given reader.
+    pub fn new(r: R) -> GzMembers<R> {
+        GzMembers::new_with_options(r, GzDecoderOptions::default())
+    }
+
+    /// Creates a new iterator over the members read from the given reader,
+    /// decoding them with the given options.
+    pub fn new_with_options(r: R, options: GzDecoderOptions) -> GzMembers<R> {
+        GzMembers {
+            inner: GzDecoder::split(r, options),
+            first: true,
+        }
+    }
+
+    /// Parses the header of the next member, returning `None` once the end of
+    /// the stream has been reached.
+    ///
+    /// # Errors
+    ///
+    /// Any error decoding the rest of the previous member, including a
+    /// mismatch with its trailer, or parsing the header is returned.
+    pub fn next_member(&mut self) -> io::Result<Option<GzMember<'_, R>>> {
+        let more = if self.first {
+            if self.inner.get_mut().fill_buf()?.is_empty() {
+                false
+            } else {
+                self.inner.read_header()?;
+                self.first = false;
+                self.inner.header.is_some()
+            }
+        } else {
+            self.inner.next_member()?
+        };
+        Ok(if more {
+            Some(GzMember {
+                inner: &mut self.inner,
+            })
+        } else {
+            None
+        })
+    }
+}
+
+impl<R> GzMembers<R> {
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`]. They apply to all members together.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this iterator is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Consumes this iterator, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+/// A single member of a gzip multistream, see [`GzMembers`]
+///
+/// This exposes a [`Read`] interface emitting the uncompressed data of the
+/// member. Its end offset, size and CRC are known once that data has been read
+/// to the end.
+///
+/// [`GzMembers`]: struct.GzMembers.html
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+#[derive(Debug)]
+pub struct GzMember<'a, R> {
+    inner: &'a mut GzDecoder<R>,
+}
+
+impl<'a, R> GzMember<'a, R> {
+    /// Returns the header of this member.
+    pub fn header(&self) -> &GzHeader {
+        self.inner.header.as_ref().unwrap()
+    }
+
+    /// Returns the index of this member in the stream, starting at zero.
+    pub fn index(&self) -> u64 {
+        self.inner.member
+    }
+
+    /// Returns the offset of the first byte of this member's header in the
+    /// compressed stream.
+    pub fn start(&self) -> u64 {
+        self.inner.start
+    }
+
+    /// Returns the offset just past this member's trailer in the compressed
+    /// stream, once it has been read.
+    pub fn end(&self) -> Option<u64> {
+        self.inner.ended.map(|ended| ended.end)
+    }
+
+    /// Returns the size of this member's uncompressed data, once it has been
+    /// read.
+    pub fn size(&self) -> Option<u64> {
+        self.inner.ended.map(|ended| ended.size)
+    }
+
+    /// Returns the CRC-32 stored in this member's trailer, once it has been
+    /// read.
+    ///
+    /// It has been checked against the data unless
+    /// [`GzDecoderOptions::verify_checksums`] is off, and is `None` if a
+    /// truncated trailer was allowed.
+    ///
+    /// [`GzDecoderOptions::verify_checksums`]: ../struct.GzDecoderOptions.html#method.verify_checksums
+    pub fn crc(&self) -> Option<u32> {
+        self.inner.ended.and_then(|ended| ended.crc)
+    }
+}
+
+impl<'a, R: BufRead> Read for GzMember<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::gz::bufread::*;
@@ -758,7 +1278,7 @@ pub mod tests {
     // test function read_and_forget of Buffer
     fn buffer_read_and_forget() {
         // this is unused except for the buffering
-        let mut part = GzHeaderPartial::new();
+        let mut part = GzHeaderPartial::for_member(0, 0, GzHeaderLimits::default());
         // this is a reader which receives data afterwards
         let mut r = BlockingCursor::new();
         let data = vec![1, 2, 3];
@@ -1,9 +1,13 @@
+use std::error::Error;
 use std::ffi::CString;
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
 use std::time;
 
 use crate::bufreader::BufReader;
-use crate::Compression;
+use crate::par::write::{par_gz_encoder, ParGzEncoder};
+use crate::{Compress, CompressOptions, Compression, CompressionStrategy, Format, ParOptions};
 
 pub static FHCRC: u8 = 1 << 1;
 pub static FEXTRA: u8 = 1 << 2;
@@ -82,6 +86,347 @@ impl GzHeader {
     }
 }
 
+/// Limits on the sizes of the variable-length fields of a gzip header.
+///
+/// The gzip decoders keep the `extra`, `filename` and `comment` fields of a
+/// header in memory while parsing it, so without a bound a malicious header
+/// could make them buffer any amount of data. A header with a field longer
+/// than its limit is rejected with a [`GzError`].
+///
+/// By default the `extra` field may be as long as the format allows, 65535
+/// bytes, `filename` up to 4 KiB and `comment` up to 64 KiB.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzBuilder, GzError, GzErrorKind, GzHeaderLimits};
+/// use flate2::read::GzDecoder;
+///
+/// let mut e = GzBuilder::new()
+///     .filename("a very long name")
+///     .write(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World").unwrap();
+/// let bytes = e.finish().unwrap();
+///
+/// let limits = GzHeaderLimits::new().max_filename(8);
+/// let mut d = GzDecoder::new_with_header_limits(&bytes[..], limits);
+/// let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+/// let err = err.get_ref().unwrap().downcast_ref::<GzError>().unwrap();
+/// assert_eq!(err.kind(), GzErrorKind::FilenameTooLong);
+/// ```
+///
+/// [`GzError`]: struct.GzError.html
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct GzHeaderLimits {
+    pub(crate) extra: usize,
+    pub(crate) filename: usize,
+    pub(crate) comment: usize,
+}
+
+impl Default for GzHeaderLimits {
+    fn default() -> GzHeaderLimits {
+        GzHeaderLimits {
+            extra: u16::MAX as usize,
+            filename: 4 * 1024,
+            comment: 64 * 1024,
+        }
+    }
+}
+
+impl GzHeaderLimits {
+    /// Creates a new set of limits with the default values.
+    pub fn new() -> GzHeaderLimits {
+        GzHeaderLimits::default()
+    }
+
+    /// Limits the length of the `extra` field.
+    pub fn max_extra(mut self, bytes: usize) -> GzHeaderLimits {
+        self.extra = bytes;
+        self
+    }
+
+    /// Limits the length of the `filename` field, not counting its
+    /// terminating zero.
+    pub fn max_filename(mut self, bytes: usize) -> GzHeaderLimits {
+        self.filename = bytes;
+        self
+    }
+
+    /// Limits the length of the `comment` field, not counting its
+    /// terminating zero.
+    pub fn max_comment(mut self, bytes: usize) -> GzHeaderLimits {
+        self.comment = bytes;
+        self
+    }
+}
+
+/// Options for the gzip decoders, most of which make them more lenient
+/// towards malformed input.
+///
+/// By default the decoders are strict: they verify the CRC-32 and size in the
+/// trailer of every member and fail on a truncated trailer or on data which
+/// follows the last member but isn't a valid member itself.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzDecoderOptions};
+/// use flate2::read::MultiGzDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World").unwrap();
+/// let mut bytes = e.finish().unwrap();
+/// // Padding as written by tar.
+/// bytes.extend_from_slice(&[0; 512]);
+///
+/// let options = GzDecoderOptions::new().ignore_trailing_garbage(true);
+/// let mut d = MultiGzDecoder::new_with_options(&bytes[..], options);
+/// let mut s = String::new();
+/// d.read_to_string(&mut s).unwrap();
+/// assert_eq!(s, "Hello World");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct GzDecoderOptions {
+    pub(crate) header_limits: GzHeaderLimits,
+    pub(crate) verify_checksums: bool,
+    pub(crate) allow_truncated_trailer: bool,
+    pub(crate) ignore_trailing_garbage: bool,
+}
+
+impl Default for GzDecoderOptions {
+    fn default() -> GzDecoderOptions {
+        GzDecoderOptions {
+            header_limits: GzHeaderLimits::default(),
+            verify_checksums: true,
+            allow_truncated_trailer: false,
+            ignore_trailing_garbage: false,
+        }
+    }
+}
+
+impl GzDecoderOptions {
+    /// Creates a new set of options with the strict defaults.
+    pub fn new() -> GzDecoderOptions {
+        GzDecoderOptions::default()
+    }
+
+    /// Configures the limits on the fields of each member's header, see
+    /// [`GzHeaderLimits`].
+    ///
+    /// [`GzHeaderLimits`]: struct.GzHeaderLimits.html
+    pub fn header_limits(mut self, limits: GzHeaderLimits) -> GzDecoderOptions {
+        self.header_limits = limits;
+        self
+    }
+
+    /// Configures whether the CRC-32 and size in the trailer of each member
+    /// are checked against the decompressed data.
+    ///
+    /// When disabled the reading decoders also skip computing the CRC-32,
+    /// which saves some time. The checksum of the header, if it has one, is
+    /// still verified.
+    pub fn verify_checksums(mut self, verify: bool) -> GzDecoderOptions {
+        self.verify_checksums = verify;
+        self
+    }
+
+    /// Configures whether input which ends within the trailer of a member is
+    /// accepted.
+    ///
+    /// When enabled all data decompressed from the member is returned and the
+    /// stream ends without an error. The checksums of the member are not
+    /// verified in that case. Input which ends before the compressed data of
+    /// the member does is still an error.
+    pub fn allow_truncated_trailer(mut self, allow: bool) -> GzDecoderOptions {
+        self.allow_truncated_trailer = allow;
+        self
+    }
+
+    /// Configures whether data which follows the last member is ignored
+    /// rather than reported as an error.
+    ///
+    /// Archivers like tar pad gzip streams with zeros and some appliances
+    /// append other junk. With this enabled, anything after a member which
+    /// doesn't start with the gzip magic bytes ends the stream, as does input
+    /// which ends before a following header is complete.
+    pub fn ignore_trailing_garbage(mut self, ignore: bool) -> GzDecoderOptions {
+        self.ignore_trailing_garbage = ignore;
+        self
+    }
+}
+
+/// An error in the framing of a gzip member, found by one of the gzip
+/// decoders.
+///
+/// The decoders return this as the inner error of an [`io::Error`] of kind
+/// `InvalidInput`, where it can be retrieved with [`io::Error::get_ref`] and
+/// `downcast_ref`. Corrupt compressed data in the body of a member is reported
+/// with a [`DecompressError`] instead.
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+/// [`io::Error::get_ref`]: https://doc.rust-lang.org/std/io/struct.Error.html#method.get_ref
+/// [`DecompressError`]: ../struct.DecompressError.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzError, GzErrorKind};
+/// use flate2::read::GzDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World").unwrap();
+/// let mut bytes = e.finish().unwrap();
+/// let len = bytes.len();
+/// bytes[len - 8] ^= 1;
+///
+/// let err = GzDecoder::new(&bytes[..]).read_to_end(&mut Vec::new()).unwrap_err();
+/// let err = err.get_ref().unwrap().downcast_ref::<GzError>().unwrap();
+/// assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+/// assert_eq!(err.member(), 0);
+/// assert_eq!(err.offset(), len as u64 - 8);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GzError {
+    kind: GzErrorKind,
+    member: u64,
+    offset: u64,
+}
+
+/// Which check of a gzip member failed, as returned by [`GzError::kind`].
+///
+/// Stored values are the ones found in the stream, and computed values the
+/// ones the decoder arrived at.
+///
+/// [`GzError::kind`]: struct.GzError.html#method.kind
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum GzErrorKind {
+    /// The member doesn't start with the gzip magic bytes, but with these.
+    InvalidMagic([u8; 2]),
+
+    /// The header names this compression method rather than deflate.
+    UnknownMethod(u8),
+
+    /// The `extra` field of the header is longer than allowed by the
+    /// [`GzHeaderLimits`] of the decoder.
+    ///
+    /// [`GzHeaderLimits`]: struct.GzHeaderLimits.html
+    ExtraTooLong,
+
+    /// The `filename` field of the header is longer than allowed by the
+    /// [`GzHeaderLimits`] of the decoder.
+    ///
+    /// [`GzHeaderLimits`]: struct.GzHeaderLimits.html
+    FilenameTooLong,
+
+    /// The `comment` field of the header is longer than allowed by the
+    /// [`GzHeaderLimits`] of the decoder.
+    ///
+    /// [`GzHeaderLimits`]: struct.GzHeaderLimits.html
+    CommentTooLong,
+
+    /// The CRC-16 of the header doesn't match the header.
+    HeaderCrcMismatch {
+        /// The CRC-16 stored in the header.
+        stored: u16,
+        /// The CRC-16 of the header read.
+        computed: u16,
+    },
+
+    /// The CRC-32 in the trailer doesn't match the decompressed data.
+    CrcMismatch {
+        /// The CRC-32 stored in the trailer.
+        stored: u32,
+        /// The CRC-32 of the decompressed data.
+        computed: u32,
+    },
+
+    /// The size in the trailer doesn't match the length of the decompressed
+    /// data, modulo 2^32.
+    SizeMismatch {
+        /// The size stored in the trailer.
+        stored: u32,
+        /// The length of the decompressed data, modulo 2^32.
+        computed: u32,
+    },
+}
+
+impl GzError {
+    pub(crate) fn new(kind: GzErrorKind, member: u64, offset: u64) -> GzError {
+        GzError {
+            kind,
+            member,
+            offset,
+        }
+    }
+
+    /// Returns which check failed.
+    pub fn kind(&self) -> GzErrorKind {
+        self.kind
+    }
+
+    /// Returns the index of the member the error was found in, starting at 0
+    /// for the first member of the stream.
+    pub fn member(&self) -> u64 {
+        self.member
+    }
+
+    /// Returns the offset in the compressed stream of the field which failed
+    /// the check, counted from the start of the first member.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl fmt::Display for GzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            GzErrorKind::InvalidMagic(magic) => write!(
+                f,
+                "invalid gzip header: magic bytes {:02x} {:02x}",
+                magic[0], magic[1]
+            )?,
+            GzErrorKind::UnknownMethod(method) => write!(
+                f,
+                "invalid gzip header: unknown compression method {}",
+                method
+            )?,
+            GzErrorKind::ExtraTooLong => f.write_str("gzip header field too long: extra")?,
+            GzErrorKind::FilenameTooLong => f.write_str("gzip header field too long: filename")?,
+            GzErrorKind::CommentTooLong => f.write_str("gzip header field too long: comment")?,
+            GzErrorKind::HeaderCrcMismatch { stored, computed } => write!(
+                f,
+                "corrupt gzip header: stored CRC {:04x} but computed {:04x}",
+                stored, computed
+            )?,
+            GzErrorKind::CrcMismatch { stored, computed } => write!(
+                f,
+                "corrupt gzip stream: stored CRC {:08x} but computed {:08x}",
+                stored, computed
+            )?,
+            GzErrorKind::SizeMismatch { stored, computed } => write!(
+                f,
+                "corrupt gzip stream: stored size {} but computed {}",
+                stored, computed
+            )?,
+        }
+        write!(f, " in member {} at offset {}", self.member, self.offset)
+    }
+}
+
+impl Error for GzError {}
+
+impl From<GzError> for io::Error {
+    fn from(err: GzError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
 /// A builder structure to create a new gzip Encoder.
 ///
 /// This structure controls header configuration options such as the filename.
@@ -115,6 +460,7 @@ pub struct GzBuilder {
     comment: Option<CString>,
     operating_system: Option<u8>,
     mtime: u32,
+    strategy: CompressionStrategy,
 }
 
 impl Default for GzBuilder {
@@ -132,6 +478,7 @@ impl GzBuilder {
             comment: None,
             operating_system: None,
             mtime: 0,
+            strategy: CompressionStrategy::Default,
         }
     }
 
@@ -147,6 +494,17 @@ impl GzBuilder {
         self
     }
 
+    /// Configure the compression strategy used by the encoder.
+    ///
+    /// This does not affect the gzip header, only the way the deflate stream
+    /// inside of it is produced. See [`CompressionStrategy`] for details.
+    ///
+    /// [`CompressionStrategy`]: ../enum.CompressionStrategy.html
+    pub fn strategy(mut self, strategy: CompressionStrategy) -> GzBuilder {
+        self.strategy = strategy;
+        self
+    }
+
     /// Configure the `extra` field in the gzip header.
     pub fn extra<T: Into<Vec<u8>>>(mut self, extra: T) -> GzBuilder {
         self.extra = Some(extra.into());
@@ -177,36 +535,129 @@ impl GzBuilder {
     ///
     /// The data written to the returned encoder will be compressed and then
     /// written out to the supplied parameter `w`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor can't be set up for `lvl`, see [`try_write`].
+    ///
+    /// [`try_write`]: #method.try_write
     pub fn write<W: Write>(self, w: W, lvl: Compression) -> write::GzEncoder<W> {
-        write::gz_encoder(self.into_header(lvl), w, lvl)
+        self.try_write(w, lvl).unwrap()
+    }
+
+    /// Consume this builder, creating a writer encoder in the process, like
+    /// [`write`] but returning an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `lvl`, for example because
+    /// the level isn't supported by the backend in use.
+    ///
+    /// [`write`]: #method.write
+    pub fn try_write<W: Write>(self, w: W, lvl: Compression) -> io::Result<write::GzEncoder<W>> {
+        let compress = self.compress(lvl)?;
+        Ok(write::gz_encoder(self.into_header(lvl), w, compress))
+    }
+
+    /// Consume this builder, creating a writer encoder which compresses on a
+    /// pool of threads, see [`ParGzEncoder`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `lvl`, or the threads can't
+    /// be spawned.
+    ///
+    /// [`ParGzEncoder`]: write/struct.ParGzEncoder.html
+    pub fn par_write<W: Write>(
+        self,
+        w: W,
+        lvl: Compression,
+        options: ParOptions,
+    ) -> io::Result<ParGzEncoder<W>> {
+        let compress_options = CompressOptions::new(lvl)
+            .format(Format::Raw)
+            .strategy(self.strategy);
+        par_gz_encoder(self.into_header(lvl), w, compress_options, options)
     }
 
     /// Consume this builder, creating a reader encoder in the process.
     ///
     /// Data read from the returned encoder will be the compressed version of
     /// the data read from the given reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor can't be set up for `lvl`, see [`try_read`].
+    ///
+    /// [`try_read`]: #method.try_read
     pub fn read<R: Read>(self, r: R, lvl: Compression) -> read::GzEncoder<R> {
         read::gz_encoder(self.buf_read(BufReader::new(r), lvl))
     }
 
+    /// Consume this builder, creating a reader encoder in the process, like
+    /// [`read`] but returning an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `lvl`, for example because
+    /// the level isn't supported by the backend in use.
+    ///
+    /// [`read`]: #method.read
+    pub fn try_read<R: Read>(self, r: R, lvl: Compression) -> io::Result<read::GzEncoder<R>> {
+        self.try_buf_read(BufReader::new(r), lvl)
+            .map(read::gz_encoder)
+    }
+
     /// Consume this builder, creating a reader encoder in the process.
     ///
     /// Data read from the returned encoder will be the compressed version of
     /// the data read from the given reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor can't be set up for `lvl`, see
+    /// [`try_buf_read`].
+    ///
+    /// [`try_buf_read`]: #method.try_buf_read
     pub fn buf_read<R>(self, r: R, lvl: Compression) -> bufread::GzEncoder<R>
     where
         R: BufRead,
     {
-        bufread::gz_encoder(self.into_header(lvl), r, lvl)
+        self.try_buf_read(r, lvl).unwrap()
+    }
+
+    /// Consume this builder, creating a reader encoder in the process, like
+    /// [`buf_read`] but returning an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `lvl`, for example because
+    /// the level isn't supported by the backend in use.
+    ///
+    /// [`buf_read`]: #method.buf_read
+    pub fn try_buf_read<R>(self, r: R, lvl: Compression) -> io::Result<bufread::GzEncoder<R>>
+    where
+        R: BufRead,
+    {
+        let compress = self.compress(lvl)?;
+        Ok(bufread::gz_encoder(self.into_header(lvl), r, compress))
+    }
+
+    pub(crate) fn compress(&self, lvl: Compression) -> io::Result<Compress> {
+        let options = CompressOptions::new(lvl)
+            .format(Format::Raw)
+            .strategy(self.strategy);
+        Ok(Compress::new_with_options(options)?)
     }
 
-    fn into_header(self, lvl: Compression) -> Vec<u8> {
+    pub(crate) fn into_header(self, lvl: Compression) -> Vec<u8> {
         let GzBuilder {
             extra,
             filename,
             comment,
             operating_system,
             mtime,
+            strategy: _,
         } = self;
         let mut flg = 0;
         let mut header = vec![0u8; 10];
@@ -253,10 +704,30 @@ impl GzBuilder {
 mod tests {
     use std::io::prelude::*;
 
-    use super::{read, write, GzBuilder};
+    use super::{read, write, GzBuilder, GzError, GzErrorKind, FHCRC};
     use crate::Compression;
     use rand::{thread_rng, Rng};
 
+    #[test]
+    fn builder_try() {
+        let level = Compression::new(42);
+        assert!(GzBuilder::new().try_write(Vec::new(), level).is_err());
+        assert!(GzBuilder::new().try_read(&b""[..], level).is_err());
+        assert!(GzBuilder::new().try_buf_read(&b""[..], level).is_err());
+
+        let mut e = GzBuilder::new()
+            .filename("foo.txt")
+            .try_write(Vec::new(), Compression::fast())
+            .unwrap();
+        e.write_all(b"foo bar baz").unwrap();
+        let inner = e.finish().unwrap();
+        let mut d = read::GzDecoder::new(&inner[..]);
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "foo bar baz");
+        assert_eq!(d.header().unwrap().filename(), Some(&b"foo.txt"[..]));
+    }
+
     #[test]
     fn roundtrip() {
         let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
@@ -321,6 +792,28 @@ mod tests {
         assert_eq!(res, vec![0, 2, 4, 6]);
     }
 
+    #[test]
+    fn strategy() {
+        let v = crate::random_bytes().take(64 * 1024).collect::<Vec<_>>();
+        let mut e = GzBuilder::new()
+            .strategy(crate::CompressionStrategy::HuffmanOnly)
+            .write(Vec::new(), Compression::default());
+        e.write_all(&v).unwrap();
+        let inner = e.finish().unwrap();
+        let mut d = read::GzDecoder::new(&inner[..]);
+        let mut res = Vec::new();
+        d.read_to_end(&mut res).unwrap();
+        assert_eq!(res, v);
+
+        let e = GzBuilder::new()
+            .strategy(crate::CompressionStrategy::Rle)
+            .read(&v[..], Compression::default());
+        let mut d = read::GzDecoder::new(e);
+        let mut res = Vec::new();
+        d.read_to_end(&mut res).unwrap();
+        assert_eq!(res, v);
+    }
+
     #[test]
     fn keep_reading_after_end() {
         let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
@@ -354,6 +847,91 @@ mod tests {
         f.flush().unwrap();
     }
 
+    fn gz_error(err: std::io::Error) -> GzError {
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        err.get_ref()
+            .and_then(|err| err.downcast_ref::<GzError>())
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn trailer_errors() {
+        let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let member = e.finish().unwrap();
+        let len = member.len();
+        let mut bytes = member.repeat(2);
+        bytes[2 * len - 2] ^= 1;
+
+        let mut d = read::MultiGzDecoder::new(&bytes[..]);
+        let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+        assert_eq!(
+            err.kind(),
+            GzErrorKind::SizeMismatch {
+                stored: 11 | 1 << 16,
+                computed: 11
+            }
+        );
+        assert_eq!(err.member(), 1);
+        assert_eq!(err.offset(), 2 * len as u64 - 4);
+
+        bytes[len - 8] ^= 1;
+        let mut d = write::GzDecoder::new(Vec::new());
+        d.write_all(&bytes[..len]).unwrap();
+        let err = gz_error(d.finish().unwrap_err());
+        assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+        assert_eq!(err.member(), 0);
+        assert_eq!(err.offset(), len as u64 - 8);
+    }
+
+    #[test]
+    fn header_errors() {
+        let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let member = e.finish().unwrap();
+
+        let mut bytes = member.clone();
+        bytes.extend_from_slice(b"garbage data");
+        let mut d = read::MultiGzDecoder::new(&bytes[..]);
+        let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+        assert_eq!(err.kind(), GzErrorKind::InvalidMagic(*b"ga"));
+        assert_eq!(err.member(), 1);
+        assert_eq!(err.offset(), member.len() as u64);
+
+        let mut bytes = member.clone();
+        bytes[2] = 7;
+        let mut d = read::GzDecoder::new(&bytes[..]);
+        let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+        assert_eq!(err.kind(), GzErrorKind::UnknownMethod(7));
+        assert_eq!(err.offset(), 2);
+
+        // The same member with a header CRC, which covers the header before it.
+        let mut crc = crate::Crc::new();
+        let mut bytes = member[..10].to_vec();
+        bytes[3] |= FHCRC;
+        crc.update(&bytes);
+        bytes.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+        bytes.extend_from_slice(&member[10..]);
+        let mut decoded = Vec::new();
+        read::GzDecoder::new(&bytes[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"hello world");
+
+        bytes[10] ^= 1;
+        let mut d = read::GzDecoder::new(&bytes[..]);
+        let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+        assert_eq!(
+            err.kind(),
+            GzErrorKind::HeaderCrcMismatch {
+                stored: crc.sum() as u16 ^ 1,
+                computed: crc.sum() as u16
+            }
+        );
+        assert_eq!(err.offset(), 10);
+    }
+
     use crate::gz::bufread::tests::BlockingCursor;
     #[test]
     // test function read_and_forget of Buffer
@@ -382,4 +960,319 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn limits() {
+        use crate::{bufread, DecompressError, DecompressLimit, DecompressLimits};
+
+        fn limit(err: std::io::Error) -> Option<DecompressLimit> {
+            let err = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+            err.unwrap().limit()
+        }
+
+        let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&[0; 10_000]).unwrap();
+        let member = e.finish().unwrap();
+        let bytes = member.repeat(3);
+
+        // The output limit covers all members together.
+        let mut d = read::MultiGzDecoder::new(&bytes[..]);
+        d.set_limits(DecompressLimits::new().max_output(25_000));
+        let mut out = Vec::new();
+        let err = d.read_to_end(&mut out).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+        assert_eq!(out, vec![0; 25_000]);
+
+        let mut d = bufread::MultiGzDecoder::new(&bytes[..]);
+        d.set_limits(DecompressLimits::new().max_members(2));
+        let mut out = Vec::new();
+        let err = d.read_to_end(&mut out).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Members));
+        assert_eq!(out.len(), 20_000);
+
+        let mut d = read::MultiGzDecoder::new(&bytes[..]);
+        d.set_limits(DecompressLimits::new().max_members(3));
+        d.read_to_end(&mut Vec::new()).unwrap();
+
+        let mut d = read::GzDecoder::new(&bytes[..]);
+        d.set_limits(DecompressLimits::new().max_ratio(5));
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Ratio));
+
+        let mut w = write::GzDecoder::new(Vec::new());
+        w.set_limits(DecompressLimits::new().max_output(5_000));
+        let err = w.write_all(&member).and(w.try_finish()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+        assert!(w.get_ref().len() <= 5_000);
+
+        let mut d = crate::read::AutoDecoder::new(&member[..]);
+        d.set_limits(DecompressLimits::new().max_output(5_000));
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+
+        // Limits set after detection reach the decoder picked.
+        let mut d = crate::read::AutoDecoder::new(&member[..]);
+        let mut buf = [0; 100];
+        d.read_exact(&mut buf).unwrap();
+        d.set_limits(DecompressLimits::new().max_output(5_000));
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit(err), Some(DecompressLimit::Output));
+    }
+
+    #[test]
+    fn header_limits() {
+        use super::GzHeaderLimits;
+
+        let encode = |builder: GzBuilder| {
+            let mut e = builder.write(Vec::new(), Compression::default());
+            e.write_all(b"hello world").unwrap();
+            e.finish().unwrap()
+        };
+        let name = encode(GzBuilder::new().filename(vec![b'a'; 100]));
+        let comment = encode(GzBuilder::new().extra(vec![0; 20]).comment(vec![b'a'; 100]));
+        let extra = encode(GzBuilder::new().extra(vec![0; 100]));
+
+        let limits = GzHeaderLimits::new()
+            .max_extra(50)
+            .max_filename(50)
+            .max_comment(50);
+        let cases = [
+            (&name, GzErrorKind::FilenameTooLong, 10),
+            (&comment, GzErrorKind::CommentTooLong, 32),
+            (&extra, GzErrorKind::ExtraTooLong, 10),
+        ];
+        for &(bytes, kind, offset) in cases.iter() {
+            let mut d = read::GzDecoder::new(&bytes[..]);
+            d.read_to_end(&mut Vec::new()).unwrap();
+
+            let mut d = read::GzDecoder::new_with_header_limits(&bytes[..], limits);
+            let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+            assert_eq!((err.kind(), err.member(), err.offset()), (kind, 0, offset));
+
+            let mut w = write::GzDecoder::new_with_header_limits(Vec::new(), limits);
+            let err = gz_error(w.write_all(bytes).unwrap_err());
+            assert_eq!(err.kind(), kind);
+        }
+
+        // Later members are held to the same limits.
+        let plain = encode(GzBuilder::new());
+        let bytes = [&plain[..], &name[..]].concat();
+        let mut d = read::MultiGzDecoder::new_with_header_limits(&bytes[..], limits);
+        let err = gz_error(d.read_to_end(&mut Vec::new()).unwrap_err());
+        assert_eq!(err.kind(), GzErrorKind::FilenameTooLong);
+        assert_eq!((err.member(), err.offset()), (1, plain.len() as u64 + 10));
+
+        // The defaults reject a filename which never ends.
+        let mut bytes = name.clone();
+        bytes.truncate(10);
+        bytes.extend(vec![b'a'; 1 << 20]);
+        let err = gz_error(
+            read::GzDecoder::new(&bytes[..])
+                .read_to_end(&mut Vec::new())
+                .unwrap_err(),
+        );
+        assert_eq!(err.kind(), GzErrorKind::FilenameTooLong);
+    }
+
+    #[test]
+    fn decoder_options() {
+        use super::GzDecoderOptions;
+
+        let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let member = e.finish().unwrap();
+        let len = member.len();
+
+        let read_all = |bytes: &[u8], options| {
+            let mut out = Vec::new();
+            read::MultiGzDecoder::new_with_options(bytes, options)
+                .read_to_end(&mut out)
+                .map(|_| out)
+        };
+        let write_all = |bytes: &[u8], options| {
+            let mut w = write::GzDecoder::new_with_options(Vec::new(), options);
+            w.write_all(bytes).and_then(|_| w.finish())
+        };
+        let strict = GzDecoderOptions::new();
+
+        let mut corrupt = member.clone();
+        corrupt[len - 8] ^= 1;
+        let options = GzDecoderOptions::new().verify_checksums(false);
+        assert!(read_all(&corrupt, strict).is_err());
+        assert_eq!(read_all(&corrupt, options).unwrap(), b"hello world");
+        assert!(write_all(&corrupt, strict).is_err());
+        assert_eq!(write_all(&corrupt, options).unwrap(), b"hello world");
+
+        let truncated = &member[..len - 3];
+        let options = GzDecoderOptions::new().allow_truncated_trailer(true);
+        let err = read_all(truncated, strict).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_all(truncated, options).unwrap(), b"hello world");
+        let mut d = read::GzDecoder::new_with_options(truncated, options);
+        d.read_to_end(&mut Vec::new()).unwrap();
+        assert!(write_all(truncated, strict).is_err());
+        assert_eq!(write_all(truncated, options).unwrap(), b"hello world");
+        // The body itself must still be complete.
+        assert!(read_all(&member[..len - 10], options).is_err());
+
+        let options = GzDecoderOptions::new().ignore_trailing_garbage(true);
+        for garbage in [&[0; 512][..], b"garbage data", &member[..5]].iter() {
+            let bytes = [&member[..], &member[..], garbage].concat();
+            assert!(read_all(&bytes, strict).is_err());
+            assert_eq!(
+                read_all(&bytes, options).unwrap(),
+                b"hello worldhello world"
+            );
+
+            let bytes = [&member[..], garbage].concat();
+            assert!(write_all(&bytes, strict).is_err());
+            assert_eq!(write_all(&bytes, options).unwrap(), b"hello world");
+        }
+        // A corrupt header after the magic bytes is still an error.
+        let mut bytes = member.repeat(2);
+        bytes[len + 2] = 7;
+        assert!(read_all(&bytes, options).is_err());
+    }
+
+    #[test]
+    fn write_multi_decoder() {
+        use super::GzDecoderOptions;
+        use crate::{DecompressError, DecompressLimit, DecompressLimits};
+
+        let mut bytes = Vec::new();
+        let mut starts = Vec::new();
+        for name in ["a", "b", "c"].iter() {
+            starts.push(bytes.len() as u64);
+            let mut e = GzBuilder::new()
+                .filename(*name)
+                .write(Vec::new(), Compression::default());
+            e.write_all(name.repeat(1000).as_bytes()).unwrap();
+            bytes.extend(e.finish().unwrap());
+        }
+        let expected = ["a", "b", "c"].iter().map(|n| n.repeat(1000));
+        let expected = expected.collect::<String>().into_bytes();
+
+        for &chunk in [1, 3, 7, 100, bytes.len()].iter() {
+            let mut d = write::MultiGzDecoder::new(Vec::new());
+            let mut names = Vec::new();
+            for mut part in bytes.chunks(chunk) {
+                while !part.is_empty() {
+                    let n = d.write(part).unwrap();
+                    part = &part[n..];
+                    if let Some(header) = d.header() {
+                        let name = header.filename().unwrap().to_vec();
+                        if names.last() != Some(&name) {
+                            assert_eq!(d.member(), names.len() as u64);
+                            names.push(name);
+                        }
+                    }
+                }
+            }
+            assert_eq!(names, [b"a", b"b", b"c"]);
+            assert_eq!(d.finish().unwrap(), expected);
+        }
+
+        // Errors report the member they occur in.
+        let mut corrupt = bytes.clone();
+        let end = starts[2] as usize;
+        corrupt[end - 8] ^= 1;
+        let mut d = write::MultiGzDecoder::new(Vec::new());
+        let err = gz_error(d.write_all(&corrupt).and(d.try_finish()).unwrap_err());
+        assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+        assert_eq!(err.member(), 1);
+        assert_eq!(err.offset(), end as u64 - 8);
+
+        let padded = [&bytes[..], &[0; 512][..]].concat();
+        let mut d = write::MultiGzDecoder::new(Vec::new());
+        assert!(d.write_all(&padded).and(d.try_finish()).is_err());
+        let options = GzDecoderOptions::new().ignore_trailing_garbage(true);
+        let mut d = write::MultiGzDecoder::new_with_options(Vec::new(), options);
+        d.write_all(&padded).unwrap();
+        assert_eq!(d.finish().unwrap(), expected);
+
+        let mut d = write::MultiGzDecoder::new(Vec::new());
+        d.write_all(&bytes[..starts[1] as usize + 5]).unwrap();
+        assert!(d.try_finish().is_err());
+
+        let mut d = write::MultiGzDecoder::new(Vec::new());
+        d.set_limits(DecompressLimits::new().max_members(2));
+        let err = d.write_all(&bytes).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+        assert_eq!(err.unwrap().limit(), Some(DecompressLimit::Members));
+        assert_eq!(d.get_ref().len(), 2000);
+    }
+
+    #[test]
+    fn members() {
+        use super::GzDecoderOptions;
+        use crate::bufread::GzMembers;
+        use crate::Crc;
+
+        let mut bytes = Vec::new();
+        let mut starts = Vec::new();
+        let names = ["a", "b", "c"];
+        for (i, name) in names.iter().enumerate() {
+            starts.push(bytes.len() as u64);
+            let mut e = GzBuilder::new()
+                .filename(*name)
+                .write(Vec::new(), Compression::default());
+            e.write_all(name.repeat(1000 * i).as_bytes()).unwrap();
+            bytes.extend(e.finish().unwrap());
+        }
+        starts.push(bytes.len() as u64);
+
+        let mut members = GzMembers::new(&bytes[..]);
+        let mut n = 0;
+        while let Some(mut member) = members.next_member().unwrap() {
+            let i = member.index() as usize;
+            assert_eq!(i, n);
+            assert_eq!(member.header().filename(), Some(names[i].as_bytes()));
+            assert_eq!(member.start(), starts[i]);
+            assert_eq!(member.end(), None);
+            let mut data = Vec::new();
+            member.read_to_end(&mut data).unwrap();
+            assert_eq!(data, names[i].repeat(1000 * i).as_bytes());
+            let mut crc = Crc::new();
+            crc.update(&data);
+            assert_eq!(member.end(), Some(starts[i + 1]));
+            assert_eq!(member.size(), Some(data.len() as u64));
+            assert_eq!(member.crc(), Some(crc.sum()));
+            n += 1;
+        }
+        assert_eq!(n, 3);
+        assert!(members.next_member().unwrap().is_none());
+
+        // Members which aren't read are skipped, and their trailers checked.
+        let mut members = GzMembers::new(&bytes[..]);
+        members.next_member().unwrap().unwrap();
+        let member = members.next_member().unwrap().unwrap();
+        assert_eq!(member.header().filename(), Some(&b"b"[..]));
+        assert_eq!(member.start(), starts[1]);
+
+        let mut corrupt = bytes.clone();
+        corrupt[starts[2] as usize - 8] ^= 1;
+        let mut members = GzMembers::new(&corrupt[..]);
+        members.next_member().unwrap().unwrap();
+        members.next_member().unwrap().unwrap();
+        let err = gz_error(members.next_member().err().unwrap());
+        assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+        assert_eq!(err.member(), 1);
+
+        assert!(GzMembers::new(&[][..]).next_member().unwrap().is_none());
+        assert!(GzMembers::new(&bytes[..5]).next_member().is_err());
+
+        let padded = [&bytes[..], &[0; 100][..]].concat();
+        let mut members = GzMembers::new(&padded[..]);
+        for _ in 0..3 {
+            members.next_member().unwrap().unwrap();
+        }
+        assert!(members.next_member().is_err());
+        let options = GzDecoderOptions::new().ignore_trailing_garbage(true);
+        let mut members = GzMembers::new_with_options(&padded[..], options);
+        let mut n = 0;
+        while members.next_member().unwrap().is_some() {
+            n += 1;
+        }
+        assert_eq!(n, 3);
+    }
 }
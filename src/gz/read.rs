@@ -2,9 +2,9 @@ use std::io;
 use std::io::prelude::*;
 
 use super::bufread;
-use super::{GzBuilder, GzHeader};
+use super::{GzBuilder, GzDecoderOptions, GzHeader, GzHeaderLimits};
 use crate::bufreader::BufReader;
-use crate::Compression;
+use crate::{Compression, DecompressLimits};
 
 /// A gzip streaming encoder
 ///
@@ -52,6 +52,15 @@ impl<R: Read> GzEncoder<R> {
     pub fn new(r: R, level: Compression) -> GzEncoder<R> {
         GzBuilder::new().read(r, level)
     }
+
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: Compression) -> io::Result<GzEncoder<R>> {
+        GzBuilder::new().try_read(r, level)
+    }
 }
 
 impl<R> GzEncoder<R> {
@@ -137,6 +146,22 @@ impl<R: Read> GzDecoder<R> {
             inner: bufread::GzDecoder::new(BufReader::new(r)),
         }
     }
+
+    /// Creates a new decoder from the given reader, immediately parsing the
+    /// gzip header, which must stay within the given limits.
+    pub fn new_with_header_limits(r: R, header_limits: GzHeaderLimits) -> GzDecoder<R> {
+        GzDecoder {
+            inner: bufread::GzDecoder::new_with_header_limits(BufReader::new(r), header_limits),
+        }
+    }
+
+    /// Creates a new decoder from the given reader with the given options,
+    /// immediately parsing the gzip header.
+    pub fn new_with_options(r: R, options: GzDecoderOptions) -> GzDecoder<R> {
+        GzDecoder {
+            inner: bufread::GzDecoder::new_with_options(BufReader::new(r), options),
+        }
+    }
 }
 
 impl<R> GzDecoder<R> {
@@ -145,6 +170,14 @@ impl<R> GzDecoder<R> {
         self.inner.header()
     }
 
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
@@ -187,11 +220,13 @@ impl<R: Read + Write> Write for GzDecoder<R> {
 /// gzip members to be joined in a single stream.  `MultiGzDecoder` will
 /// decode all consecutive members while `GzDecoder` will only decompress the
 /// first gzip member. The multistream format is commonly used in bioinformatics,
-/// for example when using the BGZF compressed data.
+/// for example when using the BGZF compressed data, which [`BgzfReader`] can
+/// also seek in.
 ///
 /// This structure exposes a [`Read`] interface that will consume all gzip members
 /// from the underlying reader and emit uncompressed data.
 ///
+/// [`BgzfReader`]: ../bgzf/struct.BgzfReader.html
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 ///
 /// # Examples
@@ -234,6 +269,26 @@ impl<R: Read> MultiGzDecoder<R> {
             inner: bufread::MultiGzDecoder::new(BufReader::new(r)),
         }
     }
+
+    /// Creates a new decoder from the given reader, immediately parsing the
+    /// (first) gzip header. The headers of all members must stay within the
+    /// given limits.
+    pub fn new_with_header_limits(r: R, header_limits: GzHeaderLimits) -> MultiGzDecoder<R> {
+        MultiGzDecoder {
+            inner: bufread::MultiGzDecoder::new_with_header_limits(
+                BufReader::new(r),
+                header_limits,
+            ),
+        }
+    }
+
+    /// Creates a new decoder from the given reader with the given options,
+    /// immediately parsing the (first) gzip header.
+    pub fn new_with_options(r: R, options: GzDecoderOptions) -> MultiGzDecoder<R> {
+        MultiGzDecoder {
+            inner: bufread::MultiGzDecoder::new_with_options(BufReader::new(r), options),
+        }
+    }
 }
 
 impl<R> MultiGzDecoder<R> {
@@ -242,6 +297,14 @@ impl<R> MultiGzDecoder<R> {
         self.inner.header()
     }
 
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
@@ -1,12 +1,13 @@
 use std::cmp;
+use std::convert::TryInto;
 use std::io;
 use std::io::prelude::*;
 
-use super::bufread::{corrupt, read_gz_header};
-use super::{GzBuilder, GzHeader};
+use super::bufread::{check_trailer, is_garbage, read_gz_header};
+use super::{GzBuilder, GzDecoderOptions, GzHeader, GzHeaderLimits};
 use crate::crc::{Crc, CrcWriter};
 use crate::zio;
-use crate::{Compress, Compression, Decompress, Status};
+use crate::{Compress, Compression, Decompress, DecompressLimits, Status};
 
 /// A gzip streaming encoder
 ///
@@ -38,9 +39,9 @@ pub struct GzEncoder<W: Write> {
     header: Vec<u8>,
 }
 
-pub fn gz_encoder<W: Write>(header: Vec<u8>, w: W, lvl: Compression) -> GzEncoder<W> {
+pub fn gz_encoder<W: Write>(header: Vec<u8>, w: W, compress: Compress) -> GzEncoder<W> {
     GzEncoder {
-        inner: zio::Writer::new(w, Compress::new(lvl, false)),
+        inner: zio::Writer::new(w, compress),
         crc: Crc::new(),
         header,
         crc_bytes_written: 0,
@@ -59,6 +60,15 @@ impl<W: Write> GzEncoder<W> {
         GzBuilder::new().write(w, level)
     }
 
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(w: W, level: Compression) -> io::Result<GzEncoder<W>> {
+        GzBuilder::new().try_write(w, level)
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -205,6 +215,14 @@ pub struct GzDecoder<W: Write> {
     crc_bytes: Vec<u8>,
     header: Option<GzHeader>,
     header_buf: Vec<u8>,
+    header_len: u64,
+    options: GzDecoderOptions,
+    multi: bool,
+    // The index of the current member and the offset it starts at.
+    member: u64,
+    start: u64,
+    // Set once data after the last member has been found to be garbage.
+    garbage: bool,
 }
 
 const CRC_BYTES_LEN: usize = 8;
@@ -215,19 +233,59 @@ impl<W: Write> GzDecoder<W> {
     /// When this encoder is dropped or unwrapped the final pieces of data will
     /// be flushed.
     pub fn new(w: W) -> GzDecoder<W> {
+        GzDecoder::new_with_options(w, GzDecoderOptions::default())
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream,
+    /// rejecting a gzip header which exceeds the given limits.
+    ///
+    /// The header is buffered until it is complete, so the limits also bound
+    /// the memory used for that.
+    pub fn new_with_header_limits(w: W, header_limits: GzHeaderLimits) -> GzDecoder<W> {
+        GzDecoder::new_with_options(w, GzDecoderOptions::new().header_limits(header_limits))
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream
+    /// with the given options.
+    ///
+    /// Data written after the trailer is ignored if
+    /// [`GzDecoderOptions::ignore_trailing_garbage`] is set, and is otherwise
+    /// not accepted.
+    ///
+    /// [`GzDecoderOptions::ignore_trailing_garbage`]: ../struct.GzDecoderOptions.html#method.ignore_trailing_garbage
+    pub fn new_with_options(w: W, options: GzDecoderOptions) -> GzDecoder<W> {
         GzDecoder {
             inner: zio::Writer::new(CrcWriter::new(w), Decompress::new(false)),
             crc_bytes: Vec::with_capacity(CRC_BYTES_LEN),
             header: None,
             header_buf: Vec::new(),
+            header_len: 0,
+            options,
+            multi: false,
+            member: 0,
+            start: 0,
+            garbage: false,
         }
     }
 
+    fn multi(mut self, flag: bool) -> GzDecoder<W> {
+        self.multi = flag;
+        self
+    }
+
     /// Returns the header associated with this stream.
     pub fn header(&self) -> Option<&GzHeader> {
         self.header.as_ref()
     }
 
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.limits = limits;
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref().get_ref()
@@ -282,26 +340,55 @@ impl<W: Write> GzDecoder<W> {
     }
 
     fn finish_and_check_crc(&mut self) -> io::Result<()> {
+        if self.garbage {
+            return Ok(());
+        }
+        if self.header.is_none() && self.member > 0 {
+            // Only part of a header follows the previous member.
+            if self.options.ignore_trailing_garbage {
+                return Ok(());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "gzip header is truncated",
+            ));
+        }
         self.inner.finish()?;
 
-        if self.crc_bytes.len() != 8 {
-            return Err(corrupt());
+        let trailer = match self.crc_bytes[..].try_into() {
+            Ok(trailer) => trailer,
+            // Only the trailer may be missing, the body itself has to be
+            // complete.
+            Err(_) if self.options.allow_truncated_trailer && self.inner.data.is_finished() => {
+                return Ok(())
+            }
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "gzip trailer is truncated",
+                ))
+            }
+        };
+        if !self.options.verify_checksums {
+            return Ok(());
         }
+        let offset = self.start + self.header_len + self.inner.data.total_in();
+        check_trailer(trailer, self.inner.get_ref().crc(), self.member, offset)
+    }
 
-        let crc = ((self.crc_bytes[0] as u32) << 0)
-            | ((self.crc_bytes[1] as u32) << 8)
-            | ((self.crc_bytes[2] as u32) << 16)
-            | ((self.crc_bytes[3] as u32) << 24);
-        let amt = ((self.crc_bytes[4] as u32) << 0)
-            | ((self.crc_bytes[5] as u32) << 8)
-            | ((self.crc_bytes[6] as u32) << 16)
-            | ((self.crc_bytes[7] as u32) << 24);
-        if crc != self.inner.get_ref().crc().sum() as u32 {
-            return Err(corrupt());
-        }
-        if amt != self.inner.get_ref().crc().amount() {
-            return Err(corrupt());
-        }
+    /// Checks the trailer of the current member and prepares for the next
+    /// one.
+    fn next_member(&mut self) -> io::Result<()> {
+        self.finish_and_check_crc()?;
+        self.start += self.header_len + self.inner.data.total_in() + CRC_BYTES_LEN as u64;
+        self.inner.base_in = self.start;
+        self.inner.base_out += self.inner.data.total_out();
+        self.inner.data.reset(false);
+        self.inner.get_mut().reset();
+        self.crc_bytes.clear();
+        self.header = None;
+        self.header_len = 0;
+        self.member += 1;
         Ok(())
     }
 }
@@ -321,6 +408,12 @@ impl<T: Read> Read for Counter<T> {
 
 impl<W: Write> Write for GzDecoder<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.garbage {
+            return Ok(buf.len());
+        }
+        if self.multi && self.crc_bytes.len() == CRC_BYTES_LEN && !buf.is_empty() {
+            self.next_member()?;
+        }
         if self.header.is_none() {
             // trying to avoid buffer usage
             let (res, pos) = {
@@ -328,7 +421,12 @@ impl<W: Write> Write for GzDecoder<W> {
                     inner: self.header_buf.chain(buf),
                     pos: 0,
                 };
-                let res = read_gz_header(&mut counter);
+                let res = read_gz_header(
+                    &mut counter,
+                    self.member,
+                    self.start,
+                    self.options.header_limits,
+                );
                 (res, counter.pos)
             };
 
@@ -338,12 +436,20 @@ impl<W: Write> Write for GzDecoder<W> {
                         // not enough data for header, save to the buffer
                         self.header_buf.extend(buf);
                         Ok(buf.len())
+                    } else if self.member > 0 && is_garbage(&err, &self.options) {
+                        self.garbage = true;
+                        self.header_buf = Vec::new();
+                        Ok(buf.len())
                     } else {
                         Err(err)
                     }
                 }
                 Ok(header) => {
+                    self.inner
+                        .limits
+                        .check_members(self.member + 1, self.start)?;
                     self.header = Some(header);
+                    self.header_len = pos as u64;
                     let pos = pos - self.header_buf.len();
                     self.header_buf.truncate(0);
                     Ok(pos)
@@ -358,6 +464,12 @@ impl<W: Write> Write for GzDecoder<W> {
                 self.crc_bytes.extend(&buf[n..n + crc_bytes]);
                 return Ok(n + crc_bytes);
             }
+            if status == Status::StreamEnd
+                && self.crc_bytes.len() == CRC_BYTES_LEN
+                && self.options.ignore_trailing_garbage
+            {
+                return Ok(buf.len());
+            }
             Ok(n)
         }
     }
@@ -373,6 +485,144 @@ impl<W: Read + Write> Read for GzDecoder<W> {
     }
 }
 
+/// A gzip streaming decoder that decodes all members of a multistream
+///
+/// This structure exposes a [`Write`] interface that will decode every gzip
+/// member written to it and emit the uncompressed data to the underlying
+/// writer `W`. See [`read::MultiGzDecoder`] for more about multistreams.
+///
+/// A call to `write` never consumes data past the end of a header, so calling
+/// [`header`] after each write observes the header of every member in turn.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`read::MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+/// [`header`]: #method.header
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// use flate2::Compression;
+/// use flate2::write::{GzEncoder, MultiGzDecoder};
+///
+/// # fn main() {
+/// #    let mut bytes = Vec::new();
+/// #    for part in &["Hello ", "World"] {
+/// #        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// #        e.write_all(part.as_bytes()).unwrap();
+/// #        bytes.extend(e.finish().unwrap());
+/// #    }
+/// #    assert_eq!("Hello World", decode_writer(bytes).unwrap());
+/// # }
+/// // Uncompresses a multistream gzip encoded vector of bytes and returns a
+/// // string or error
+/// fn decode_writer(bytes: Vec<u8>) -> io::Result<String> {
+///    let mut writer = Vec::new();
+///    let mut decoder = MultiGzDecoder::new(writer);
+///    decoder.write_all(&bytes[..])?;
+///    writer = decoder.finish()?;
+///    let return_string = String::from_utf8(writer).expect("String parsing error");
+///    Ok(return_string)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MultiGzDecoder<W: Write> {
+    inner: GzDecoder<W>,
+}
+
+impl<W: Write> MultiGzDecoder<W> {
+    /// Creates a new decoder which will write uncompressed data to the stream.
+    /// If the gzip stream contains multiple members all will be decoded.
+    pub fn new(w: W) -> MultiGzDecoder<W> {
+        MultiGzDecoder::new_with_options(w, GzDecoderOptions::default())
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream.
+    /// The headers of all members must stay within the given limits.
+    pub fn new_with_header_limits(w: W, header_limits: GzHeaderLimits) -> MultiGzDecoder<W> {
+        MultiGzDecoder::new_with_options(w, GzDecoderOptions::new().header_limits(header_limits))
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream
+    /// with the given options.
+    pub fn new_with_options(w: W, options: GzDecoderOptions) -> MultiGzDecoder<W> {
+        MultiGzDecoder {
+            inner: GzDecoder::new_with_options(w, options).multi(true),
+        }
+    }
+
+    /// Returns the header of the member currently being decoded, once it has
+    /// been parsed.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.header()
+    }
+
+    /// Returns the index of the member currently being decoded, starting at
+    /// zero.
+    pub fn member(&self) -> u64 {
+        self.inner.member
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt this
+    /// object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Attempt to finish this output stream, checking the trailer of the last
+    /// member.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to finish the stream, returning any
+    /// errors which happen.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.inner.try_finish()
+    }
+
+    /// Consumes this decoder, flushing the output stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(self) -> io::Result<W> {
+        self.inner.finish()
+    }
+}
+
+impl<W: Write> Write for MultiGzDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Read + Write> Read for MultiGzDecoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
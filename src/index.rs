@@ -0,0 +1,581 @@
+//! Indexes of access points into deflate streams, for random access without
+//! decoding everything in front of the data wanted, as zlib's `zran` example
+//! does.
+
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::{Decompress, FlushDecompress, Format, Status};
+
+/// How far back a deflate stream can refer, and so the most of the data in
+/// front of an access point needed to resume decoding there.
+const WINDOW_SIZE: usize = 32 * 1024;
+// How much input to read at a time.
+const BUF_SIZE: usize = 64 * 1024;
+// The start of a serialized index, followed by the version of the format.
+const MAGIC: [u8; 7] = *b"flate2i";
+const VERSION: u8 = 1;
+
+/// Reads into `buf`, retrying when interrupted.
+fn read_some<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match r.read(buf) {
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            result => return result,
+        }
+    }
+}
+
+fn invalid_index() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid deflate stream index")
+}
+
+/// A place in a deflate stream at which decoding can start over, along with
+/// the data in front of it that the stream can refer back to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPoint {
+    out: u64,
+    input: u64,
+    bits: u8,
+    window: Vec<u8>,
+}
+
+impl AccessPoint {
+    /// Returns the offset in the decompressed data this point is at.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.out
+    }
+
+    /// Returns the offset of the first byte of compressed input after this
+    /// point.
+    ///
+    /// As deflate blocks don't start on byte boundaries, the point can be
+    /// some of the high [`bits`] of the byte before.
+    ///
+    /// [`bits`]: #method.bits
+    pub fn compressed_offset(&self) -> u64 {
+        self.input
+    }
+
+    /// Returns how many bits of the byte before the compressed offset come
+    /// after this point.
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// Returns the data in front of this point that the stream can refer back
+    /// to, which is up to 32 KiB of it.
+    pub fn window(&self) -> &[u8] {
+        &self.window
+    }
+}
+
+/// An index of access points into a gzip, zlib or raw deflate stream
+///
+/// An index is built in a single pass over the stream, which verifies its
+/// checksum along the way, and records an access point at the first block
+/// boundary at least a given span of decompressed data after the last one.
+/// A [`SeekableGzDecoder`] then seeks by starting over at the last access
+/// point in front of the offset wanted, only decoding the data in between.
+/// Each access point holds 32 KiB of decompressed data, so the span trades
+/// the size of the index against the cost of a seek. As with [`GzDecoder`],
+/// only the first member of a gzip stream is indexed.
+///
+/// Since building an index takes as long as decompressing the whole stream,
+/// an index can be saved with [`write_to`] and loaded again with
+/// [`read_from`], in a format which stays stable across versions of this
+/// crate. It consists of, with all integers little-endian:
+///
+/// * the 7 bytes `flate2i` followed by the version of the format, 1
+/// * the size of the decompressed data as a `u64`
+/// * the number of access points as a `u64`
+/// * for each access point, its decompressed offset and compressed offset
+///   as `u64`s, its bits as a `u8`, the length of its window as a `u16`,
+///   and the window itself
+///
+/// [`SeekableGzDecoder`]: read/struct.SeekableGzDecoder.html
+/// [`GzDecoder`]: read/struct.GzDecoder.html
+/// [`write_to`]: #method.write_to
+/// [`read_from`]: #method.read_from
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io::{self, Cursor, SeekFrom};
+/// use flate2::read::SeekableGzDecoder;
+/// use flate2::write::GzEncoder;
+/// use flate2::{Compression, Format, Index};
+///
+/// # fn main() { run().unwrap(); }
+/// # fn run() -> io::Result<()> {
+/// let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World")?;
+/// let bytes = e.finish()?;
+///
+/// let index = Index::build(&bytes[..], Format::Gzip, 1 << 20)?;
+/// let mut d = SeekableGzDecoder::new(Cursor::new(bytes), index);
+/// d.seek(SeekFrom::Start(6))?;
+/// let mut s = String::new();
+/// d.read_to_string(&mut s)?;
+/// assert_eq!(s, "World");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    size: u64,
+    points: Vec<AccessPoint>,
+}
+
+impl Index {
+    /// Builds an index of the stream read from `r`, which is in the given
+    /// format, with access points about `span` bytes of decompressed data
+    /// apart.
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading fails, or if the stream is invalid or truncated.
+    pub fn build<R: Read>(mut r: R, format: Format, span: u64) -> io::Result<Index> {
+        let mut decompress = Decompress::new_with_format(format, 15);
+        let mut points = Vec::new();
+        if format == Format::Raw {
+            // Otherwise the first block boundary is right after the header.
+            points.push(AccessPoint {
+                out: 0,
+                input: 0,
+                bits: 0,
+                window: Vec::new(),
+            });
+        }
+
+        let mut input = vec![0; BUF_SIZE];
+        let (mut pos, mut len) = (0, 0);
+        let mut out = vec![0; WINDOW_SIZE];
+        let mut window = Vec::with_capacity(3 * WINDOW_SIZE);
+        let mut eof = false;
+        loop {
+            if pos == len && !eof {
+                len = read_some(&mut r, &mut input)?;
+                pos = 0;
+                eof = len == 0;
+            }
+            let before = (decompress.total_in(), decompress.total_out());
+            let status =
+                decompress.decompress(&input[pos..len], &mut out, FlushDecompress::Block)?;
+            pos += (decompress.total_in() - before.0) as usize;
+            let produced = (decompress.total_out() - before.1) as usize;
+            window.extend_from_slice(&out[..produced]);
+            if window.len() > 2 * WINDOW_SIZE {
+                window.drain(..window.len() - WINDOW_SIZE);
+            }
+            if status == Status::StreamEnd {
+                break;
+            }
+
+            let total = decompress.total_out();
+            let due = !matches!(points.last(), Some(point) if total - point.out < span);
+            if decompress.at_block_boundary() && !decompress.last_block() && due {
+                let keep = cmp::min(window.len(), WINDOW_SIZE);
+                points.push(AccessPoint {
+                    out: total,
+                    input: decompress.total_in(),
+                    bits: decompress.unused_bits(),
+                    window: window[window.len() - keep..].to_vec(),
+                });
+            }
+            if eof && before == (decompress.total_in(), decompress.total_out()) {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        Ok(Index {
+            size: decompress.total_out(),
+            points,
+        })
+    }
+
+    /// Returns the size of the decompressed data.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the access points, in the order they are in the stream.
+    pub fn points(&self) -> &[AccessPoint] {
+        &self.points
+    }
+
+    /// Writes this index out in its serialized form.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&self.size.to_le_bytes())?;
+        w.write_all(&(self.points.len() as u64).to_le_bytes())?;
+        for point in &self.points {
+            w.write_all(&point.out.to_le_bytes())?;
+            w.write_all(&point.input.to_le_bytes())?;
+            w.write_all(&[point.bits])?;
+            w.write_all(&(point.window.len() as u16).to_le_bytes())?;
+            w.write_all(&point.window)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [`write_to`].
+    ///
+    /// [`write_to`]: #method.write_to
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading fails, or if the data read isn't a valid index.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Index> {
+        fn u64_from<R: Read>(r: &mut R) -> io::Result<u64> {
+            let mut buf = [0; 8];
+            r.read_exact(&mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        }
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if magic[..7] != MAGIC || magic[7] != VERSION {
+            return Err(invalid_index());
+        }
+        let size = u64_from(&mut r)?;
+        let count = u64_from(&mut r)?;
+        let mut points: Vec<AccessPoint> = Vec::new();
+        for _ in 0..count {
+            let out = u64_from(&mut r)?;
+            let input = u64_from(&mut r)?;
+            let mut buf = [0; 3];
+            r.read_exact(&mut buf)?;
+            let bits = buf[0];
+            let len = usize::from(u16::from_le_bytes([buf[1], buf[2]]));
+            let in_order = match points.last() {
+                Some(last) => last.out < out && last.input <= input,
+                None => true,
+            };
+            // Leftover bits are taken from the byte in front of the point.
+            let has_byte = bits == 0 || input > 0;
+            if bits > 7 || !has_byte || len > WINDOW_SIZE || out > size || !in_order {
+                return Err(invalid_index());
+            }
+            let mut window = vec![0; len];
+            r.read_exact(&mut window)?;
+            points.push(AccessPoint {
+                out,
+                input,
+                bits,
+                window,
+            });
+        }
+        if points.first().map(|first| first.out) != Some(0) {
+            return Err(invalid_index());
+        }
+        Ok(Index { size, points })
+    }
+}
+
+/// A decoder for a gzip, zlib or raw deflate stream which can seek using an
+/// [`Index`]
+///
+/// Seeking only moves the position, and the next read then starts over at
+/// the last access point in front of it. Checksums aren't verified, as the
+/// data is hardly ever decoded from the start; they were when building the
+/// index. The index has to be one of the stream read, or decoding fails or
+/// returns the wrong data.
+///
+/// [`Index`]: ../struct.Index.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io::{self, Cursor, SeekFrom};
+/// use flate2::read::SeekableGzDecoder;
+/// use flate2::write::ZlibEncoder;
+/// use flate2::{Compression, Format, Index};
+///
+/// # fn main() { run().unwrap(); }
+/// # fn run() -> io::Result<()> {
+/// let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World")?;
+/// let bytes = e.finish()?;
+///
+/// let index = Index::build(&bytes[..], Format::Zlib, 1 << 20)?;
+/// let mut d = SeekableGzDecoder::new(Cursor::new(bytes), index);
+/// d.seek(SeekFrom::End(-5))?;
+/// let mut s = String::new();
+/// d.read_to_string(&mut s)?;
+/// assert_eq!(s, "World");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SeekableGzDecoder<R> {
+    inner: R,
+    index: Index,
+    decompress: Decompress,
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    // The offset in the decompressed data of the next byte read, and whether
+    // the decoder has to start over from an access point to get there.
+    offset: u64,
+    stale: bool,
+}
+
+impl<R: Read + Seek> SeekableGzDecoder<R> {
+    /// Creates a new decoder for the stream read from `r`, using the given
+    /// index of it.
+    pub fn new(r: R, index: Index) -> SeekableGzDecoder<R> {
+        SeekableGzDecoder {
+            inner: r,
+            index,
+            decompress: Decompress::new(false),
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            offset: 0,
+            stale: true,
+        }
+    }
+
+    /// Starts decoding over at the last access point in front of the
+    /// offset, and skips ahead to it.
+    fn restart(&mut self) -> io::Result<()> {
+        let points = &self.index.points;
+        let i = match points.binary_search_by_key(&self.offset, |point| point.out) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let point = &points[i];
+
+        let at = point.input - u64::from(point.bits > 0);
+        self.inner.seek(SeekFrom::Start(at))?;
+        self.decompress.reset(false);
+        if point.bits > 0 {
+            let mut byte = [0];
+            self.inner.read_exact(&mut byte)?;
+            let bits = byte[0] >> (8 - point.bits);
+            self.decompress.prime(point.bits, u16::from(bits))?;
+        }
+        if !point.window.is_empty() {
+            self.decompress.set_dictionary(&point.window)?;
+        }
+        self.pos = 0;
+        self.cap = 0;
+
+        let target = self.offset;
+        self.offset = point.out;
+        self.stale = false;
+        let skip = target - self.offset;
+        io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        if self.offset != target {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the index this decoder seeks with.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl<R> SeekableGzDecoder<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableGzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.index.size || into.is_empty() {
+            return Ok(0);
+        }
+        if self.stale {
+            self.restart()?;
+        }
+        loop {
+            if self.pos == self.cap {
+                self.cap = read_some(&mut self.inner, &mut self.buf)?;
+                self.pos = 0;
+            }
+            let before = (self.decompress.total_in(), self.decompress.total_out());
+            let input = &self.buf[self.pos..self.cap];
+            let status = self
+                .decompress
+                .decompress(input, into, FlushDecompress::None)?;
+            self.pos += (self.decompress.total_in() - before.0) as usize;
+            let n = (self.decompress.total_out() - before.1) as usize;
+            self.offset += n as u64;
+            if n > 0 || status == Status::StreamEnd {
+                return Ok(n);
+            }
+            if self.cap == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableGzDecoder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.offset, delta),
+            SeekFrom::End(delta) => (self.index.size, delta),
+        };
+        let offset = if delta < 0 {
+            base.checked_sub(delta.wrapping_neg() as u64)
+        } else {
+            base.checked_add(delta as u64)
+        };
+        let offset = offset.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        if offset != self.offset {
+            self.offset = offset;
+            self.stale = true;
+        }
+        Ok(offset)
+    }
+}
+
+impl<R: Read + Seek + Write> Write for SeekableGzDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use std::io::{Cursor, SeekFrom};
+
+    use super::{Index, SeekableGzDecoder};
+    use crate::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use crate::{Compression, Format};
+
+    fn sample() -> Vec<u8> {
+        crate::random_bytes()
+            .take(1_000_000)
+            .map(|b| b % 16 + b'a')
+            .collect()
+    }
+
+    fn compress(data: &[u8], format: Format) -> Vec<u8> {
+        fn finish<W: Write>(mut w: W, data: &[u8]) -> W {
+            w.write_all(data).unwrap();
+            w
+        }
+        let lvl = Compression::default();
+        match format {
+            Format::Raw => finish(DeflateEncoder::new(Vec::new(), lvl), data).finish(),
+            Format::Zlib => finish(ZlibEncoder::new(Vec::new(), lvl), data).finish(),
+            _ => finish(GzEncoder::new(Vec::new(), lvl), data).finish(),
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn seek_with_index() {
+        let data = sample();
+        for &format in [Format::Raw, Format::Zlib, Format::Gzip].iter() {
+            let bytes = compress(&data, format);
+            let index = Index::build(&bytes[..], format, 100_000).unwrap();
+            assert_eq!(index.uncompressed_size(), data.len() as u64);
+            assert!(index.points().len() > 5);
+            assert!(index.points().iter().any(|point| point.bits() > 0));
+
+            let mut d = SeekableGzDecoder::new(Cursor::new(&bytes[..]), index);
+            for &offset in [0, 999_990, 123_456, 500_000, 1, 1_000_000, 2_000_000].iter() {
+                assert_eq!(d.seek(SeekFrom::Start(offset)).unwrap(), offset);
+                let mut buf = vec![0; 20_000];
+                let n = d.read(&mut buf).unwrap();
+                let expected = data.get(offset as usize..).unwrap_or(&[]);
+                assert_eq!(&buf[..n], &expected[..n]);
+                assert!(n > 0 || expected.is_empty());
+            }
+
+            d.seek(SeekFrom::End(-300_000)).unwrap();
+            d.seek(SeekFrom::Current(100_000)).unwrap();
+            let mut out = Vec::new();
+            d.read_to_end(&mut out).unwrap();
+            assert!(out == &data[800_000..]);
+            assert!(d.seek(SeekFrom::Current(-2_000_000)).is_err());
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let data = sample();
+        let bytes = compress(&data, Format::Gzip);
+        let index = Index::build(&bytes[..], Format::Gzip, 200_000).unwrap();
+        let mut saved = Vec::new();
+        index.write_to(&mut saved).unwrap();
+        assert_eq!(&saved[..8], b"flate2i\x01");
+        assert_eq!(Index::read_from(&saved[..]).unwrap(), index);
+
+        assert!(Index::read_from(&saved[..saved.len() - 1]).is_err());
+        saved[7] = 2;
+        assert!(Index::read_from(&saved[..]).is_err());
+    }
+
+    #[test]
+    fn read_invalid_points() {
+        fn saved(points: &[(u64, u64, u8)]) -> Vec<u8> {
+            let mut saved = b"flate2i\x01".to_vec();
+            saved.extend_from_slice(&100u64.to_le_bytes());
+            saved.extend_from_slice(&(points.len() as u64).to_le_bytes());
+            for &(out, input, bits) in points {
+                saved.extend_from_slice(&out.to_le_bytes());
+                saved.extend_from_slice(&input.to_le_bytes());
+                saved.extend_from_slice(&[bits, 0, 0]);
+            }
+            saved
+        }
+
+        assert!(Index::read_from(&saved(&[(0, 0, 0), (50, 10, 3)])[..]).is_ok());
+        // Leftover bits without a byte in front of the point.
+        assert!(Index::read_from(&saved(&[(0, 0, 3)])[..]).is_err());
+        // Compressed offsets going backwards.
+        assert!(Index::read_from(&saved(&[(0, 10, 0), (50, 5, 0)])[..]).is_err());
+        assert!(Index::read_from(&saved(&[(0, 0, 0), (50, 10, 0), (60, 9, 0)])[..]).is_err());
+    }
+
+    #[test]
+    fn invalid_streams() {
+        let data = sample();
+        let bytes = compress(&data, Format::Gzip);
+        assert!(Index::build(&bytes[..bytes.len() / 2], Format::Gzip, 1 << 20).is_err());
+        let mut corrupt = bytes.clone();
+        let len = corrupt.len();
+        corrupt[len - 8] ^= 1;
+        assert!(Index::build(&corrupt[..], Format::Gzip, 1 << 20).is_err());
+        assert!(Index::build(&bytes[..], Format::Zlib, 1 << 20).is_err());
+    }
+}
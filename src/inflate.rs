@@ -0,0 +1,1098 @@
+//! A streaming decoder for raw deflate data.
+//!
+//! This backs `Decompress` on the pure-Rust backend. Unlike the decoder of
+//! miniz_oxide it hands back input it has read ahead whenever it stops short
+//! of the input, and exposes where it is in the bit stream, which is what
+//! priming and marking a stream rely on.
+
+use std::cmp;
+use std::mem;
+use std::ops::Range;
+
+/// The size of the window decoded data is written to, which is also the
+/// furthest a deflate stream can refer back.
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+
+// Codes up to this length are decoded with a single table lookup.
+const FAST_BITS: u32 = 10;
+const FAST_MASK: u64 = (1 << FAST_BITS) - 1;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code lengths of the code length code are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Why a deflate stream could not be decoded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InflateError {
+    /// A block header used the reserved block type.
+    ReservedBlockType,
+    /// The length of a stored block did not match its complement.
+    StoredLengthMismatch,
+    /// The Huffman code description of a dynamic block was invalid.
+    InvalidTable,
+    /// A literal/length code was not part of the code.
+    InvalidCode,
+    /// A distance code was not part of the code.
+    InvalidDistanceCode,
+    /// A distance pointed back before the start of the data.
+    DistanceTooFarBack,
+}
+
+/// What stopped a call to `Inflater::inflate`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InflateStatus {
+    /// The final block has been decoded.
+    Done,
+    /// All input has been consumed.
+    NeedsInput,
+    /// The end of the window has been reached.
+    OutputFull,
+    /// A block has ended, and stopping at the end of blocks was asked for.
+    BlockEnd,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    BlockHeader,
+    StoredLength,
+    Stored(usize),
+    DynamicCounts,
+    CodeLengthCodes(usize),
+    CodeLengths(usize),
+    Codes,
+    // A match which did not fit into the window yet.
+    Copy { len: usize, dist: usize },
+    Done,
+}
+
+/// A canonical Huffman code.
+#[derive(Clone)]
+struct Huffman {
+    // Indexed by the next `FAST_BITS` bits of input, holding
+    // `length << 9 | symbol` for codes no longer than that, or zero.
+    fast: [u16; 1 << FAST_BITS],
+    // The number of codes of each length and the symbols ordered by their
+    // code, for decoding the longer codes a bit at a time.
+    counts: [u16; 16],
+    symbols: [u16; 288],
+}
+
+impl Huffman {
+    fn new() -> Huffman {
+        Huffman {
+            fast: [0; 1 << FAST_BITS],
+            counts: [0; 16],
+            symbols: [0; 288],
+        }
+    }
+
+    /// Builds the code from the code length of each symbol.
+    ///
+    /// Like zlib an incomplete code is only accepted if it consists of a
+    /// single code of length one, which deflate uses for a block with only one
+    /// distance, or if it is empty.
+    fn build(&mut self, lengths: &[u8], allow_incomplete: bool) -> Result<(), InflateError> {
+        self.counts = [0; 16];
+        for &len in lengths {
+            self.counts[usize::from(len)] += 1;
+        }
+        self.counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for len in 1..16 {
+            left = (left << 1) - i32::from(self.counts[len]);
+            if left < 0 {
+                return Err(InflateError::InvalidTable);
+            }
+        }
+        let codes: u16 = self.counts.iter().sum();
+        if left > 0 && !(allow_incomplete && (codes == 0 || codes == 1 && self.counts[1] == 1)) {
+            return Err(InflateError::InvalidTable);
+        }
+
+        let mut offsets = [0; 16];
+        let mut next_code = [0; 16];
+        let mut code = 0;
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + self.counts[len - 1];
+            code = (code + self.counts[len - 1]) << 1;
+            next_code[len] = code;
+        }
+        offsets[0] = 0;
+
+        self.fast = [0; 1 << FAST_BITS];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = usize::from(len);
+            self.symbols[usize::from(offsets[len])] = symbol as u16;
+            offsets[len] += 1;
+
+            let code = next_code[len];
+            next_code[len] += 1;
+            if len as u32 <= FAST_BITS {
+                // Codes are stored starting with their most significant bit,
+                // while the table is indexed by bits in the order they arrive.
+                let reversed = (code.reverse_bits() >> (16 - len)) as usize;
+                let entry = (len as u16) << 9 | symbol as u16;
+                for slot in self.fast.iter_mut().skip(reversed).step_by(1 << len) {
+                    *slot = entry;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes a symbol from the `count` low bits of `bits`, returning it
+    /// along with its length or `None` if more bits are needed.
+    #[inline]
+    fn decode(&self, bits: u64, count: u32) -> Result<Option<(usize, u32)>, InflateError> {
+        let entry = self.fast[(bits & FAST_MASK) as usize];
+        if entry != 0 {
+            let len = u32::from(entry >> 9);
+            if len > count {
+                return Ok(None);
+            }
+            return Ok(Some((usize::from(entry & 0x1ff), len)));
+        }
+
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            if len > count {
+                return Ok(None);
+            }
+            code |= ((bits >> (len - 1)) & 1) as i32;
+            let count = i32::from(self.counts[len as usize]);
+            if code - first < count {
+                let symbol = self.symbols[(index + code - first) as usize];
+                return Ok(Some((usize::from(symbol), len)));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidCode)
+    }
+}
+
+/// Input bits not yet decoded, along with the input they are taken from.
+struct Bits<'a> {
+    buf: u64,
+    count: u32,
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bits<'a> {
+    /// Moves as many whole bytes of input into the bit buffer as fit.
+    #[inline]
+    fn refill(&mut self) {
+        if self.input.len() - self.pos >= 8 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&self.input[self.pos..self.pos + 8]);
+            let n = (63 - self.count) / 8;
+            self.buf |= u64::from_le_bytes(bytes) << self.count;
+            self.pos += n as usize;
+            self.count += n * 8;
+            self.buf &= (1 << self.count) - 1;
+        } else {
+            while self.count <= 56 && self.pos < self.input.len() {
+                self.buf |= u64::from(self.input[self.pos]) << self.count;
+                self.pos += 1;
+                self.count += 8;
+            }
+        }
+    }
+
+    /// Makes sure `n` bits are available, returning whether they are.
+    #[inline]
+    fn need(&mut self, n: u32) -> bool {
+        if self.count < n {
+            self.refill();
+        }
+        self.count >= n
+    }
+
+    #[inline]
+    fn peek(&self, n: u32) -> usize {
+        (self.buf & ((1 << n) - 1)) as usize
+    }
+
+    #[inline]
+    fn consume(&mut self, n: u32) {
+        self.buf >>= n;
+        self.count -= n;
+    }
+}
+
+/// A streaming raw deflate decoder writing into a 32KiB window.
+#[derive(Clone)]
+pub struct Inflater {
+    state: State,
+    bit_buf: u64,
+    bit_count: u32,
+    last_block: bool,
+    litlen: Huffman,
+    dist: Huffman,
+    // Whether the tables above hold the fixed codes.
+    fixed: bool,
+    literals: usize,
+    distances: usize,
+    code_lengths: usize,
+    lengths: [u8; 320],
+    // The number of bytes in the window that may be referred back to, and
+    // the most there can be.
+    history: usize,
+    window_size: usize,
+    // Whether the current call returns at the end of a block.
+    stop_at_block: bool,
+    // The number of bits of the match being copied and how much of it has
+    // been copied already, for `mark`.
+    match_bits: u32,
+    match_copied: usize,
+}
+
+impl Inflater {
+    pub fn new() -> Inflater {
+        Inflater {
+            state: State::BlockHeader,
+            bit_buf: 0,
+            bit_count: 0,
+            last_block: false,
+            litlen: Huffman::new(),
+            dist: Huffman::new(),
+            fixed: false,
+            literals: 0,
+            distances: 0,
+            code_lengths: 0,
+            lengths: [0; 320],
+            history: 0,
+            window_size: WINDOW_SIZE,
+            stop_at_block: false,
+            match_bits: 0,
+            match_copied: 0,
+        }
+    }
+
+    /// Prepares for a new stream, keeping the allocated tables.
+    pub fn reset(&mut self) {
+        self.state = State::BlockHeader;
+        self.bit_buf = 0;
+        self.bit_count = 0;
+        self.last_block = false;
+        self.history = 0;
+    }
+
+    /// Limits how far back distances may refer, as a stream compressed with
+    /// a smaller window can't refer back any further.
+    pub fn set_window_size(&mut self, size: usize) {
+        self.window_size = cmp::min(size, WINDOW_SIZE);
+    }
+
+    /// Makes `len` more bytes before the current window position available to
+    /// be referred back to, such as a preset dictionary.
+    pub fn add_history(&mut self, len: usize) {
+        self.history = cmp::min(self.history + len, self.window_size);
+    }
+
+    /// Returns the number of bytes before the current window position which
+    /// may be referred back to.
+    pub fn history(&self) -> usize {
+        self.history
+    }
+
+    /// Inserts `bits` bits of `value` into the input ahead of any further
+    /// input, returning false if more than 32 bits would be waiting, which is
+    /// the most zlib's `inflatePrime` allows and leaves room to refill.
+    pub fn prime(&mut self, bits: u32, value: u32) -> bool {
+        if bits > 32 || self.bit_count + bits > 32 {
+            return false;
+        }
+        let mask = (1u64 << bits) - 1;
+        self.bit_buf |= (u64::from(value) & mask) << self.bit_count;
+        self.bit_count += bits;
+        true
+    }
+
+    /// Returns the number of bits back from the current input position at
+    /// which the code being decoded started and how many bytes it produced so
+    /// far, or `None` and the number of bytes left in a stored block when not
+    /// decoding codes. This mirrors zlib's `inflateMark`.
+    pub fn mark(&self) -> (Option<u32>, usize) {
+        match self.state {
+            State::Codes => (Some(0), 0),
+            State::Copy { .. } => (Some(self.match_bits), self.match_copied),
+            State::Stored(remaining) => (None, remaining),
+            _ => (None, 0),
+        }
+    }
+
+    /// Returns whether the decoder is between two blocks, before the first one
+    /// or after the final one.
+    pub fn at_block_boundary(&self) -> bool {
+        matches!(self.state, State::BlockHeader | State::Done)
+    }
+
+    /// Returns whether the block being decoded, or the one just finished, is
+    /// the final block of the stream.
+    pub fn last_block(&self) -> bool {
+        self.last_block
+    }
+
+    /// Returns the number of bits of input taken but not decoded yet.
+    pub fn unused_bits(&self) -> u32 {
+        self.bit_count
+    }
+
+    /// Decodes `input` into `window` starting at `pos`, stopping after `limit`
+    /// bytes or at the end of the window, as well as at the end of each block
+    /// if `stop_at_block` is set. Returns the number of bytes consumed and
+    /// produced.
+    ///
+    /// Unless all input was consumed, input which has been moved into the bit
+    /// buffer but not decoded is handed back on return, except for the bits
+    /// of a partial byte.
+    pub fn inflate(
+        &mut self,
+        input: &[u8],
+        window: &mut [u8],
+        pos: usize,
+        limit: usize,
+        stop_at_block: bool,
+    ) -> (usize, usize, Result<InflateStatus, InflateError>) {
+        debug_assert_eq!(window.len(), WINDOW_SIZE);
+        let mut bits = Bits {
+            buf: self.bit_buf,
+            count: self.bit_count,
+            input,
+            pos: 0,
+        };
+        let mut out = pos;
+        let end = cmp::min(pos + limit, WINDOW_SIZE);
+        self.stop_at_block = stop_at_block;
+        let res = self.run(&mut bits, window, &mut out, pos, end);
+
+        // Give back the whole bytes of input left in the bit buffer. They
+        // were added last, so they are at the top. When out of input the bits
+        // all belong to the code which could not be decoded yet and are kept.
+        if res != Ok(InflateStatus::NeedsInput) {
+            let unused = cmp::min(bits.count / 8, bits.pos as u32);
+            bits.pos -= unused as usize;
+            bits.count -= unused * 8;
+        }
+        self.bit_buf = bits.buf & ((1 << bits.count) - 1);
+        self.bit_count = bits.count;
+        self.history = cmp::min(self.history + (out - pos), self.window_size);
+        (bits.pos, out - pos, res)
+    }
+
+    fn run(
+        &mut self,
+        bits: &mut Bits,
+        window: &mut [u8],
+        out: &mut usize,
+        start: usize,
+        end: usize,
+    ) -> Result<InflateStatus, InflateError> {
+        loop {
+            match self.state {
+                State::BlockHeader => {
+                    if !bits.need(3) {
+                        return Ok(InflateStatus::NeedsInput);
+                    }
+                    self.last_block = bits.peek(1) == 1;
+                    let kind = bits.peek(3) >> 1;
+                    bits.consume(3);
+                    self.state = match kind {
+                        0 => State::StoredLength,
+                        1 => {
+                            self.build_fixed();
+                            State::Codes
+                        }
+                        2 => State::DynamicCounts,
+                        _ => return Err(InflateError::ReservedBlockType),
+                    };
+                }
+                State::StoredLength => {
+                    // Stored blocks start at the next byte of input.
+                    let partial = bits.count % 8;
+                    bits.consume(partial);
+                    if !bits.need(32) {
+                        return Ok(InflateStatus::NeedsInput);
+                    }
+                    let len = bits.peek(16);
+                    bits.consume(16);
+                    let nlen = bits.peek(16);
+                    bits.consume(16);
+                    if len != !nlen & 0xffff {
+                        return Err(InflateError::StoredLengthMismatch);
+                    }
+                    self.state = State::Stored(len);
+                }
+                State::Stored(mut remaining) => {
+                    while remaining > 0 && bits.count >= 8 && *out < end {
+                        window[*out] = bits.buf as u8;
+                        bits.consume(8);
+                        *out += 1;
+                        remaining -= 1;
+                    }
+                    let n = cmp::min(remaining, cmp::min(bits.input.len() - bits.pos, end - *out));
+                    window[*out..*out + n].copy_from_slice(&bits.input[bits.pos..bits.pos + n]);
+                    bits.pos += n;
+                    *out += n;
+                    remaining -= n;
+                    if remaining > 0 {
+                        self.state = State::Stored(remaining);
+                        return Ok(if *out == end {
+                            InflateStatus::OutputFull
+                        } else {
+                            InflateStatus::NeedsInput
+                        });
+                    }
+                    if self.end_block() {
+                        return Ok(InflateStatus::BlockEnd);
+                    }
+                }
+                State::DynamicCounts => {
+                    if !bits.need(14) {
+                        return Ok(InflateStatus::NeedsInput);
+                    }
+                    self.literals = bits.peek(5) + 257;
+                    self.distances = (bits.peek(10) >> 5) + 1;
+                    self.code_lengths = (bits.peek(14) >> 10) + 4;
+                    bits.consume(14);
+                    if self.literals > 286 || self.distances > 30 {
+                        return Err(InflateError::InvalidTable);
+                    }
+                    self.lengths[..19].copy_from_slice(&[0; 19]);
+                    self.state = State::CodeLengthCodes(0);
+                }
+                State::CodeLengthCodes(mut index) => {
+                    while index < self.code_lengths {
+                        if !bits.need(3) {
+                            self.state = State::CodeLengthCodes(index);
+                            return Ok(InflateStatus::NeedsInput);
+                        }
+                        self.lengths[CODE_LENGTH_ORDER[index]] = bits.peek(3) as u8;
+                        bits.consume(3);
+                        index += 1;
+                    }
+                    // The literal/length table holds the code length code
+                    // until the actual codes are known.
+                    self.fixed = false;
+                    self.litlen.build(&self.lengths[..19], false)?;
+                    self.state = State::CodeLengths(0);
+                }
+                State::CodeLengths(mut index) => {
+                    let total = self.literals + self.distances;
+                    while index < total {
+                        bits.need(7 + 7);
+                        let (symbol, len) = match self
+                            .litlen
+                            .decode(bits.buf, bits.count)
+                            .map_err(|_| InflateError::InvalidTable)?
+                        {
+                            Some(decoded) => decoded,
+                            None => {
+                                self.state = State::CodeLengths(index);
+                                return Ok(InflateStatus::NeedsInput);
+                            }
+                        };
+                        if symbol < 16 {
+                            bits.consume(len);
+                            self.lengths[index] = symbol as u8;
+                            index += 1;
+                            continue;
+                        }
+                        let (extra, base, value) = match symbol {
+                            16 if index == 0 => return Err(InflateError::InvalidTable),
+                            16 => (2, 3, self.lengths[index - 1]),
+                            17 => (3, 3, 0),
+                            _ => (7, 11, 0),
+                        };
+                        if bits.count < len + extra {
+                            self.state = State::CodeLengths(index);
+                            return Ok(InflateStatus::NeedsInput);
+                        }
+                        let repeat = base + ((bits.buf >> len) & ((1 << extra) - 1)) as usize;
+                        bits.consume(len + extra);
+                        if index + repeat > total {
+                            return Err(InflateError::InvalidTable);
+                        }
+                        for length in &mut self.lengths[index..index + repeat] {
+                            *length = value;
+                        }
+                        index += repeat;
+                    }
+                    if self.lengths[256] == 0 {
+                        return Err(InflateError::InvalidTable);
+                    }
+                    self.litlen.build(&self.lengths[..self.literals], true)?;
+                    self.dist.build(&self.lengths[self.literals..total], true)?;
+                    self.state = State::Codes;
+                }
+                State::Codes => {
+                    if let Some(status) = self.decode_codes(bits, window, out, start, end)? {
+                        return Ok(status);
+                    }
+                }
+                State::Copy { len, dist } => {
+                    let n = cmp::min(len, end - *out);
+                    copy_match(window, *out, dist, n);
+                    *out += n;
+                    if n < len {
+                        self.match_copied += n;
+                        self.state = State::Copy { len: len - n, dist };
+                        return Ok(InflateStatus::OutputFull);
+                    }
+                    self.state = State::Codes;
+                }
+                State::Done => return Ok(InflateStatus::Done),
+            }
+        }
+    }
+
+    /// Decodes literals and matches until the end of the block, returning a
+    /// status if decoding has to stop before that.
+    fn decode_codes(
+        &mut self,
+        bits: &mut Bits,
+        window: &mut [u8],
+        out: &mut usize,
+        start: usize,
+        end: usize,
+    ) -> Result<Option<InflateStatus>, InflateError> {
+        loop {
+            bits.refill();
+            let (symbol, len) = match self.litlen.decode(bits.buf, bits.count)? {
+                Some(decoded) => decoded,
+                None => return Ok(Some(InflateStatus::NeedsInput)),
+            };
+            // The end of the block can still be read without room for output.
+            if *out == end && symbol != 256 {
+                return Ok(Some(InflateStatus::OutputFull));
+            }
+            if symbol < 256 {
+                bits.consume(len);
+                window[*out] = symbol as u8;
+                *out += 1;
+                continue;
+            }
+            if symbol == 256 {
+                bits.consume(len);
+                if self.end_block() {
+                    return Ok(Some(InflateStatus::BlockEnd));
+                }
+                return Ok(None);
+            }
+
+            // A match only consumes its bits once all of them are available.
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err(InflateError::InvalidCode);
+            }
+            let length_extra = u32::from(LENGTH_EXTRA[symbol]);
+            let used = len + length_extra;
+            if bits.count < used {
+                return Ok(Some(InflateStatus::NeedsInput));
+            }
+            let length = usize::from(LENGTH_BASE[symbol])
+                + ((bits.buf >> len) & ((1 << length_extra) - 1)) as usize;
+
+            let (dist_symbol, dist_len) = match self
+                .dist
+                .decode(bits.buf >> used, bits.count - used)
+                .map_err(|_| InflateError::InvalidDistanceCode)?
+            {
+                Some(decoded) => decoded,
+                None => return Ok(Some(InflateStatus::NeedsInput)),
+            };
+            if dist_symbol >= DIST_BASE.len() {
+                return Err(InflateError::InvalidDistanceCode);
+            }
+            let dist_extra = u32::from(DIST_EXTRA[dist_symbol]);
+            let total = used + dist_len + dist_extra;
+            if bits.count < total {
+                return Ok(Some(InflateStatus::NeedsInput));
+            }
+            let dist = usize::from(DIST_BASE[dist_symbol])
+                + ((bits.buf >> (used + dist_len)) & ((1 << dist_extra) - 1)) as usize;
+            if dist > cmp::min(self.history + (*out - start), self.window_size) {
+                return Err(InflateError::DistanceTooFarBack);
+            }
+            bits.consume(total);
+
+            let n = cmp::min(length, end - *out);
+            copy_match(window, *out, dist, n);
+            *out += n;
+            if n < length {
+                self.match_bits = total;
+                self.match_copied = n;
+                self.state = State::Copy {
+                    len: length - n,
+                    dist,
+                };
+                return Ok(Some(InflateStatus::OutputFull));
+            }
+        }
+    }
+
+    /// Moves past the end of a block, returning whether to stop there.
+    fn end_block(&mut self) -> bool {
+        self.state = if self.last_block {
+            State::Done
+        } else {
+            State::BlockHeader
+        };
+        self.stop_at_block
+    }
+
+    fn build_fixed(&mut self) {
+        if self.fixed {
+            return;
+        }
+        let mut lengths = [0; 288];
+        lengths[..144].copy_from_slice(&[8; 144]);
+        lengths[144..256].copy_from_slice(&[9; 112]);
+        lengths[256..280].copy_from_slice(&[7; 24]);
+        lengths[280..].copy_from_slice(&[8; 8]);
+        self.litlen.build(&lengths, false).unwrap();
+        self.dist.build(&[5; 32], false).unwrap();
+        self.fixed = true;
+    }
+}
+
+/// Copies `len` bytes from `dist` bytes back to `pos` in the window.
+#[inline]
+fn copy_match(window: &mut [u8], pos: usize, dist: usize, len: usize) {
+    if dist <= pos && dist >= len {
+        window.copy_within(pos - dist..pos - dist + len, pos);
+    } else {
+        for i in pos..pos + len {
+            window[i] = window[(i.wrapping_sub(dist)) & WINDOW_MASK];
+        }
+    }
+}
+
+/// Part of a deflate stream decoded without knowing the data before it, as
+/// returned by `speculate`.
+#[derive(Debug)]
+pub struct Speculation {
+    /// The bits of the input decoding could have started at with the same
+    /// result. There can be a few when the first block is stored, as zero bits
+    /// in front of its header are skipped over the same way as its padding.
+    pub starts: Range<usize>,
+    /// The bit of the input right after the last block decoded.
+    pub end: usize,
+    /// Whether the last block decoded is the final one of the stream.
+    pub last: bool,
+    /// The decoded data, in which a symbol `256 + i` stands for byte `i` of
+    /// the 32KiB window preceding the start, which isn't known yet.
+    pub symbols: Vec<u16>,
+}
+
+/// Guesses where the first block starting in `input` is, and decodes from
+/// there up to the end of the first block ending at or after bit `stop`, or
+/// of the final block.
+///
+/// Only dynamic and stored blocks are looked for, as fixed blocks have too
+/// little structure to be told apart from other data. The first bit from
+/// which the input decodes that far is taken, which can still be the wrong
+/// one, so the caller has to check that the previous part of the stream
+/// really ends there.
+pub fn speculate(input: &[u8], stop: usize) -> Option<Speculation> {
+    let mut speculator = Speculator {
+        litlen: Huffman::new(),
+        dist: Huffman::new(),
+        fixed: false,
+        lengths: [0; 286 + 30],
+        symbols: Vec::new(),
+    };
+    (0..cmp::min(stop, input.len() * 8)).find_map(|start| {
+        let (starts, end, last) = speculator.decode(input, start, stop)?;
+        Some(Speculation {
+            starts,
+            end,
+            last,
+            symbols: mem::take(&mut speculator.symbols),
+        })
+    })
+}
+
+/// Decodes whole blocks out of input which is all there, into symbols
+/// standing for either bytes or places in the unknown window.
+struct Speculator {
+    litlen: Huffman,
+    dist: Huffman,
+    fixed: bool,
+    lengths: [u8; 286 + 30],
+    symbols: Vec<u16>,
+}
+
+impl Speculator {
+    /// Decodes from bit `start` of `input`, returning the starts which are as
+    /// good, and where and in which kind of block decoding stopped, or `None`
+    /// if the data doesn't decode.
+    fn decode(
+        &mut self,
+        input: &[u8],
+        start: usize,
+        stop: usize,
+    ) -> Option<(Range<usize>, usize, bool)> {
+        self.symbols.clear();
+        let mut bits = Bits {
+            buf: 0,
+            count: 0,
+            input,
+            pos: start / 8,
+        };
+        let skip = (start % 8) as u32;
+        if !bits.need(skip) {
+            return None;
+        }
+        bits.consume(skip);
+
+        let mut starts = start..start + 1;
+        let mut first = true;
+        loop {
+            if !bits.need(3) {
+                return None;
+            }
+            let last = bits.peek(1) == 1;
+            let kind = bits.peek(3) >> 1;
+            bits.consume(3);
+            match kind {
+                0 => {
+                    // The end of a flush looks like a stored block a bit or
+                    // two early, so only trust one with nothing odd about it.
+                    let partial = bits.count % 8;
+                    if first {
+                        if last || bits.peek(partial) != 0 {
+                            return None;
+                        }
+                        let padded = bits.pos * 8 - (bits.count - partial) as usize;
+                        starts.end = padded - 2;
+                    }
+                    self.stored(&mut bits)?;
+                }
+                1 if !first => {
+                    self.build_fixed();
+                    self.codes(&mut bits)?;
+                }
+                2 => {
+                    self.dynamic(&mut bits)?;
+                    self.codes(&mut bits)?;
+                }
+                _ => return None,
+            }
+            let end = bits.pos * 8 - bits.count as usize;
+            if last || end >= stop {
+                return Some((starts, end, last));
+            }
+            first = false;
+        }
+    }
+
+    fn stored(&mut self, bits: &mut Bits) -> Option<()> {
+        let partial = bits.count % 8;
+        bits.consume(partial);
+        if !bits.need(32) {
+            return None;
+        }
+        let len = bits.peek(16);
+        bits.consume(16);
+        let nlen = bits.peek(16);
+        bits.consume(16);
+        if len != !nlen & 0xffff {
+            return None;
+        }
+
+        let mut remaining = len;
+        while remaining > 0 && bits.count >= 8 {
+            self.symbols.push(u16::from(bits.buf as u8));
+            bits.consume(8);
+            remaining -= 1;
+        }
+        let data = bits.input.get(bits.pos..bits.pos + remaining)?;
+        self.symbols
+            .extend(data.iter().map(|&byte| u16::from(byte)));
+        bits.pos += remaining;
+        Some(())
+    }
+
+    fn dynamic(&mut self, bits: &mut Bits) -> Option<()> {
+        if !bits.need(14) {
+            return None;
+        }
+        let literals = bits.peek(5) + 257;
+        let distances = (bits.peek(10) >> 5) + 1;
+        let code_lengths = (bits.peek(14) >> 10) + 4;
+        bits.consume(14);
+        if literals > 286 || distances > 30 {
+            return None;
+        }
+
+        self.lengths[..19].copy_from_slice(&[0; 19]);
+        for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+            if !bits.need(3) {
+                return None;
+            }
+            self.lengths[index] = bits.peek(3) as u8;
+            bits.consume(3);
+        }
+        self.fixed = false;
+        self.litlen.build(&self.lengths[..19], false).ok()?;
+
+        let total = literals + distances;
+        let mut index = 0;
+        while index < total {
+            bits.need(7 + 7);
+            let (symbol, len) = self.litlen.decode(bits.buf, bits.count).ok()??;
+            if symbol < 16 {
+                bits.consume(len);
+                self.lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            let (extra, base, value) = match symbol {
+                16 if index == 0 => return None,
+                16 => (2, 3, self.lengths[index - 1]),
+                17 => (3, 3, 0),
+                _ => (7, 11, 0),
+            };
+            if bits.count < len + extra {
+                return None;
+            }
+            let repeat = base + ((bits.buf >> len) & ((1 << extra) - 1)) as usize;
+            bits.consume(len + extra);
+            if index + repeat > total {
+                return None;
+            }
+            for length in &mut self.lengths[index..index + repeat] {
+                *length = value;
+            }
+            index += repeat;
+        }
+        if self.lengths[256] == 0 {
+            return None;
+        }
+        self.litlen.build(&self.lengths[..literals], true).ok()?;
+        self.dist.build(&self.lengths[literals..total], true).ok()
+    }
+
+    fn codes(&mut self, bits: &mut Bits) -> Option<()> {
+        loop {
+            bits.refill();
+            let (symbol, len) = self.litlen.decode(bits.buf, bits.count).ok()??;
+            if symbol < 256 {
+                bits.consume(len);
+                self.symbols.push(symbol as u16);
+                continue;
+            }
+            if symbol == 256 {
+                bits.consume(len);
+                return Some(());
+            }
+
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return None;
+            }
+            let length_extra = u32::from(LENGTH_EXTRA[symbol]);
+            let used = len + length_extra;
+            if bits.count < used {
+                return None;
+            }
+            let length = usize::from(LENGTH_BASE[symbol])
+                + ((bits.buf >> len) & ((1 << length_extra) - 1)) as usize;
+            let (dist_symbol, dist_len) = self
+                .dist
+                .decode(bits.buf >> used, bits.count - used)
+                .ok()??;
+            if dist_symbol >= DIST_BASE.len() {
+                return None;
+            }
+            let dist_extra = u32::from(DIST_EXTRA[dist_symbol]);
+            let total = used + dist_len + dist_extra;
+            if bits.count < total {
+                return None;
+            }
+            let dist = usize::from(DIST_BASE[dist_symbol])
+                + ((bits.buf >> (used + dist_len)) & ((1 << dist_extra) - 1)) as usize;
+            bits.consume(total);
+
+            // Matches reaching back before the start copy a placeholder for
+            // the window byte they refer to.
+            let pos = self.symbols.len();
+            if dist > pos + WINDOW_SIZE {
+                return None;
+            }
+            for pos in pos..pos + length {
+                let symbol = if dist <= pos {
+                    self.symbols[pos - dist]
+                } else {
+                    (256 + WINDOW_SIZE + pos - dist) as u16
+                };
+                self.symbols.push(symbol);
+            }
+        }
+    }
+
+    fn build_fixed(&mut self) {
+        if self.fixed {
+            return;
+        }
+        let mut lengths = [0; 288];
+        lengths[..144].copy_from_slice(&[8; 144]);
+        lengths[144..256].copy_from_slice(&[9; 112]);
+        lengths[256..280].copy_from_slice(&[7; 24]);
+        lengths[280..].copy_from_slice(&[8; 8]);
+        self.litlen.build(&lengths, false).unwrap();
+        self.dist.build(&[5; 32], false).unwrap();
+        self.fixed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{speculate, InflateError, InflateStatus, Inflater, WINDOW_SIZE};
+    use crate::{Compress, Compression, FlushCompress};
+
+    /// Decodes `input` fed in chunks of `chunk` bytes.
+    fn inflate(input: &[u8], chunk: usize) -> Result<Vec<u8>, InflateError> {
+        let mut inflater = Inflater::new();
+        let mut window = vec![0; WINDOW_SIZE];
+        let mut pos = 0;
+        let mut output = Vec::new();
+        let mut consumed = 0;
+        loop {
+            let end = std::cmp::min(consumed + chunk, input.len());
+            let (n, produced, res) =
+                inflater.inflate(&input[consumed..end], &mut window, pos, WINDOW_SIZE, false);
+            consumed += n;
+            output.extend_from_slice(&window[pos..pos + produced]);
+            pos = (pos + produced) % WINDOW_SIZE;
+            match res? {
+                InflateStatus::Done => return Ok(output),
+                InflateStatus::NeedsInput if consumed == input.len() => panic!("truncated"),
+                _ => {}
+            }
+        }
+    }
+
+    fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+        let mut c = Compress::new(Compression::new(level), false);
+        let mut out = Vec::with_capacity(data.len() + 1024);
+        c.compress_vec(data, &mut out, FlushCompress::Finish)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn block_types() {
+        let data = crate::random_bytes()
+            .take(100_000)
+            .map(|b| b % 16)
+            .collect::<Vec<_>>();
+        for &level in &[0, 1, 6, 9] {
+            let encoded = deflate(&data, level);
+            for &chunk in &[1, 7, 4096, encoded.len()] {
+                assert_eq!(inflate(&encoded, chunk).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_block() {
+        // A fixed block holding "a" followed by a match of length 3.
+        let encoded = [0x4b, 0x04, 0x02, 0x00];
+        assert_eq!(inflate(&encoded, 1).unwrap(), b"aaaa");
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(inflate(&[0x07], 1), Err(InflateError::ReservedBlockType));
+        assert_eq!(
+            inflate(&[0x01, 0x01, 0x00, 0x00, 0xff], 1),
+            Err(InflateError::StoredLengthMismatch)
+        );
+        // A fixed block starting with a distance of one.
+        assert_eq!(
+            inflate(&[0x03, 0x02], 1),
+            Err(InflateError::DistanceTooFarBack)
+        );
+        // A dynamic block with an empty code length code.
+        assert_eq!(
+            inflate(&[0x05, 0x00, 0x00, 0x00], 1),
+            Err(InflateError::InvalidTable)
+        );
+    }
+
+    #[test]
+    fn prime() {
+        // Split the first byte of a stream off and feed its bits separately.
+        let encoded = deflate(b"hello hello hello", 6);
+        let mut inflater = Inflater::new();
+        assert!(inflater.prime(3, u32::from(encoded[0])));
+        assert!(inflater.prime(5, u32::from(encoded[0] >> 3)));
+        let mut window = vec![0; WINDOW_SIZE];
+        let (consumed, produced, res) =
+            inflater.inflate(&encoded[1..], &mut window, 0, WINDOW_SIZE, false);
+        assert_eq!(res, Ok(InflateStatus::Done));
+        assert_eq!(consumed, encoded.len() - 1);
+        assert_eq!(&window[..produced], b"hello hello hello");
+        assert!(!inflater.prime(33, 0));
+
+        let mut inflater = Inflater::new();
+        assert!(inflater.prime(32, 0));
+        assert!(!inflater.prime(1, 0));
+    }
+
+    #[test]
+    fn speculate_from_middle() {
+        let data = crate::random_bytes()
+            .take(300_000)
+            .map(|b| b % 16)
+            .collect::<Vec<_>>();
+        let encoded = deflate(&data, 6);
+        let found = speculate(&encoded[encoded.len() / 2..], usize::MAX).unwrap();
+        assert!(!found.starts.is_empty());
+        assert!(found.last);
+        assert_eq!((found.end + 7) / 8, encoded.len() - encoded.len() / 2);
+
+        // The decoded symbols are the end of the data, with placeholders for
+        // the bytes before them.
+        let start = data.len() - found.symbols.len();
+        assert!(start > 0);
+        for (i, &symbol) in found.symbols.iter().enumerate() {
+            let expected = match usize::from(symbol).checked_sub(256) {
+                Some(index) => data[start + index - WINDOW_SIZE],
+                None => symbol as u8,
+            };
+            assert_eq!(expected, data[start + i]);
+        }
+
+        assert!(speculate(&[0xff; 64], usize::MAX).is_none());
+    }
+}
@@ -9,7 +9,8 @@
 //!
 //! These three formats are all closely related and largely only differ in their
 //! headers/footers. This crate has three types in each submodule for dealing
-//! with these three formats.
+//! with these three formats. An `AutoDecoder` in each submodule detects which
+//! of the formats it is given.
 //!
 //! # Implementation
 //!
@@ -65,9 +66,18 @@
 //! `Write` trait if `T: Write`. That is, the "dual trait" is forwarded directly
 //! to the underlying object if available.
 //!
+//! Formats built on gzip which add random access, such as BGZF and dictzip,
+//! have modules of their own like [`bgzf`] and [`dictzip`]. Any other stream
+//! can be seeked in by building an [`Index`] of it for
+//! [`read::SeekableGzDecoder`].
+//!
 //! [`read`]: read/index.html
 //! [`bufread`]: bufread/index.html
 //! [`write`]: write/index.html
+//! [`bgzf`]: bgzf/index.html
+//! [`dictzip`]: dictzip/index.html
+//! [`Index`]: struct.Index.html
+//! [`read::SeekableGzDecoder`]: read/struct.SeekableGzDecoder.html
 //! [read]: https://doc.rust-lang.org/std/io/trait.Read.html
 //! [write]: https://doc.rust-lang.org/std/io/trait.Write.html
 //! [bufread]: https://doc.rust-lang.org/std/io/trait.BufRead.html
@@ -77,18 +87,38 @@
 #![allow(trivial_numeric_casts)]
 #![cfg_attr(test, deny(warnings))]
 
+pub use crate::auto::DetectedFormat;
 pub use crate::crc::{Crc, CrcReader, CrcWriter};
 pub use crate::gz::GzBuilder;
+pub use crate::gz::GzDecoderOptions;
+pub use crate::gz::GzError;
+pub use crate::gz::GzErrorKind;
 pub use crate::gz::GzHeader;
-pub use crate::mem::{Compress, CompressError, Decompress, DecompressError, Status};
+pub use crate::gz::GzHeaderLimits;
+pub use crate::index::{AccessPoint, Index};
+pub use crate::mem::{
+    Compress, CompressError, CompressOption, CompressOptions, CompressionStrategy, Decompress,
+    DecompressError, DecompressErrorKind, DecompressLimit, DecompressLimits, DecompressMark,
+    Format, Status,
+};
 pub use crate::mem::{FlushCompress, FlushDecompress};
+pub use crate::par::ParOptions;
 
+mod adler;
+mod auto;
+pub mod bgzf;
 mod bufreader;
 mod crc;
 mod deflate;
+pub mod dictzip;
 mod ffi;
 mod gz;
+mod index;
+// With a zlib backend only the speculative decoding in here is used.
+#[cfg_attr(feature = "any_zlib", allow(dead_code))]
+mod inflate;
 mod mem;
+mod par;
 mod zio;
 mod zlib;
 
@@ -97,11 +127,15 @@ mod zlib;
 ///
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 pub mod read {
+    pub use crate::auto::read::AutoDecoder;
     pub use crate::deflate::read::DeflateDecoder;
     pub use crate::deflate::read::DeflateEncoder;
     pub use crate::gz::read::GzDecoder;
     pub use crate::gz::read::GzEncoder;
     pub use crate::gz::read::MultiGzDecoder;
+    pub use crate::index::SeekableGzDecoder;
+    pub use crate::par::read::ParGzDecoder;
+    pub use crate::par::read::ParMultiGzDecoder;
     pub use crate::zlib::read::ZlibDecoder;
     pub use crate::zlib::read::ZlibEncoder;
 }
@@ -111,10 +145,14 @@ pub mod read {
 ///
 /// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
 pub mod write {
+    pub use crate::auto::write::AutoDecoder;
     pub use crate::deflate::write::DeflateDecoder;
     pub use crate::deflate::write::DeflateEncoder;
     pub use crate::gz::write::GzDecoder;
     pub use crate::gz::write::GzEncoder;
+    pub use crate::gz::write::MultiGzDecoder;
+    pub use crate::par::write::ParGzEncoder;
+    pub use crate::par::write::ParZlibEncoder;
     pub use crate::zlib::write::ZlibDecoder;
     pub use crate::zlib::write::ZlibEncoder;
 }
@@ -124,10 +162,13 @@ pub mod write {
 ///
 /// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
 pub mod bufread {
+    pub use crate::auto::bufread::AutoDecoder;
     pub use crate::deflate::bufread::DeflateDecoder;
     pub use crate::deflate::bufread::DeflateEncoder;
     pub use crate::gz::bufread::GzDecoder;
     pub use crate::gz::bufread::GzEncoder;
+    pub use crate::gz::bufread::GzMember;
+    pub use crate::gz::bufread::GzMembers;
     pub use crate::gz::bufread::MultiGzDecoder;
     pub use crate::zlib::bufread::ZlibDecoder;
     pub use crate::zlib::bufread::ZlibEncoder;
@@ -143,12 +184,14 @@ fn _assert_send_sync() {
     _assert_send_sync::<read::GzEncoder<&[u8]>>();
     _assert_send_sync::<read::GzDecoder<&[u8]>>();
     _assert_send_sync::<read::MultiGzDecoder<&[u8]>>();
+    _assert_send_sync::<read::AutoDecoder<&[u8]>>();
     _assert_send_sync::<write::DeflateEncoder<Vec<u8>>>();
     _assert_send_sync::<write::DeflateDecoder<Vec<u8>>>();
     _assert_send_sync::<write::ZlibEncoder<Vec<u8>>>();
     _assert_send_sync::<write::ZlibDecoder<Vec<u8>>>();
     _assert_send_sync::<write::GzEncoder<Vec<u8>>>();
     _assert_send_sync::<write::GzDecoder<Vec<u8>>>();
+    _assert_send_sync::<write::AutoDecoder<Vec<u8>>>();
 }
 
 /// When compressing data, the compression level can be specified by a value in
@@ -16,8 +16,14 @@ use crate::Compression;
 /// It is recommended to use the I/O stream adaptors over this type as they're
 /// easier to use.
 ///
+/// A `Compress` can be copied with [`try_clone`], so that for example one
+/// which was given a dictionary or a common prefix can be reused for many
+/// streams. With the zlib backends, which can always copy their state, it
+/// also implements `Clone`.
+///
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 /// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`try_clone`]: #method.try_clone
 #[derive(Debug)]
 pub struct Compress {
     inner: Deflate,
@@ -33,14 +39,21 @@ pub struct Compress {
 /// It is recommended to use the I/O stream adaptors over this type as they're
 /// easier to use.
 ///
+/// A `Decompress` can be cloned, including the window of recent output, so
+/// that decompression can continue from the same point in more than one way.
+/// [`try_clone`] does the same without panicking if the zlib backends fail to
+/// allocate memory for the copy.
+///
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 /// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`try_clone`]: #method.try_clone
 #[derive(Debug)]
 pub struct Decompress {
     inner: Inflate,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 /// Values which indicate the form of flushing to be used when compressing
 /// in-memory data.
 pub enum FlushCompress {
@@ -81,11 +94,352 @@ pub enum FlushCompress {
     /// data has yet to be processed.
     Finish = ffi::MZ_FINISH as isize,
 
-    #[doc(hidden)]
-    _Nonexhaustive,
+    /// The current deflate block is completed and all of its output is
+    /// flushed, but the output is not aligned to a byte boundary.
+    ///
+    /// Up to seven bits of the end of the block are held back until more data
+    /// is compressed, so unlike with `Flush::Partial` the decompressor may not
+    /// be able to finish the block yet. This controls where blocks end, such
+    /// as to make room for the points of an index for random access.
+    ///
+    /// The miniz_oxide backend always ends blocks on a byte boundary and treats
+    /// this like `Flush::Sync`.
+    Block = ffi::MZ_BLOCK as isize,
+}
+
+/// Values which tune the compression algorithm for particular kinds of input.
+///
+/// The strategy only affects the compression ratio, not the correctness of the
+/// compressed output, and any of them can be decompressed by a standard
+/// decoder.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum CompressionStrategy {
+    /// The default strategy, suitable for most kinds of data.
+    #[default]
+    Default = ffi::MZ_DEFAULT_STRATEGY as isize,
+
+    /// Intended for data produced by a filter or predictor, consisting mostly
+    /// of small values with a somewhat random distribution, such as filtered
+    /// PNG scanlines.
+    ///
+    /// This restricts string matching to longer matches and favors Huffman
+    /// coding of the remaining literals.
+    Filtered = ffi::MZ_FILTERED as isize,
+
+    /// Disables string matching entirely and only Huffman codes the literals.
+    HuffmanOnly = ffi::MZ_HUFFMAN_ONLY as isize,
+
+    /// Limits string matching to a distance of one, which is run-length
+    /// encoding.
+    ///
+    /// This is almost as fast as `HuffmanOnly` while giving better compression
+    /// for data with long runs of repeated bytes.
+    Rle = ffi::MZ_RLE as isize,
+
+    /// Prevents the use of dynamic Huffman codes, which allows for a simpler
+    /// decoder in special applications.
+    Fixed = ffi::MZ_FIXED as isize,
+}
+
+/// The container format wrapped around a deflate stream.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// A raw deflate stream without any header or trailer.
+    Raw,
+
+    /// A deflate stream with a zlib header and Adler-32 trailer.
+    Zlib,
+
+    /// A deflate stream with a minimal gzip header and CRC-32 trailer.
+    Gzip,
+}
+
+/// The parameters accepted by [`CompressOptions`], used to report which one
+/// could not be honoured when creating a [`Compress`].
+///
+/// [`CompressOptions`]: struct.CompressOptions.html
+/// [`Compress`]: struct.Compress.html
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CompressOption {
+    /// The compression level.
+    Level,
+    /// The container format.
+    Format,
+    /// The base-2 logarithm of the window size.
+    WindowBits,
+    /// The amount of memory used for the internal compression state.
+    MemLevel,
+    /// The compression strategy.
+    Strategy,
+}
+
+impl fmt::Display for CompressOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CompressOption::Level => "level",
+            CompressOption::Format => "format",
+            CompressOption::WindowBits => "window bits",
+            CompressOption::MemLevel => "memory level",
+            CompressOption::Strategy => "strategy",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A builder for the full set of parameters used to create a [`Compress`].
+///
+/// The defaults match those of [`Compress::new`] with a zlib header: a
+/// 32KiB window (`window_bits` of 15), a `mem_level` of 8 and the default
+/// strategy.
+///
+/// Not every backend can honour every combination of options. Creating a
+/// `Compress` with options the backend doesn't support returns an error,
+/// see [`CompressError::unsupported_option`].
+///
+/// # Examples
+///
+/// ```
+/// use flate2::{Compress, CompressOptions, Compression, Format};
+///
+/// let options = CompressOptions::new(Compression::default()).format(Format::Raw);
+/// let compress = Compress::new_with_options(options).unwrap();
+/// # drop(compress);
+/// ```
+///
+/// [`Compress`]: struct.Compress.html
+/// [`Compress::new`]: struct.Compress.html#method.new
+/// [`CompressError::unsupported_option`]: struct.CompressError.html#method.unsupported_option
+#[derive(Clone, Debug)]
+pub struct CompressOptions {
+    pub(crate) level: Compression,
+    pub(crate) format: Format,
+    pub(crate) window_bits: u8,
+    pub(crate) mem_level: u8,
+    pub(crate) strategy: CompressionStrategy,
+}
+
+impl CompressOptions {
+    /// Creates a new set of options using the given compression level and
+    /// defaults for everything else.
+    pub fn new(level: Compression) -> CompressOptions {
+        CompressOptions {
+            level,
+            format: Format::Zlib,
+            window_bits: ffi::MZ_DEFAULT_WINDOW_BITS as u8,
+            mem_level: 8,
+            strategy: CompressionStrategy::Default,
+        }
+    }
+
+    /// Configures the container format of the compressed stream.
+    pub fn format(mut self, format: Format) -> CompressOptions {
+        self.format = format;
+        self
+    }
+
+    /// Configures the base-2 logarithm of the sliding window size, which must
+    /// be between 9 and 15.
+    ///
+    /// The pure-Rust backend always works with a 32KiB window internally. A
+    /// smaller window is emulated by restarting it with the second half of
+    /// the window whenever it could refer back further, which costs some
+    /// compression ratio compared to a zlib backend.
+    pub fn window_bits(mut self, window_bits: u8) -> CompressOptions {
+        self.window_bits = window_bits;
+        self
+    }
+
+    /// Configures how much memory is used for the internal compression state,
+    /// which must be between 1 and 9.
+    ///
+    /// Lower values use less memory but are slower and reduce the compression
+    /// ratio, higher values use more memory for better speed.
+    pub fn mem_level(mut self, mem_level: u8) -> CompressOptions {
+        self.mem_level = mem_level;
+        self
+    }
+
+    /// Configures the compression strategy.
+    pub fn strategy(mut self, strategy: CompressionStrategy) -> CompressOptions {
+        self.strategy = strategy;
+        self
+    }
+}
+
+/// Limits on how much output a decoder may produce, to protect against
+/// decompression bombs.
+///
+/// A small compressed stream can expand to gigabytes of output. The decoders
+/// in the [`read`], [`bufread`] and [`write`] modules accept these limits
+/// through their `set_limits` methods and fail with an error of kind
+/// [`DecompressErrorKind::LimitExceeded`] as soon as one of them is exceeded.
+/// No output beyond [`max_output`] is ever returned, while all output up to
+/// it is returned before the error.
+///
+/// By default nothing is limited.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, DecompressError, DecompressLimit, DecompressLimits};
+/// use flate2::read::GzDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// let mut e = GzEncoder::new(Vec::new(), Compression::best());
+/// e.write_all(&[0; 1 << 20]).unwrap();
+/// let bytes = e.finish().unwrap();
+///
+/// let mut d = GzDecoder::new(&bytes[..]);
+/// d.set_limits(DecompressLimits::new().max_output(64 * 1024));
+/// let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+/// let err = err.get_ref().unwrap().downcast_ref::<DecompressError>().unwrap();
+/// assert_eq!(err.limit(), Some(DecompressLimit::Output));
+/// ```
+///
+/// [`read`]: read/index.html
+/// [`bufread`]: bufread/index.html
+/// [`write`]: write/index.html
+/// [`DecompressErrorKind::LimitExceeded`]: enum.DecompressErrorKind.html#variant.LimitExceeded
+/// [`max_output`]: #method.max_output
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DecompressLimits {
+    max_output: Option<u64>,
+    max_ratio: Option<u64>,
+    max_members: Option<u64>,
+}
+
+impl DecompressLimits {
+    /// Creates a new set of limits which doesn't limit anything.
+    pub fn new() -> DecompressLimits {
+        DecompressLimits::default()
+    }
+
+    /// Limits the total number of bytes of decompressed output.
+    pub fn max_output(mut self, bytes: u64) -> DecompressLimits {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    /// Limits the expansion ratio, the number of bytes of output per byte of
+    /// compressed input.
+    ///
+    /// The ratio is checked against the input consumed so far, so data which
+    /// compresses well may exceed it early in the stream even if it wouldn't
+    /// overall. Deflate can't expand data by more than a factor of about
+    /// 1032, so only lower ratios have any effect.
+    pub fn max_ratio(mut self, ratio: u64) -> DecompressLimits {
+        self.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Limits the number of gzip members a multi-member decoder will decode.
+    ///
+    /// This only applies to decoders which decode more than one member, such
+    /// as [`MultiGzDecoder`].
+    ///
+    /// [`MultiGzDecoder`]: read/struct.MultiGzDecoder.html
+    pub fn max_members(mut self, members: u64) -> DecompressLimits {
+        self.max_members = Some(members);
+        self
+    }
+
+    /// Shortens `dst` so that reading into it can't produce output beyond the
+    /// output limit, while leaving room for one byte more to find out whether
+    /// the limit is exceeded at all.
+    pub(crate) fn clamp<'a>(&self, total_out: u64, dst: &'a mut [u8]) -> &'a mut [u8] {
+        match self.max_output {
+            Some(max) => {
+                let room = max.saturating_sub(total_out).saturating_add(1);
+                if room < dst.len() as u64 {
+                    &mut dst[..room as usize]
+                } else {
+                    dst
+                }
+            }
+            None => dst,
+        }
+    }
+
+    /// Returns how many bytes of `total_out` lie beyond the output limit.
+    pub(crate) fn excess(&self, total_out: u64) -> u64 {
+        self.max_output
+            .map_or(0, |max| total_out.saturating_sub(max))
+    }
+
+    /// Fails if the given totals of a decoder exceed the output or ratio
+    /// limit.
+    pub(crate) fn check(&self, total_in: u64, total_out: u64) -> Result<(), DecompressError> {
+        if self.excess(total_out) > 0 {
+            return Err(decompress_limited(DecompressLimit::Output, total_in));
+        }
+        match self.max_ratio {
+            Some(ratio) if total_out > total_in.saturating_mul(ratio) => {
+                Err(decompress_limited(DecompressLimit::Ratio, total_in))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns how many of the `n` bytes a read just produced may be handed
+    /// out, failing if none of them are within the limits.
+    ///
+    /// A read which crosses the output limit still returns the bytes before
+    /// it, and the limit is only reported by the next read.
+    pub(crate) fn check_read(
+        &self,
+        total_in: u64,
+        total_out: u64,
+        n: usize,
+    ) -> Result<usize, DecompressError> {
+        let excess = self.excess(total_out);
+        if excess > 0 && excess < n as u64 {
+            let n = n - excess as usize;
+            self.check(total_in, total_out - excess)?;
+            return Ok(n);
+        }
+        self.check(total_in, total_out)?;
+        Ok(n)
+    }
+
+    /// Fails if starting member number `members` would exceed the member
+    /// limit.
+    pub(crate) fn check_members(&self, members: u64, offset: u64) -> Result<(), DecompressError> {
+        match self.max_members {
+            Some(max) if members > max => Err(decompress_limited(DecompressLimit::Members, offset)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The limits of [`DecompressLimits`], used to report which one a decoder
+/// exceeded.
+///
+/// [`DecompressLimits`]: struct.DecompressLimits.html
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DecompressLimit {
+    /// The total size of the output.
+    Output,
+    /// The ratio of output to input.
+    Ratio,
+    /// The number of gzip members.
+    Members,
+}
+
+impl fmt::Display for DecompressLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DecompressLimit::Output => "output size",
+            DecompressLimit::Ratio => "expansion ratio",
+            DecompressLimit::Members => "member count",
+        };
+        f.write_str(name)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 /// Values which indicate the form of flushing to be used when
 /// decompressing in-memory data.
 pub enum FlushDecompress {
@@ -109,21 +463,92 @@ pub enum FlushDecompress {
     /// data has yet to be processed.
     Finish = ffi::MZ_FINISH as isize,
 
-    #[doc(hidden)]
-    _Nonexhaustive,
+    /// Decompression stops at the end of the current deflate block, or right
+    /// after the header of a zlib or gzip stream, to let the caller keep track
+    /// of where blocks begin.
+    ///
+    /// After each call [`Decompress::at_block_boundary`] tells whether it
+    /// stopped at a boundary, and [`Decompress::last_block`] whether the final
+    /// block of the stream has been reached.
+    ///
+    /// [`Decompress::at_block_boundary`]: struct.Decompress.html#method.at_block_boundary
+    /// [`Decompress::last_block`]: struct.Decompress.html#method.last_block
+    Block = ffi::MZ_BLOCK as isize,
 }
 
 /// The inner state for an error when decompressing
 #[derive(Debug)]
 pub(crate) enum DecompressErrorInner {
-    General { msg: ErrorMessage },
+    General {
+        msg: ErrorMessage,
+        kind: DecompressErrorKind,
+    },
     NeedsDictionary(u32),
+    Truncated,
+    LimitExceeded(DecompressLimit),
 }
 
 /// Error returned when a decompression object finds that the input stream of
 /// bytes was not a valid input stream of bytes.
 #[derive(Debug)]
-pub struct DecompressError(pub(crate) DecompressErrorInner);
+pub struct DecompressError {
+    pub(crate) inner: DecompressErrorInner,
+    offset: u64,
+}
+
+/// The reason decompression failed, as returned by [`DecompressError::kind`].
+///
+/// [`DecompressError::kind`]: struct.DecompressError.html#method.kind
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DecompressErrorKind {
+    /// The zlib or gzip header is malformed, such as one whose check bits are
+    /// wrong or which names an unknown compression method.
+    InvalidHeader,
+
+    /// A deflate block header uses the reserved block type.
+    InvalidBlockType,
+
+    /// The Huffman code description of a dynamic deflate block is invalid,
+    /// such as code lengths which don't make up a complete code.
+    InvalidHuffmanTable,
+
+    /// A distance code is invalid, or a distance refers back further than the
+    /// output or the window reaches.
+    InvalidDistance,
+
+    /// The compressed data is corrupt in some other way, such as a stored
+    /// block whose length doesn't match its complement.
+    InvalidData,
+
+    /// The checksum or length in the zlib or gzip trailer doesn't match the
+    /// decompressed data.
+    ChecksumMismatch,
+
+    /// The input ended before the end of the stream.
+    ///
+    /// `Decompress` itself can't tell whether more input is coming, and the
+    /// readers and writers of this crate end their output like the input for
+    /// raw deflate and zlib streams. So this is only reported once the input
+    /// ends while a reader skips over corrupt data in recovering mode.
+    Truncated,
+
+    /// The stream requires a dictionary, see
+    /// [`DecompressError::needs_dictionary`].
+    ///
+    /// [`DecompressError::needs_dictionary`]: struct.DecompressError.html#method.needs_dictionary
+    NeedsDictionary,
+
+    /// One of the [`DecompressLimits`] of a decoder was exceeded, see
+    /// [`DecompressError::limit`].
+    ///
+    /// [`DecompressLimits`]: struct.DecompressLimits.html
+    /// [`DecompressError::limit`]: struct.DecompressError.html#method.limit
+    LimitExceeded,
+
+    /// Any other error, such as a method called at the wrong time.
+    Other,
+}
 
 impl DecompressError {
     /// Indicates whether decompression failed due to requiring a dictionary.
@@ -131,35 +556,140 @@ impl DecompressError {
     /// The resulting integer is the Adler-32 checksum of the dictionary
     /// required.
     pub fn needs_dictionary(&self) -> Option<u32> {
-        match self.0 {
+        match self.inner {
             DecompressErrorInner::NeedsDictionary(adler) => Some(adler),
             _ => None,
         }
     }
+
+    /// Indicates whether a decoder failed because it exceeded one of its
+    /// [`DecompressLimits`], and which one.
+    ///
+    /// [`DecompressLimits`]: struct.DecompressLimits.html
+    pub fn limit(&self) -> Option<DecompressLimit> {
+        match self.inner {
+            DecompressErrorInner::LimitExceeded(limit) => Some(limit),
+            _ => None,
+        }
+    }
+
+    /// Returns why decompression failed.
+    pub fn kind(&self) -> DecompressErrorKind {
+        match &self.inner {
+            DecompressErrorInner::General { kind, .. } => *kind,
+            DecompressErrorInner::NeedsDictionary(_) => DecompressErrorKind::NeedsDictionary,
+            DecompressErrorInner::Truncated => DecompressErrorKind::Truncated,
+            DecompressErrorInner::LimitExceeded(_) => DecompressErrorKind::LimitExceeded,
+        }
+    }
+
+    /// Returns the number of bytes of compressed input which had been consumed
+    /// when the error was found, counted from the start of the stream like
+    /// [`Decompress::total_in`].
+    ///
+    /// Corrupt data is usually found in the byte right before this offset, but
+    /// as a Huffman code can only be found invalid once enough of it has been
+    /// read, the actual corruption may lie a few bytes earlier.
+    ///
+    /// [`Decompress::total_in`]: struct.Decompress.html#method.total_in
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Records the offset of an error returned by a backend, which doesn't
+    /// know it.
+    pub(crate) fn at(mut self, offset: u64) -> DecompressError {
+        self.offset = offset;
+        self
+    }
 }
 
 #[inline]
-pub(crate) fn decompress_failed<T>(msg: ErrorMessage) -> Result<T, DecompressError> {
-    Err(DecompressError(DecompressErrorInner::General { msg }))
+pub(crate) fn decompress_failed<T>(
+    kind: DecompressErrorKind,
+    msg: ErrorMessage,
+) -> Result<T, DecompressError> {
+    Err(DecompressError {
+        inner: DecompressErrorInner::General { msg, kind },
+        offset: 0,
+    })
 }
 
 #[inline]
 pub(crate) fn decompress_need_dict<T>(adler: u32) -> Result<T, DecompressError> {
-    Err(DecompressError(DecompressErrorInner::NeedsDictionary(
-        adler,
-    )))
+    Err(DecompressError {
+        inner: DecompressErrorInner::NeedsDictionary(adler),
+        offset: 0,
+    })
+}
+
+#[inline]
+pub(crate) fn decompress_truncated(offset: u64) -> DecompressError {
+    DecompressError {
+        inner: DecompressErrorInner::Truncated,
+        offset,
+    }
+}
+
+#[inline]
+pub(crate) fn decompress_limited(limit: DecompressLimit, offset: u64) -> DecompressError {
+    DecompressError {
+        inner: DecompressErrorInner::LimitExceeded(limit),
+        offset,
+    }
+}
+
+/// The inner state for an error when compressing
+#[derive(Debug)]
+pub(crate) enum CompressErrorInner {
+    General {
+        msg: ErrorMessage,
+    },
+    UnsupportedOption(CompressOption),
+    // Only the miniz_oxide backend can't always copy its state.
+    #[cfg_attr(feature = "any_zlib", allow(dead_code))]
+    Unavailable,
 }
 
 /// Error returned when a compression object is used incorrectly or otherwise
 /// generates an error.
 #[derive(Debug)]
-pub struct CompressError {
-    pub(crate) msg: ErrorMessage,
+pub struct CompressError(pub(crate) CompressErrorInner);
+
+impl CompressError {
+    /// Indicates whether creating a compression object failed because one of
+    /// its options is out of range or not supported by the backend.
+    pub fn unsupported_option(&self) -> Option<CompressOption> {
+        match self.0 {
+            CompressErrorInner::UnsupportedOption(option) => Some(option),
+            _ => None,
+        }
+    }
 }
 
 #[inline]
 pub(crate) fn compress_failed<T>(msg: ErrorMessage) -> Result<T, CompressError> {
-    Err(CompressError { msg })
+    Err(CompressError(CompressErrorInner::General { msg }))
+}
+
+#[inline]
+pub(crate) fn compress_unsupported<T>(option: CompressOption) -> Result<T, CompressError> {
+    Err(CompressError(CompressErrorInner::UnsupportedOption(option)))
+}
+
+#[inline]
+#[cfg_attr(feature = "any_zlib", allow(dead_code))]
+pub(crate) fn compress_unavailable<T>() -> Result<T, CompressError> {
+    Err(CompressError(CompressErrorInner::Unavailable))
+}
+
+/// Maps the `zlib_header` flag taken by the older constructors to a format.
+fn zlib_format(zlib_header: bool) -> Format {
+    if zlib_header {
+        Format::Zlib
+    } else {
+        Format::Raw
+    }
 }
 
 /// Possible status results of compressing some data or successfully
@@ -191,16 +721,120 @@ pub enum Status {
     StreamEnd,
 }
 
+/// The position of a decompressor within the deflate stream, as returned by
+/// [`Decompress::mark`].
+///
+/// [`Decompress::mark`]: struct.Decompress.html#method.mark
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecompressMark {
+    /// Not inside a block, such as in the zlib or gzip header, in the header
+    /// of a block or between two blocks.
+    OutsideBlock,
+
+    /// Inside a stored block, `remaining` bytes of which are still to be
+    /// copied.
+    Stored {
+        /// The number of bytes of the block still to be copied.
+        remaining: usize,
+    },
+
+    /// Inside a compressed block, possibly partway through writing out the
+    /// output of a literal or a length/distance pair.
+    Code {
+        /// The number of bits before the current bit position in the input
+        /// at which the code being written out starts.
+        bits_back: u32,
+
+        /// The number of bytes of output of that code already produced.
+        emitted: usize,
+    },
+}
+
 impl Compress {
     /// Creates a new object ready for compressing data that it's given.
     ///
     /// The `level` argument here indicates what level of compression is going
     /// to be performed, and the `zlib_header` argument indicates whether the
     /// output data should have a zlib header or not.
+    ///
+    /// # Panics
+    ///
+    /// If `level` isn't supported by the backend in use or the backend fails
+    /// to set up its state, `new` will panic. Use [`try_new`] to handle that
+    /// instead.
+    ///
+    /// [`try_new`]: #method.try_new
     pub fn new(level: Compression, zlib_header: bool) -> Compress {
-        Compress {
-            inner: Deflate::make(level, zlib_header, ffi::MZ_DEFAULT_WINDOW_BITS as u8),
+        Compress::new_with_strategy(level, zlib_header, CompressionStrategy::Default)
+    }
+
+    /// Creates a new object ready for compressing data that it's given,
+    /// returning an error instead of panicking if that isn't possible.
+    ///
+    /// The arguments are the same as for [`new`]. Use
+    /// [`new_with_options`] to pick a window size or the gzip format without
+    /// risking a panic.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't supported by the backend in use or
+    /// if the backend fails to set up its state, for example because it ran
+    /// out of memory.
+    ///
+    /// [`new`]: #method.new
+    /// [`new_with_options`]: #method.new_with_options
+    pub fn try_new(level: Compression, zlib_header: bool) -> Result<Compress, CompressError> {
+        Compress::new_with_options(CompressOptions::new(level).format(zlib_format(zlib_header)))
+    }
+
+    /// Creates a new object ready for compressing data that it's given.
+    ///
+    /// The `level` argument here indicates what level of compression is going
+    /// to be performed, and the `zlib_header` argument indicates whether the
+    /// output data should have a zlib header or not. The `strategy` argument
+    /// tunes the compression algorithm for the kind of data being compressed.
+    ///
+    /// # Panics
+    ///
+    /// If `level` isn't supported by the backend in use or the backend fails
+    /// to set up its state, `new_with_strategy` will panic. Use
+    /// [`new_with_options`] to handle that instead.
+    ///
+    /// [`new_with_options`]: #method.new_with_options
+    pub fn new_with_strategy(
+        level: Compression,
+        zlib_header: bool,
+        strategy: CompressionStrategy,
+    ) -> Compress {
+        let options = CompressOptions::new(level)
+            .format(zlib_format(zlib_header))
+            .strategy(strategy);
+        Compress::new_with_options(options).unwrap()
+    }
+
+    /// Creates a new object ready for compressing data with the given options.
+    ///
+    /// This gives access to the full set of parameters supported by zlib's
+    /// `deflateInit2`, see [`CompressOptions`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the options is out of range or can't be
+    /// honoured by the backend in use. The offending option is available
+    /// through [`CompressError::unsupported_option`].
+    ///
+    /// [`CompressOptions`]: struct.CompressOptions.html
+    /// [`CompressError::unsupported_option`]: struct.CompressError.html#method.unsupported_option
+    pub fn new_with_options(options: CompressOptions) -> Result<Compress, CompressError> {
+        if options.window_bits < 9 || options.window_bits > 15 {
+            return compress_unsupported(CompressOption::WindowBits);
+        }
+        if options.mem_level < 1 || options.mem_level > 9 {
+            return compress_unsupported(CompressOption::MemLevel);
         }
+        Ok(Compress {
+            inner: Deflate::make(&options)?,
+        })
     }
 
     /// Creates a new object ready for compressing data that it's given.
@@ -214,13 +848,17 @@ impl Compress {
     /// # Panics
     ///
     /// If `window_bits` does not fall into the range 9 ..= 15,
-    /// `new_with_window_bits` will panic.
+    /// `new_with_window_bits` will panic. It also panics where [`new`] does.
+    /// Use [`try_new_with_window_bits`] to handle either instead.
     ///
     /// # Note
     ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support custom window bits.
-    #[cfg(feature = "any_zlib")]
+    /// See [`CompressOptions::window_bits`] for how windows smaller than
+    /// 32KiB are handled by the pure-Rust backend.
+    ///
+    /// [`new`]: #method.new
+    /// [`try_new_with_window_bits`]: #method.try_new_with_window_bits
+    /// [`CompressOptions::window_bits`]: struct.CompressOptions.html#method.window_bits
     pub fn new_with_window_bits(
         level: Compression,
         zlib_header: bool,
@@ -230,9 +868,30 @@ impl Compress {
             window_bits > 8 && window_bits < 16,
             "window_bits must be within 9 ..= 15"
         );
-        Compress {
-            inner: Deflate::make(level, zlib_header, window_bits),
-        }
+        Compress::try_new_with_window_bits(level, zlib_header, window_bits).unwrap()
+    }
+
+    /// Creates a new object ready for compressing data that it's given,
+    /// returning an error instead of panicking if that isn't possible.
+    ///
+    /// The arguments are the same as for [`new_with_window_bits`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `window_bits` does not fall into the range
+    /// 9 ..= 15, or for the same reasons as [`try_new`].
+    ///
+    /// [`new_with_window_bits`]: #method.new_with_window_bits
+    /// [`try_new`]: #method.try_new
+    pub fn try_new_with_window_bits(
+        level: Compression,
+        zlib_header: bool,
+        window_bits: u8,
+    ) -> Result<Compress, CompressError> {
+        let options = CompressOptions::new(level)
+            .format(zlib_format(zlib_header))
+            .window_bits(window_bits);
+        Compress::new_with_options(options)
     }
 
     /// Creates a new object ready for compressing data that it's given.
@@ -245,22 +904,40 @@ impl Compress {
     ///
     /// # Panics
     ///
-    /// If `window_bits` does not fall into the range 9 ..= 15,
-    /// `new_with_window_bits` will panic.
+    /// If `window_bits` does not fall into the range 9 ..= 15, `new_gzip`
+    /// will panic. It also panics where [`new`] does. Use [`try_new_gzip`]
+    /// to handle either instead.
     ///
     /// # Note
     ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support gzip headers for Compress.
-    #[cfg(feature = "any_zlib")]
+    /// See [`new_with_window_bits`] for how windows smaller than 32KiB are
+    /// handled by the pure-Rust backend.
+    ///
+    /// [`new`]: #method.new
+    /// [`try_new_gzip`]: #method.try_new_gzip
+    /// [`new_with_window_bits`]: #method.new_with_window_bits
     pub fn new_gzip(level: Compression, window_bits: u8) -> Compress {
         assert!(
             window_bits > 8 && window_bits < 16,
             "window_bits must be within 9 ..= 15"
         );
-        Compress {
-            inner: Deflate::make(level, true, window_bits + 16),
-        }
+        Compress::try_new_gzip(level, window_bits).unwrap()
+    }
+
+    /// Creates a new object ready for compressing data that it's given in
+    /// the gzip format, returning an error instead of panicking if that isn't
+    /// possible.
+    ///
+    /// The arguments are the same as for [`new_gzip`], and the errors the
+    /// same as for [`try_new_with_window_bits`].
+    ///
+    /// [`new_gzip`]: #method.new_gzip
+    /// [`try_new_with_window_bits`]: #method.try_new_with_window_bits
+    pub fn try_new_gzip(level: Compression, window_bits: u8) -> Result<Compress, CompressError> {
+        let options = CompressOptions::new(level)
+            .format(Format::Gzip)
+            .window_bits(window_bits);
+        Compress::new_with_options(options)
     }
 
     /// Returns the total number of input bytes which have been processed by
@@ -278,20 +955,68 @@ impl Compress {
     /// Specifies the compression dictionary to use.
     ///
     /// Returns the Adler-32 checksum of the dictionary.
-    #[cfg(feature = "any_zlib")]
+    ///
+    /// For zlib streams this has to be called before any data is compressed,
+    /// and the dictionary is announced in the zlib header. Raw deflate streams
+    /// can also be given a dictionary after a flush on the zlib backends, and
+    /// gzip streams don't support dictionaries at all.
     pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
-        let stream = &mut *self.inner.inner.stream_wrapper;
-        stream.msg = std::ptr::null_mut();
-        let rc = unsafe {
-            assert!(dictionary.len() < ffi::uInt::MAX as usize);
-            ffi::deflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as ffi::uInt)
-        };
+        self.inner.set_dictionary(dictionary)
+    }
 
-        match rc {
-            ffi::MZ_STREAM_ERROR => compress_failed(self.inner.inner.msg()),
-            ffi::MZ_OK => Ok(stream.adler as u32),
-            c => panic!("unknown return code: {}", c),
-        }
+    /// Returns the data the compressor can currently refer back to, which is
+    /// at most the last window size worth of the data given to it, including
+    /// any dictionary.
+    ///
+    /// This is the equivalent of zlib's `deflateGetDictionary`. Passing it to
+    /// [`set_dictionary`] of another compressor lets that continue where this
+    /// one left off.
+    ///
+    /// The zlib backends require zlib 1.2.9 or later for this. The
+    /// miniz_oxide backend returns the input it keeps to recreate its state
+    /// for [`try_clone`].
+    ///
+    /// [`set_dictionary`]: #method.set_dictionary
+    /// [`try_clone`]: #method.try_clone
+    pub fn get_dictionary(&self) -> Result<Vec<u8>, CompressError> {
+        self.inner.get_dictionary()
+    }
+
+    /// Inserts `bits` bits of `value` into the output ahead of the compressed
+    /// data, starting with the least significant bit.
+    ///
+    /// This is the equivalent of zlib's `deflatePrime`, and is useful to
+    /// continue a deflate stream that ends partway through a byte, such as
+    /// when appending to an existing stream. At most 16 bits can be inserted
+    /// at a time.
+    ///
+    /// The miniz_oxide backend only supports this for raw deflate streams
+    /// before any data has been compressed. When the number of bits inserted
+    /// is not a multiple of eight, it follows them with an empty stored block
+    /// to get back to a byte boundary, so its output differs from zlib's but
+    /// decompresses to the same data.
+    pub fn prime(&mut self, bits: u8, value: u16) -> Result<(), CompressError> {
+        self.inner.prime(bits, value)
+    }
+
+    /// Creates a copy of this compressor in the same state, which continues
+    /// the same stream independently of it.
+    ///
+    /// The miniz_oxide backend's state can't be copied, only recreated from
+    /// the last 32KiB of input, which it keeps for that. So there this fails
+    /// unless no data has been compressed yet, apart from any dictionary, or
+    /// the last call to `compress` which was given data ended with
+    /// [`FlushCompress::Sync`] or [`FlushCompress::Full`] and had room for
+    /// all of its output. The same goes for [`get_dictionary`] on that
+    /// backend.
+    ///
+    /// [`FlushCompress::Sync`]: enum.FlushCompress.html#variant.Sync
+    /// [`FlushCompress::Full`]: enum.FlushCompress.html#variant.Full
+    /// [`get_dictionary`]: #method.get_dictionary
+    pub fn try_clone(&self) -> Result<Compress, CompressError> {
+        Ok(Compress {
+            inner: self.inner.try_clone()?,
+        })
     }
 
     /// Quickly resets this compressor without having to reallocate anything.
@@ -311,18 +1036,21 @@ impl Compress {
     /// the compression of the available input data before changing the
     /// compression level. Flushing the stream before calling this method
     /// ensures that the function will succeed on the first call.
+    ///
+    /// The compression strategy this object was created with is kept.
     #[cfg(feature = "any_zlib")]
     pub fn set_level(&mut self, level: Compression) -> Result<(), CompressError> {
         use std::os::raw::c_int;
+        let strategy = self.inner.strategy;
         let stream = &mut *self.inner.inner.stream_wrapper;
         stream.msg = std::ptr::null_mut();
 
-        let rc = unsafe { ffi::deflateParams(stream, level.0 as c_int, ffi::MZ_DEFAULT_STRATEGY) };
+        let rc = unsafe { ffi::deflateParams(stream, level.0 as c_int, strategy) };
 
         match rc {
             ffi::MZ_OK => Ok(()),
             ffi::MZ_BUF_ERROR => compress_failed(self.inner.inner.msg()),
-            c => panic!("unknown return code: {}", c),
+            c => compress_failed(self.inner.inner.error(c)),
         }
     }
 
@@ -372,15 +1100,48 @@ impl Compress {
     }
 }
 
+/// Only the zlib backends can always copy the state of a compressor.
+///
+/// # Panics
+///
+/// Panics if zlib fails to allocate memory for the copy.
+#[cfg(feature = "any_zlib")]
+impl Clone for Compress {
+    fn clone(&self) -> Compress {
+        self.try_clone()
+            .expect("failed to copy the compressor state")
+    }
+}
+
 impl Decompress {
     /// Creates a new object ready for decompressing data that it's given.
     ///
     /// The `zlib_header` argument indicates whether the input data is expected
     /// to have a zlib header or not.
+    ///
+    /// # Panics
+    ///
+    /// If the backend fails to set up its state, `new` will panic. Use
+    /// [`try_new`] to handle that instead.
+    ///
+    /// [`try_new`]: #method.try_new
     pub fn new(zlib_header: bool) -> Decompress {
-        Decompress {
-            inner: Inflate::make(zlib_header, ffi::MZ_DEFAULT_WINDOW_BITS as u8),
-        }
+        Decompress::new_with_format(zlib_format(zlib_header), ffi::MZ_DEFAULT_WINDOW_BITS as u8)
+    }
+
+    /// Creates a new object ready for decompressing data that it's given,
+    /// returning an error instead of panicking if that isn't possible.
+    ///
+    /// The `zlib_header` argument is the same as for [`new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend fails to set up its state, for example
+    /// because it ran out of memory.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(zlib_header: bool) -> Result<Decompress, DecompressError> {
+        Decompress::try_new_with_format(zlib_format(zlib_header), ffi::MZ_DEFAULT_WINDOW_BITS as u8)
     }
 
     /// Creates a new object ready for decompressing data that it's given.
@@ -388,25 +1149,34 @@ impl Decompress {
     /// The `zlib_header` argument indicates whether the input data is expected
     /// to have a zlib header or not. The `window_bits` parameter indicates the
     /// base-2 logarithm of the sliding window size and must be between 9 and 15.
+    /// Streams with a zlib header announcing a larger window than this fail
+    /// to decompress.
     ///
     /// # Panics
     ///
     /// If `window_bits` does not fall into the range 9 ..= 15,
-    /// `new_with_window_bits` will panic.
+    /// `new_with_window_bits` will panic. It also panics where [`new`] does.
+    /// Use [`try_new_with_window_bits`] to handle either instead.
     ///
-    /// # Note
-    ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support custom window bits.
-    #[cfg(feature = "any_zlib")]
+    /// [`new`]: #method.new
+    /// [`try_new_with_window_bits`]: #method.try_new_with_window_bits
     pub fn new_with_window_bits(zlib_header: bool, window_bits: u8) -> Decompress {
-        assert!(
-            window_bits > 8 && window_bits < 16,
-            "window_bits must be within 9 ..= 15"
-        );
-        Decompress {
-            inner: Inflate::make(zlib_header, window_bits),
-        }
+        Decompress::new_with_format(zlib_format(zlib_header), window_bits)
+    }
+
+    /// Creates a new object ready for decompressing data that it's given,
+    /// returning an error instead of panicking if that isn't possible.
+    ///
+    /// The arguments are the same as for [`new_with_window_bits`], and the
+    /// errors the same as for [`try_new_with_format`].
+    ///
+    /// [`new_with_window_bits`]: #method.new_with_window_bits
+    /// [`try_new_with_format`]: #method.try_new_with_format
+    pub fn try_new_with_window_bits(
+        zlib_header: bool,
+        window_bits: u8,
+    ) -> Result<Decompress, DecompressError> {
+        Decompress::try_new_with_format(zlib_format(zlib_header), window_bits)
     }
 
     /// Creates a new object ready for decompressing data that it's given.
@@ -416,22 +1186,115 @@ impl Decompress {
     ///
     /// # Panics
     ///
+    /// If `window_bits` does not fall into the range 9 ..= 15, `new_gzip`
+    /// will panic. It also panics where [`new`] does. Use [`try_new_gzip`]
+    /// to handle either instead.
+    ///
+    /// [`new`]: #method.new
+    /// [`try_new_gzip`]: #method.try_new_gzip
+    pub fn new_gzip(window_bits: u8) -> Decompress {
+        Decompress::new_with_format(Format::Gzip, window_bits)
+    }
+
+    /// Creates a new object ready for decompressing a gzip stream, returning
+    /// an error instead of panicking if that isn't possible.
+    ///
+    /// The arguments are the same as for [`new_gzip`], and the errors the
+    /// same as for [`try_new_with_format`].
+    ///
+    /// [`new_gzip`]: #method.new_gzip
+    /// [`try_new_with_format`]: #method.try_new_with_format
+    pub fn try_new_gzip(window_bits: u8) -> Result<Decompress, DecompressError> {
+        Decompress::try_new_with_format(Format::Gzip, window_bits)
+    }
+
+    /// Creates a new object ready for decompressing data in the given
+    /// container format.
+    ///
+    /// The `window_bits` parameter indicates the base-2 logarithm of the
+    /// sliding window size and must be between 9 and 15.
+    ///
+    /// # Panics
+    ///
     /// If `window_bits` does not fall into the range 9 ..= 15,
-    /// `new_with_window_bits` will panic.
+    /// `new_with_format` will panic. It also panics where [`new`] does. Use
+    /// [`try_new_with_format`] to handle either instead.
     ///
-    /// # Note
+    /// [`new`]: #method.new
+    /// [`try_new_with_format`]: #method.try_new_with_format
+    pub fn new_with_format(format: Format, window_bits: u8) -> Decompress {
+        assert!(
+            window_bits > 8 && window_bits < 16,
+            "window_bits must be within 9 ..= 15"
+        );
+        Decompress::try_new_with_format(format, window_bits).unwrap()
+    }
+
+    /// Creates a new object ready for decompressing data in the given
+    /// container format, returning an error instead of panicking if that
+    /// isn't possible.
     ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support gzip headers for Decompress.
-    #[cfg(feature = "any_zlib")]
-    pub fn new_gzip(window_bits: u8) -> Decompress {
+    /// The arguments are the same as for [`new_with_format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `window_bits` does not fall into the range
+    /// 9 ..= 15 or if the backend fails to set up its state, for example
+    /// because it ran out of memory.
+    ///
+    /// [`new_with_format`]: #method.new_with_format
+    pub fn try_new_with_format(
+        format: Format,
+        window_bits: u8,
+    ) -> Result<Decompress, DecompressError> {
+        Decompress::make(Some(format), window_bits)
+    }
+
+    /// Creates a new object ready for decompressing either a zlib or a gzip
+    /// stream, detected from the first byte of the input.
+    ///
+    /// The `window_bits` parameter indicates the base-2 logarithm of the
+    /// sliding window size and must be between 9 and 15.
+    ///
+    /// # Panics
+    ///
+    /// If `window_bits` does not fall into the range 9 ..= 15,
+    /// `new_auto` will panic. It also panics where [`new`] does. Use
+    /// [`try_new_auto`] to handle either instead.
+    ///
+    /// [`new`]: #method.new
+    /// [`try_new_auto`]: #method.try_new_auto
+    pub fn new_auto(window_bits: u8) -> Decompress {
         assert!(
             window_bits > 8 && window_bits < 16,
             "window_bits must be within 9 ..= 15"
         );
-        Decompress {
-            inner: Inflate::make(true, window_bits + 16),
+        Decompress::try_new_auto(window_bits).unwrap()
+    }
+
+    /// Creates a new object ready for decompressing either a zlib or a gzip
+    /// stream, returning an error instead of panicking if that isn't
+    /// possible.
+    ///
+    /// The arguments are the same as for [`new_auto`], and the errors the same
+    /// as for [`try_new_with_format`].
+    ///
+    /// [`new_auto`]: #method.new_auto
+    /// [`try_new_with_format`]: #method.try_new_with_format
+    pub fn try_new_auto(window_bits: u8) -> Result<Decompress, DecompressError> {
+        Decompress::make(None, window_bits)
+    }
+
+    fn make(format: Option<Format>, window_bits: u8) -> Result<Decompress, DecompressError> {
+        if !(9..=15).contains(&window_bits) {
+            return decompress_failed(
+                DecompressErrorKind::Other,
+                ErrorMessage(Some("window bits must be within 9 ..= 15")),
+            );
         }
+        Ok(Decompress {
+            inner: Inflate::make(format, window_bits)?,
+        })
     }
 
     /// Returns the total number of input bytes which have been processed by
@@ -474,7 +1337,9 @@ impl Decompress {
         output: &mut [u8],
         flush: FlushDecompress,
     ) -> Result<Status, DecompressError> {
-        self.inner.decompress(input, output, flush)
+        self.inner
+            .decompress(input, output, flush)
+            .map_err(|e| e.at(self.inner.total_in()))
     }
 
     /// Decompresses the input data into the extra space in the output vector
@@ -512,22 +1377,132 @@ impl Decompress {
         }
     }
 
+    /// Skips input up to the next full flush point so that decompression can
+    /// resume after corrupt data.
+    ///
+    /// This is the equivalent of zlib's `inflateSync`. The input is scanned
+    /// for the `00 00 FF FF` bytes which end the empty stored block written by
+    /// [`FlushCompress::Full`], and the number of bytes skipped is reflected
+    /// in [`total_in`]. Returns `Status::Ok` once the marker has been found,
+    /// after which `decompress` continues with the data following it. Returns
+    /// `Status::BufError` if `input` did not contain the marker; all of it was
+    /// consumed and `sync` should be called again with more input.
+    ///
+    /// The checksum in a zlib or gzip trailer is no longer verified after a
+    /// successful sync. The marker may also occur by chance in compressed
+    /// data, in which case decompression fails again soon after.
+    ///
+    /// [`FlushCompress::Full`]: enum.FlushCompress.html#variant.Full
+    /// [`total_in`]: #method.total_in
+    pub fn sync(&mut self, input: &[u8]) -> Result<Status, DecompressError> {
+        self.inner
+            .sync(input)
+            .map_err(|e| e.at(self.inner.total_in()))
+    }
+
     /// Specifies the decompression dictionary to use.
-    #[cfg(feature = "any_zlib")]
+    ///
+    /// Returns the Adler-32 checksum of the dictionary.
+    ///
+    /// For zlib streams this has to be called after `decompress` returned an
+    /// error for which [`DecompressError::needs_dictionary`] is set, and fails
+    /// the same way if the dictionary doesn't match the one the stream was
+    /// compressed with. Raw deflate streams can be given a dictionary at any
+    /// time.
+    ///
+    /// [`DecompressError::needs_dictionary`]: struct.DecompressError.html#method.needs_dictionary
     pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
-        let stream = &mut *self.inner.inner.stream_wrapper;
-        stream.msg = std::ptr::null_mut();
-        let rc = unsafe {
-            assert!(dictionary.len() < ffi::uInt::MAX as usize);
-            ffi::inflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as ffi::uInt)
-        };
+        self.inner
+            .set_dictionary(dictionary)
+            .map_err(|e| e.at(self.inner.total_in()))
+    }
 
-        match rc {
-            ffi::MZ_STREAM_ERROR => decompress_failed(self.inner.inner.msg()),
-            ffi::MZ_DATA_ERROR => decompress_need_dict(stream.adler as u32),
-            ffi::MZ_OK => Ok(stream.adler as u32),
-            c => panic!("unknown return code: {}", c),
-        }
+    /// Returns the decompressed data the stream can currently refer back to,
+    /// which is at most the last window size worth of output, including any
+    /// dictionary.
+    ///
+    /// This is the equivalent of zlib's `inflateGetDictionary`. Passing it to
+    /// [`set_dictionary`] of another decompressor lets that continue where
+    /// this one left off.
+    ///
+    /// The zlib backends require zlib 1.2.9 or later for this.
+    ///
+    /// [`set_dictionary`]: #method.set_dictionary
+    pub fn get_dictionary(&self) -> Result<Vec<u8>, DecompressError> {
+        self.inner.get_dictionary()
+    }
+
+    /// Returns whether the last call to `decompress` stopped right at the end
+    /// of a deflate block, or right after the header of a zlib or gzip stream.
+    ///
+    /// This is what decompressing with [`FlushDecompress::Block`] stops at.
+    /// The input up to here can be resumed from with [`prime`], giving it the
+    /// [`unused_bits`] of the last input byte, and [`set_dictionary`], giving
+    /// it the last 32KiB of output.
+    ///
+    /// [`FlushDecompress::Block`]: enum.FlushDecompress.html#variant.Block
+    /// [`prime`]: #method.prime
+    /// [`unused_bits`]: #method.unused_bits
+    /// [`set_dictionary`]: #method.set_dictionary
+    pub fn at_block_boundary(&self) -> bool {
+        self.inner.at_block_boundary()
+    }
+
+    /// Returns whether the block being decompressed, or the one which just
+    /// ended, is the final block of the deflate stream.
+    pub fn last_block(&self) -> bool {
+        self.inner.last_block()
+    }
+
+    /// Returns the number of bits of the input consumed so far which have not
+    /// been decoded yet.
+    ///
+    /// At a block boundary these are the high bits of the last input byte,
+    /// which are where the next block starts.
+    pub fn unused_bits(&self) -> u8 {
+        self.inner.unused_bits()
+    }
+
+    /// Inserts `bits` bits of `value` into the input ahead of the data passed
+    /// to the next call to `decompress`, starting with the least significant
+    /// bit.
+    ///
+    /// This is the equivalent of zlib's `inflatePrime`, and together with
+    /// [`mark`] and [`set_dictionary`] allows resuming decompression partway
+    /// through a byte of a raw deflate stream. At most 16 bits can be inserted
+    /// at a time, and they are not counted in [`total_in`].
+    ///
+    /// The miniz_oxide backend only supports this once any zlib or gzip header
+    /// has been read.
+    ///
+    /// [`mark`]: #method.mark
+    /// [`set_dictionary`]: #method.set_dictionary
+    /// [`total_in`]: #method.total_in
+    pub fn prime(&mut self, bits: u8, value: u16) -> Result<(), DecompressError> {
+        self.inner
+            .prime(bits, value)
+            .map_err(|e| e.at(self.inner.total_in()))
+    }
+
+    /// Returns where decompression is within the current deflate block.
+    ///
+    /// This is the equivalent of zlib's `inflateMark`. It can be used after a
+    /// call to `decompress` to find points at which the stream can later be
+    /// resumed from, as an index for random access does.
+    pub fn mark(&self) -> DecompressMark {
+        self.inner.mark()
+    }
+
+    /// Creates a copy of this decompressor in the same state, which continues
+    /// the same stream independently of it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the zlib backends can't allocate memory for the copy.
+    pub fn try_clone(&self) -> Result<Decompress, DecompressError> {
+        Ok(Decompress {
+            inner: self.inner.try_clone()?,
+        })
     }
 
     /// Performs the equivalent of replacing this decompression state with a
@@ -539,7 +1514,39 @@ impl Decompress {
     /// The argument provided here indicates whether the reset state will
     /// attempt to decode a zlib header first or not.
     pub fn reset(&mut self, zlib_header: bool) {
-        self.inner.reset(zlib_header);
+        self.inner.reset(Some(zlib_format(zlib_header)));
+    }
+
+    /// Performs the equivalent of replacing this decompression state with a
+    /// freshly allocated copy expecting the given container format.
+    pub fn reset_with_format(&mut self, format: Format) {
+        self.inner.reset(Some(format));
+    }
+
+    /// Performs the equivalent of replacing this decompression state with a
+    /// freshly allocated copy detecting a zlib or gzip stream, like one
+    /// created with [`new_auto`].
+    ///
+    /// [`new_auto`]: #method.new_auto
+    pub fn reset_auto(&mut self) {
+        self.inner.reset(None);
+    }
+
+    /// Returns whether the end of the stream has been reached, for the
+    /// decoders which accept input ending right after it.
+    pub(crate) fn is_finished(&mut self) -> bool {
+        let status = self.inner.decompress(&[], &mut [], FlushDecompress::Finish);
+        matches!(status, Ok(Status::StreamEnd))
+    }
+}
+
+/// # Panics
+///
+/// Panics if the zlib backends fail to allocate memory for the copy.
+impl Clone for Decompress {
+    fn clone(&self) -> Decompress {
+        self.try_clone()
+            .expect("failed to copy the decompressor state")
     }
 }
 
@@ -548,8 +1555,8 @@ impl Error for DecompressError {}
 impl DecompressError {
     /// Retrieve the implementation's message about why the operation failed, if one exists.
     pub fn message(&self) -> Option<&str> {
-        match &self.0 {
-            DecompressErrorInner::General { msg } => msg.get(),
+        match &self.inner {
+            DecompressErrorInner::General { msg, .. } => msg.get(),
             _ => None,
         }
     }
@@ -557,19 +1564,35 @@ impl DecompressError {
 
 impl From<DecompressError> for io::Error {
     fn from(data: DecompressError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, data)
+        let kind = match data.inner {
+            DecompressErrorInner::Truncated => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, data)
     }
 }
 
 impl fmt::Display for DecompressError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let msg = match &self.0 {
-            DecompressErrorInner::General { msg } => msg.get(),
+        let msg = match &self.inner {
+            DecompressErrorInner::General { msg, .. } => msg.get(),
             DecompressErrorInner::NeedsDictionary { .. } => Some("requires a dictionary"),
+            DecompressErrorInner::Truncated => Some("unexpected end of input"),
+            DecompressErrorInner::LimitExceeded(limit) => {
+                return write!(
+                    f,
+                    "decompression limit exceeded: {} at offset {}",
+                    limit, self.offset
+                );
+            }
         };
         match msg {
-            Some(msg) => write!(f, "deflate decompression error: {}", msg),
-            None => write!(f, "deflate decompression error"),
+            Some(msg) => write!(
+                f,
+                "deflate decompression error: {} at offset {}",
+                msg, self.offset
+            ),
+            None => write!(f, "deflate decompression error at offset {}", self.offset),
         }
     }
 }
@@ -579,7 +1602,10 @@ impl Error for CompressError {}
 impl CompressError {
     /// Retrieve the implementation's message about why the operation failed, if one exists.
     pub fn message(&self) -> Option<&str> {
-        self.msg.get()
+        match &self.0 {
+            CompressErrorInner::General { msg } => msg.get(),
+            _ => None,
+        }
     }
 }
 
@@ -591,9 +1617,20 @@ impl From<CompressError> for io::Error {
 
 impl fmt::Display for CompressError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.msg.get() {
-            Some(msg) => write!(f, "deflate compression error: {}", msg),
-            None => write!(f, "deflate compression error"),
+        match &self.0 {
+            CompressErrorInner::General { msg } => match msg.get() {
+                Some(msg) => write!(f, "deflate compression error: {}", msg),
+                None => write!(f, "deflate compression error"),
+            },
+            CompressErrorInner::UnsupportedOption(option) => {
+                write!(f, "deflate compression error: unsupported {}", option)
+            }
+            CompressErrorInner::Unavailable => {
+                write!(
+                    f,
+                    "deflate compression error: not supported by this backend"
+                )
+            }
         }
     }
 }
@@ -603,10 +1640,7 @@ mod tests {
     use std::io::Write;
 
     use crate::write;
-    use crate::{Compression, Decompress, FlushDecompress};
-
-    #[cfg(feature = "any_zlib")]
-    use crate::{Compress, FlushCompress};
+    use crate::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
 
     #[test]
     fn issue51() {
@@ -666,7 +1700,113 @@ mod tests {
         assert!(dst.starts_with(string));
     }
 
+    #[test]
+    fn strategies() {
+        use crate::CompressionStrategy;
+        use std::io::Read;
+
+        let string = "hello world, hello world! aaaaaaaaaaaaaaaaaaaa".repeat(100);
+        let mut default_len = 0;
+        for &strategy in &[
+            CompressionStrategy::Default,
+            CompressionStrategy::Filtered,
+            CompressionStrategy::HuffmanOnly,
+            CompressionStrategy::Rle,
+            CompressionStrategy::Fixed,
+        ] {
+            let compress =
+                crate::Compress::new_with_strategy(Compression::default(), true, strategy);
+            let mut e = write::ZlibEncoder::new_with_compress(Vec::new(), compress);
+            e.write_all(string.as_bytes()).unwrap();
+            let zlib = e.finish().unwrap();
+
+            let mut decoded = String::new();
+            crate::read::ZlibDecoder::new(&zlib[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, string, "{:?}", strategy);
+
+            // The block type follows the final block bit of the first block,
+            // right after the two byte zlib header.
+            let block_type = (zlib[2] >> 1) & 0b11;
+            match strategy {
+                CompressionStrategy::Default => default_len = zlib.len(),
+                // Without matches beyond a distance of one the repeated words
+                // can't be referenced, so these do worse than the default.
+                CompressionStrategy::HuffmanOnly | CompressionStrategy::Rle => {
+                    assert!(zlib.len() > default_len, "{:?}", strategy)
+                }
+                CompressionStrategy::Fixed => assert_eq!(block_type, 0b01),
+                CompressionStrategy::Filtered => {}
+            }
+            if strategy != CompressionStrategy::Fixed {
+                assert_eq!(block_type, 0b10, "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn options() {
+        use crate::{CompressOption, CompressOptions, Format};
+        use std::io::Read;
+
+        let string = "hello world, hello world!".repeat(100);
+        let options = CompressOptions::new(Compression::best())
+            .format(Format::Raw)
+            .strategy(crate::CompressionStrategy::Filtered);
+        let compress = crate::Compress::new_with_options(options).unwrap();
+        let mut e = write::DeflateEncoder::new_with_compress(Vec::new(), compress);
+        e.write_all(string.as_bytes()).unwrap();
+        let deflate = e.finish().unwrap();
+
+        let mut decoded = String::new();
+        crate::read::DeflateDecoder::new(&deflate[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, string);
+
+        let err = crate::Compress::new_with_options(
+            CompressOptions::new(Compression::default()).window_bits(16),
+        )
+        .unwrap_err();
+        assert_eq!(err.unsupported_option(), Some(CompressOption::WindowBits));
+
+        let err = crate::Compress::new_with_options(
+            CompressOptions::new(Compression::default()).mem_level(0),
+        )
+        .unwrap_err();
+        assert_eq!(err.unsupported_option(), Some(CompressOption::MemLevel));
+        assert!(err.message().is_none());
+    }
+
     #[cfg(feature = "any_zlib")]
+    #[test]
+    fn mem_level() {
+        let options = crate::CompressOptions::new(Compression::default()).mem_level(1);
+        let mut encoder = Compress::new_with_options(options).unwrap();
+        let mut encoded = Vec::with_capacity(1024);
+        encoder
+            .compress_vec(b"hello, hello!", &mut encoded, FlushCompress::Finish)
+            .unwrap();
+
+        let mut decoder = Decompress::new(true);
+        let mut decoded = Vec::with_capacity(1024);
+        decoder
+            .decompress_vec(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(decoded, b"hello, hello!");
+    }
+
+    #[cfg(not(feature = "any_zlib"))]
+    #[test]
+    fn mem_level_unsupported() {
+        use crate::CompressOption;
+
+        let options = crate::CompressOptions::new(Compression::default()).mem_level(1);
+        let err = crate::Compress::new_with_options(options).unwrap_err();
+        assert_eq!(err.unsupported_option(), Some(CompressOption::MemLevel));
+    }
+
     #[test]
     fn set_dictionary_with_zlib_header() {
         let string = "hello, hello!".as_bytes();
@@ -715,7 +1855,19 @@ mod tests {
         assert_eq!(&decoded[..decoder.total_out() as usize], string);
     }
 
-    #[cfg(feature = "any_zlib")]
+    #[test]
+    fn set_dictionary_too_late() {
+        let mut encoder = Compress::new(Compression::default(), true);
+        let mut encoded = Vec::with_capacity(1024);
+        encoder
+            .compress_vec(b"hello", &mut encoded, FlushCompress::None)
+            .unwrap();
+        assert!(encoder.set_dictionary(b"hello").is_err());
+
+        let mut encoder = Compress::new_gzip(Compression::default(), 15);
+        assert!(encoder.set_dictionary(b"hello").is_err());
+    }
+
     #[test]
     fn set_dictionary_raw() {
         let string = "hello, hello!".as_bytes();
@@ -746,7 +1898,148 @@ mod tests {
         assert_eq!(&decoded[..decoder.total_out() as usize], string);
     }
 
-    #[cfg(feature = "any_zlib")]
+    #[test]
+    fn window_bits() {
+        let string = "hello world, hello world! abcdefghijklmnopqrstuvwxyz".repeat(1000);
+        for &window_bits in &[9, 12, 15] {
+            for &zlib_header in &[true, false] {
+                let mut encoder = Compress::new_with_window_bits(
+                    Compression::default(),
+                    zlib_header,
+                    window_bits,
+                );
+                // Use a small output buffer to exercise the header and trailer
+                // handling.
+                let mut encoded = Vec::new();
+                let mut output = [0; 13];
+                loop {
+                    let input = &string.as_bytes()[encoder.total_in() as usize..];
+                    let before = encoder.total_out();
+                    let status = encoder
+                        .compress(input, &mut output, FlushCompress::Finish)
+                        .unwrap();
+                    encoded.extend_from_slice(&output[..(encoder.total_out() - before) as usize]);
+                    if status == crate::Status::StreamEnd {
+                        break;
+                    }
+                }
+                assert_eq!(encoder.total_in(), string.len() as u64);
+
+                let mut decoder = Decompress::new_with_window_bits(zlib_header, window_bits);
+                let mut decoded = Vec::with_capacity(string.len());
+                for chunk in encoded.chunks(7) {
+                    let mut chunk = chunk;
+                    while !chunk.is_empty() {
+                        let before = decoder.total_in();
+                        decoder
+                            .decompress_vec(chunk, &mut decoded, FlushDecompress::None)
+                            .unwrap();
+                        chunk = &chunk[(decoder.total_in() - before) as usize..];
+                    }
+                }
+                assert_eq!(decoded, string.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn small_window_keeps_matches() {
+        // Data repeating within a small window still compresses well, and
+        // never refers back further than the window.
+        let data = (0..100_000).map(|i| (i % 100) as u8).collect::<Vec<_>>();
+        let mut encoder = Compress::new_with_window_bits(Compression::default(), false, 9);
+        let mut encoded = Vec::with_capacity(data.len());
+        encoder
+            .compress_vec(&data, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(encoder.total_in(), data.len() as u64);
+        assert!(encoded.len() < data.len() / 10, "{} bytes", encoded.len());
+
+        let mut decoder = Decompress::new_with_window_bits(false, 9);
+        let mut decoded = Vec::with_capacity(data.len());
+        decoder
+            .decompress_vec(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn window_too_far_back() {
+        // A distance of 1KiB is too far back for a 512 byte window.
+        let data = (0..1024u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let mut encoder = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(4096);
+        encoder
+            .compress_vec(&data, &mut encoded, FlushCompress::None)
+            .unwrap();
+        encoder
+            .compress_vec(&data, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        assert!(encoded.len() < 1536);
+
+        // zlib only checks distances reaching back before the output of the
+        // current call, so decompress a bit at a time.
+        let mut decoder = Decompress::new_with_window_bits(false, 9);
+        let mut output = [0; 64];
+        let res = loop {
+            let input = &encoded[decoder.total_in() as usize..];
+            match decoder.decompress(input, &mut output, FlushDecompress::None) {
+                Ok(crate::Status::StreamEnd) => break Ok(()),
+                Ok(_) => {}
+                Err(err) => break Err(err),
+            }
+        };
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn window_bits_too_small() {
+        let mut zlib = Vec::new();
+        write::ZlibEncoder::new(&mut zlib, Compression::default())
+            .write_all(b"hello world")
+            .unwrap();
+
+        let mut decoder = Decompress::new_with_window_bits(true, 9);
+        let mut decoded = Vec::with_capacity(1024);
+        assert!(decoder
+            .decompress_vec(&zlib, &mut decoded, FlushDecompress::Finish)
+            .is_err());
+    }
+
+    #[test]
+    fn gzip_header_fields() {
+        let mut gz = crate::GzBuilder::new()
+            .filename("hello.txt")
+            .comment("a comment")
+            .extra(vec![1, 2, 3])
+            .write(Vec::new(), Compression::default());
+        gz.write_all(b"hello world").unwrap();
+        let gz = gz.finish().unwrap();
+
+        let mut decoder = Decompress::new_gzip(15);
+        let mut decoded = Vec::with_capacity(1024);
+        let mut status = crate::Status::Ok;
+        for byte in gz.chunks(1) {
+            status = decoder
+                .decompress_vec(byte, &mut decoded, FlushDecompress::None)
+                .unwrap();
+        }
+        assert_eq!(status, crate::Status::StreamEnd);
+        assert_eq!(decoder.total_in(), gz.len() as u64);
+        assert_eq!(decoded, b"hello world");
+
+        // A corrupted trailer is detected.
+        let mut corrupt = gz.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        let mut decoder = Decompress::new_gzip(15);
+        let mut decoded = Vec::with_capacity(1024);
+        assert!(decoder
+            .decompress_vec(&corrupt, &mut decoded, FlushDecompress::Finish)
+            .is_err());
+    }
+
     #[test]
     fn test_gzip_flate() {
         let string = "hello, hello!".as_bytes();
@@ -772,6 +2065,509 @@ mod tests {
         assert_eq!(&decoded[..decoder.total_out() as usize], string);
     }
 
+    #[test]
+    fn sync() {
+        use crate::Status;
+
+        let mut c = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(b"lost", &mut encoded, FlushCompress::Full)
+            .unwrap();
+        let start = encoded.len();
+        c.compress_vec(b"kept", &mut encoded, FlushCompress::Finish)
+            .unwrap();
+
+        // Mark the first block with the reserved block type so that decoding
+        // fails right away, then hand over the input one byte at a time to
+        // split the marker.
+        encoded[0] |= 0x06;
+        let mut d = Decompress::new(false);
+        let mut decoded = Vec::with_capacity(64);
+        assert!(d
+            .decompress_vec(&encoded, &mut decoded, FlushDecompress::None)
+            .is_err());
+        let mut pos = d.total_in() as usize;
+        while d.sync(&encoded[pos..pos + 1]).unwrap() == Status::BufError {
+            pos += 1;
+        }
+        assert_eq!(pos + 1, start);
+        assert_eq!(d.sync(&[]).unwrap(), Status::BufError);
+
+        let status = d
+            .decompress_vec(&encoded[start..], &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(decoded, b"kept");
+    }
+
+    #[test]
+    fn prime() {
+        use crate::Status;
+
+        let string = "hello world, hello world!".repeat(20);
+
+        // Start the stream with an empty fixed block, which takes ten bits.
+        let mut c = Compress::new(Compression::default(), false);
+        c.prime(10, 0b10).unwrap();
+        assert!(c.prime(17, 0).is_err());
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(string.as_bytes(), &mut encoded, FlushCompress::Finish)
+            .unwrap();
+
+        // Hand over the first byte as bits, split partway through it.
+        let mut d = Decompress::new(false);
+        d.prime(3, u16::from(encoded[0])).unwrap();
+        d.prime(5, u16::from(encoded[0] >> 3)).unwrap();
+        assert!(d.prime(17, 0).is_err());
+        let mut decoded = Vec::with_capacity(string.len());
+        let status = d
+            .decompress_vec(&encoded[1..], &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(d.total_in() as usize, encoded.len() - 1);
+        assert_eq!(decoded, string.as_bytes());
+
+        // The miniz_oxide backend only takes bits once the header is read.
+        if cfg!(not(feature = "any_zlib")) {
+            assert!(Decompress::new(true).prime(3, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn prime_too_many_bits() {
+        // Like inflatePrime, at most 32 bits can be waiting at once.
+        let mut d = Decompress::new(false);
+        d.prime(16, 0).unwrap();
+        d.prime(16, 0).unwrap();
+        assert!(d.prime(16, 0).is_err());
+        assert!(d.prime(1, 0).is_err());
+
+        // The 32 zero bits start a stored block with a bad length.
+        let mut out = [0; 16];
+        let err = d
+            .decompress(&[0xff; 8], &mut out, FlushDecompress::None)
+            .unwrap_err();
+        assert_eq!(err.kind(), crate::DecompressErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mark() {
+        use crate::DecompressMark;
+
+        let mut d = Decompress::new(false);
+        assert_eq!(d.mark(), DecompressMark::OutsideBlock);
+
+        // A stored block is left partway through when the output fills up.
+        let data = vec![7; 100];
+        let mut c = Compress::new(Compression::none(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(&data, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        let mut out = [0; 10];
+        d.decompress(&encoded, &mut out, FlushDecompress::None)
+            .unwrap();
+        assert_eq!(d.mark(), DecompressMark::Stored { remaining: 90 });
+
+        // Writing out matches a byte at a time stops partway through them.
+        let mut c = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(&data, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        let mut d = Decompress::new(false);
+        let mut in_match = false;
+        while d.total_out() < data.len() as u64 {
+            let pos = d.total_in() as usize;
+            d.decompress(&encoded[pos..], &mut out[..1], FlushDecompress::None)
+                .unwrap();
+            if let DecompressMark::Code { bits_back, emitted } = d.mark() {
+                in_match |= bits_back > 0 && emitted > 0;
+            }
+        }
+        assert!(in_match);
+    }
+
+    #[test]
+    fn block_flush() {
+        use crate::Status;
+
+        let chunks = [
+            "hello world, ".repeat(50),
+            "goodbye world, ".repeat(50),
+            "hello again, ".repeat(50),
+        ];
+        let data = chunks.concat();
+        let mut c = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(4096);
+        for chunk in &chunks[..2] {
+            c.compress_vec(chunk.as_bytes(), &mut encoded, FlushCompress::Block)
+                .unwrap();
+        }
+        c.compress_vec(chunks[2].as_bytes(), &mut encoded, FlushCompress::Finish)
+            .unwrap();
+
+        let mut d = Decompress::new(false);
+        let mut decoded = Vec::with_capacity(data.len());
+        let mut boundaries = Vec::new();
+        loop {
+            let pos = d.total_in() as usize;
+            let status = d
+                .decompress_vec(&encoded[pos..], &mut decoded, FlushDecompress::Block)
+                .unwrap();
+            if status == Status::StreamEnd {
+                break;
+            }
+            assert!(d.at_block_boundary());
+            if !d.last_block() {
+                boundaries.push((d.total_in() as usize, d.unused_bits(), decoded.len()));
+            }
+        }
+        assert!(d.last_block());
+        assert!(boundaries.len() >= 2);
+        assert_eq!(decoded, data.as_bytes());
+
+        // Each boundary can be resumed from with a fresh decompressor.
+        for &(pos, bits, out) in &boundaries {
+            let mut d = Decompress::new(false);
+            if bits > 0 {
+                d.prime(bits, u16::from(encoded[pos - 1] >> (8 - bits)))
+                    .unwrap();
+            }
+            d.set_dictionary(&decoded[..out]).unwrap();
+            let mut rest = Vec::with_capacity(data.len());
+            let status = d
+                .decompress_vec(&encoded[pos..], &mut rest, FlushDecompress::Finish)
+                .unwrap();
+            assert_eq!(status, Status::StreamEnd);
+            assert_eq!(rest, &data.as_bytes()[out..]);
+        }
+
+        // For a zlib stream decompression stops right after the header.
+        let mut c = Compress::new(Compression::default(), true);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(b"hello", &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        let mut d = Decompress::new(true);
+        d.decompress_vec(&encoded, &mut decoded, FlushDecompress::Block)
+            .unwrap();
+        assert_eq!(d.total_in(), 2);
+        assert_eq!(d.total_out(), 0);
+        assert!(d.at_block_boundary());
+    }
+
+    #[test]
+    fn clone() {
+        use crate::Status;
+
+        let prefix = "a common prefix, ".repeat(20);
+        let mut c = Compress::new(Compression::default(), true);
+        let mut encoded_prefix = Vec::with_capacity(1024);
+        c.compress_vec(prefix.as_bytes(), &mut encoded_prefix, FlushCompress::Full)
+            .unwrap();
+
+        let mut encoded = Vec::new();
+        for suffix in &["hello", "world"] {
+            let mut c = c.try_clone().unwrap();
+            let mut e = encoded_prefix.clone();
+            e.reserve(1024);
+            c.compress_vec(suffix.as_bytes(), &mut e, FlushCompress::Finish)
+                .unwrap();
+            encoded.push(e);
+        }
+
+        // Fork a decompressor after the prefix to decompress both streams.
+        let mut d = Decompress::new(true);
+        let mut decoded = Vec::with_capacity(1024);
+        d.decompress_vec(&encoded_prefix, &mut decoded, FlushDecompress::None)
+            .unwrap();
+        assert_eq!(decoded, prefix.as_bytes());
+        for (e, suffix) in encoded.iter().zip(&["hello", "world"]) {
+            let mut d = d.clone();
+            let mut decoded = Vec::with_capacity(1024);
+            let status = d
+                .decompress_vec(
+                    &e[encoded_prefix.len()..],
+                    &mut decoded,
+                    FlushDecompress::Finish,
+                )
+                .unwrap();
+            assert_eq!(status, Status::StreamEnd);
+            assert_eq!(decoded, suffix.as_bytes());
+            assert_eq!(d.total_out() as usize, prefix.len() + suffix.len());
+        }
+    }
+
+    #[test]
+    fn try_clone_mid_stream() {
+        let mut c = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(b"hello world", &mut encoded, FlushCompress::Sync)
+            .unwrap();
+        // miniz_oxide can't recreate a compressor which can refer back to
+        // earlier data, until a full flush has it forget about that.
+        let mut copy = c.try_clone().unwrap();
+        let mut copied = encoded.clone();
+        copied.reserve(1024);
+        copy.compress_vec(b"hello world again", &mut copied, FlushCompress::Finish)
+            .unwrap();
+        let mut d = Decompress::new(false);
+        let mut decoded = Vec::with_capacity(1024);
+        d.decompress_vec(&copied, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(decoded, b"hello worldhello world again");
+
+        // Until a flush miniz_oxide may hold on to input it can't be
+        // recreated from.
+        c.compress_vec(b"hello", &mut encoded, FlushCompress::None)
+            .unwrap();
+        if cfg!(not(feature = "any_zlib")) {
+            assert!(c.try_clone().is_err());
+        }
+        c.compress_vec(b"", &mut encoded, FlushCompress::Full)
+            .unwrap();
+        let len = encoded.len();
+        let mut copy = c.try_clone().unwrap();
+        c.compress_vec(b"hello again", &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        let mut copied = encoded[..len].to_vec();
+        copied.reserve(1024);
+        copy.compress_vec(b"hello again", &mut copied, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(copied, encoded);
+    }
+
+    #[test]
+    #[cfg(feature = "any_zlib")]
+    fn clone_unflushed() {
+        let mut c = Compress::new(Compression::default(), true);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(b"hello world", &mut encoded, FlushCompress::None)
+            .unwrap();
+        let mut copy = c.clone();
+        let mut copied = encoded.clone();
+        copied.reserve(1024);
+        c.compress_vec(b", hello", &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        copy.compress_vec(b", hello", &mut copied, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(copied, encoded);
+    }
+
+    #[test]
+    fn clone_with_dictionary() {
+        let dictionary = b"hello world, goodbye world";
+        let mut c = Compress::new(Compression::default(), false);
+        c.set_dictionary(dictionary).unwrap();
+        for message in &["hello world", "goodbye world, hello world"] {
+            let mut c = c.try_clone().unwrap();
+            let mut encoded = Vec::with_capacity(1024);
+            c.compress_vec(message.as_bytes(), &mut encoded, FlushCompress::Finish)
+                .unwrap();
+
+            let mut d = Decompress::new(false);
+            d.set_dictionary(dictionary).unwrap();
+            let mut decoded = Vec::with_capacity(1024);
+            d.decompress_vec(&encoded, &mut decoded, FlushDecompress::Finish)
+                .unwrap();
+            assert_eq!(decoded, message.as_bytes());
+        }
+    }
+
+    #[test]
+    fn get_dictionary() {
+        let first: Vec<u8> = (0..40_000u32).map(|i| (i * i % 251) as u8).collect();
+        let second = b"a chunk which refers back to the first one".to_vec();
+        let tail = &first[first.len() - 32768..];
+
+        let mut c = Compress::new(Compression::default(), false);
+        assert_eq!(c.get_dictionary().unwrap(), b"");
+        let mut encoded = Vec::with_capacity(first.len() * 2);
+        c.compress_vec(&first, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(c.get_dictionary().unwrap(), tail);
+
+        let mut d = Decompress::new(false);
+        let mut decoded = Vec::with_capacity(first.len());
+        d.decompress_vec(&encoded, &mut decoded, FlushDecompress::None)
+            .unwrap();
+        assert_eq!(decoded, first);
+        assert_eq!(d.get_dictionary().unwrap(), tail);
+
+        // Chain the next chunk onto the first with the retrieved dictionaries.
+        let dictionary = d.get_dictionary().unwrap();
+        let mut c = Compress::new(Compression::default(), false);
+        c.set_dictionary(&dictionary).unwrap();
+        assert_eq!(c.get_dictionary().unwrap(), tail);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(&second, &mut encoded, FlushCompress::Finish)
+            .unwrap();
+
+        let dictionary = d.get_dictionary().unwrap();
+        let mut d = Decompress::new(false);
+        d.set_dictionary(&dictionary).unwrap();
+        let mut decoded = Vec::with_capacity(1024);
+        d.decompress_vec(&encoded, &mut decoded, FlushDecompress::None)
+            .unwrap();
+        assert_eq!(decoded, second);
+    }
+
+    #[test]
+    fn get_dictionary_follows_input() {
+        let mut c = Compress::new(Compression::default(), false);
+        let mut encoded = Vec::with_capacity(1024);
+        c.compress_vec(b"hello world", &mut encoded, FlushCompress::None)
+            .unwrap();
+        assert_eq!(c.get_dictionary().unwrap(), b"hello world");
+        c.compress_vec(b", goodbye world", &mut encoded, FlushCompress::Sync)
+            .unwrap();
+        assert_eq!(c.get_dictionary().unwrap(), b"hello world, goodbye world");
+        // A full flush forgets about the earlier data.
+        c.compress_vec(b"", &mut encoded, FlushCompress::Full)
+            .unwrap();
+        assert_eq!(c.get_dictionary().unwrap(), b"");
+    }
+
+    #[test]
+    fn error_kinds() {
+        use crate::{DecompressErrorKind, Format};
+
+        let decompress = |format: Format, input: &[u8]| {
+            let mut decoded = Vec::with_capacity(1024);
+            Decompress::new_with_format(format, 15)
+                .decompress_vec(input, &mut decoded, FlushDecompress::Finish)
+                .unwrap_err()
+        };
+        // A stored block followed by a block of the reserved type.
+        let mut raw = vec![0x00, 100, 0x00, !100, 0xff];
+        raw.extend_from_slice(&[b'x'; 100]);
+        raw.push(0x07);
+        let err = decompress(Format::Raw, &raw);
+        assert_eq!(err.kind(), DecompressErrorKind::InvalidBlockType);
+        assert_eq!(err.offset(), 106);
+        assert!(err.to_string().ends_with("at offset 106"));
+
+        let cases: Vec<(Format, &[u8], DecompressErrorKind)> = vec![
+            // A stored block with a broken length.
+            (
+                Format::Raw,
+                &[0x01, 0x01, 0x00, 0x00, 0xff],
+                DecompressErrorKind::InvalidData,
+            ),
+            // A dynamic block with an incomplete code for the code lengths.
+            (
+                Format::Raw,
+                &[0x05, 0x00, 0x02, 0x00, 0x00, 0x00],
+                DecompressErrorKind::InvalidHuffmanTable,
+            ),
+            // A fixed block starting with a match.
+            (
+                Format::Raw,
+                &[0x03, 0x02, 0x00, 0x00],
+                DecompressErrorKind::InvalidDistance,
+            ),
+            // Headers with a bad check and an unknown compression method.
+            (
+                Format::Zlib,
+                &[0x78, 0x9d],
+                DecompressErrorKind::InvalidHeader,
+            ),
+            (
+                Format::Zlib,
+                &[0x77, 0x85],
+                DecompressErrorKind::InvalidHeader,
+            ),
+            (
+                Format::Gzip,
+                &[0x1f, 0x8b, 0x07, 0, 0, 0, 0, 0, 0, 0],
+                DecompressErrorKind::InvalidHeader,
+            ),
+        ];
+        for &(format, input, kind) in cases.iter() {
+            assert_eq!(decompress(format, input).kind(), kind, "{:x?}", input);
+        }
+
+        let mut zlib = Vec::with_capacity(1024);
+        Compress::new(Compression::default(), true)
+            .compress_vec(b"hello world", &mut zlib, FlushCompress::Finish)
+            .unwrap();
+        *zlib.last_mut().unwrap() ^= 1;
+        let err = decompress(Format::Zlib, &zlib);
+        assert_eq!(err.kind(), DecompressErrorKind::ChecksumMismatch);
+        assert_eq!(err.offset(), zlib.len() as u64);
+    }
+
+    #[test]
+    fn decompress_formats() {
+        use crate::{CompressOptions, Format, Status};
+
+        let string = "hello world, hello world!".repeat(100);
+        let mut decompress = Decompress::new_auto(15);
+        for &format in &[Format::Raw, Format::Zlib, Format::Gzip] {
+            let options = CompressOptions::new(Compression::default()).format(format);
+            let mut compress = Compress::new_with_options(options).unwrap();
+            let mut encoded = Vec::with_capacity(1024);
+            compress
+                .compress_vec(string.as_bytes(), &mut encoded, FlushCompress::Finish)
+                .unwrap();
+
+            let mut decoded = Vec::with_capacity(string.len());
+            Decompress::new_with_format(format, 15)
+                .decompress_vec(&encoded, &mut decoded, FlushDecompress::Finish)
+                .unwrap();
+            assert_eq!(decoded, string.as_bytes());
+
+            if format == Format::Raw {
+                continue;
+            }
+            // Feed the input a byte at a time so detection has to wait for it.
+            decompress.reset_auto();
+            let mut decoded = Vec::with_capacity(string.len());
+            let mut status = decompress
+                .decompress_vec(&[], &mut decoded, FlushDecompress::None)
+                .unwrap();
+            assert_eq!(status, Status::BufError);
+            while status != Status::StreamEnd {
+                let pos = decompress.total_in() as usize;
+                status = decompress
+                    .decompress_vec(&encoded[pos..pos + 1], &mut decoded, FlushDecompress::None)
+                    .unwrap();
+            }
+            assert_eq!(decompress.total_in(), encoded.len() as u64);
+            assert_eq!(decoded, string.as_bytes());
+        }
+    }
+
+    #[test]
+    fn try_new() {
+        use crate::{CompressOption, DecompressErrorKind, Format};
+
+        assert!(Compress::try_new(Compression::default(), true).is_ok());
+        let err = Compress::try_new(Compression::new(42), false).unwrap_err();
+        assert_eq!(err.unsupported_option(), Some(CompressOption::Level));
+
+        assert!(Compress::try_new_with_window_bits(Compression::default(), true, 9).is_ok());
+        assert!(Compress::try_new_gzip(Compression::default(), 15).is_ok());
+        for &bits in &[0, 8, 16, 20] {
+            let err = Compress::try_new_with_window_bits(Compression::default(), false, bits)
+                .unwrap_err();
+            assert_eq!(err.unsupported_option(), Some(CompressOption::WindowBits));
+            let err = Compress::try_new_gzip(Compression::default(), bits).unwrap_err();
+            assert_eq!(err.unsupported_option(), Some(CompressOption::WindowBits));
+        }
+
+        assert!(Decompress::try_new(true).is_ok());
+        assert!(Decompress::try_new_with_format(Format::Gzip, 9).is_ok());
+        assert!(Decompress::try_new_with_window_bits(false, 12).is_ok());
+        assert!(Decompress::try_new_gzip(15).is_ok());
+        assert!(Decompress::try_new_auto(15).is_ok());
+        for &bits in &[0, 8, 16, 20] {
+            let err = Decompress::try_new_with_format(Format::Raw, bits).unwrap_err();
+            assert_eq!(err.kind(), DecompressErrorKind::Other);
+            assert!(Decompress::try_new_with_window_bits(true, bits).is_err());
+            assert!(Decompress::try_new_gzip(bits).is_err());
+            assert!(Decompress::try_new_auto(bits).is_err());
+        }
+    }
+
     #[cfg(feature = "any_zlib")]
     #[test]
     fn test_error_message() {
@@ -0,0 +1,370 @@
+//! Compression and decompression spread over a pool of threads.
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+pub mod read;
+pub mod write;
+
+/// The default amount of uncompressed data in a block.
+const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Options for the multithreaded encoders and decoders, such as
+/// [`write::ParGzEncoder`].
+///
+/// [`write::ParGzEncoder`]: write/struct.ParGzEncoder.html
+///
+/// # Examples
+///
+/// ```
+/// use flate2::ParOptions;
+///
+/// let options = ParOptions::new().threads(4).block_size(1 << 20);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ParOptions {
+    pub(crate) threads: usize,
+    pub(crate) block_size: usize,
+}
+
+impl Default for ParOptions {
+    fn default() -> ParOptions {
+        ParOptions::new()
+    }
+}
+
+impl ParOptions {
+    /// Creates options with as many threads as the system has available
+    /// parallelism, and blocks of 128 KiB.
+    pub fn new() -> ParOptions {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        ParOptions {
+            threads,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Sets the number of worker threads, which is at least one.
+    pub fn threads(mut self, threads: usize) -> ParOptions {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the amount of uncompressed data in each block, which is at least
+    /// one byte.
+    ///
+    /// Larger blocks compress slightly better, while smaller ones keep more
+    /// threads busy on small inputs.
+    pub fn block_size(mut self, block_size: usize) -> ParOptions {
+        self.block_size = block_size.max(1);
+        self
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of worker threads, which pick up jobs in the order they are
+/// submitted.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Pool {
+    pub fn new(threads: usize) -> io::Result<Pool> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let mut pool = Pool {
+            jobs: Some(jobs),
+            workers: Vec::with_capacity(threads),
+        };
+        for _ in 0..threads {
+            let queue = queue.clone();
+            let worker = thread::Builder::new()
+                .name("flate2-worker".to_string())
+                .spawn(move || loop {
+                    let job = match queue.lock() {
+                        Ok(queue) => queue.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        // A panicking job drops its result sender, which is
+                        // how it gets reported, and the worker carries on.
+                        Ok(job) => drop(panic::catch_unwind(AssertUnwindSafe(job))),
+                        Err(_) => return,
+                    }
+                })?;
+            pool.workers.push(worker);
+        }
+        Ok(pool)
+    }
+
+    /// Runs `f` on one of the workers, returning a receiver for its result.
+    pub fn run<T, F>(&self, f: F) -> mpsc::Receiver<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job = Box::new(move || {
+            let _ = tx.send(f());
+        });
+        // The workers only go away once the pool is dropped.
+        self.jobs.as_ref().unwrap().send(job).unwrap();
+        rx
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Waits for the result of a job run on a [`Pool`].
+pub(crate) fn wait<T>(result: &mpsc::Receiver<io::Result<T>>) -> io::Result<T> {
+    result
+        .recv()
+        .unwrap_or_else(|_| Err(io::Error::other("worker thread panicked")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::ParOptions;
+    use crate::write::{DeflateEncoder, GzEncoder, ParGzEncoder, ParZlibEncoder};
+    use crate::{read, Compression, Crc, GzBuilder, GzError, GzErrorKind};
+    use rand::{thread_rng, Rng};
+
+    fn sample() -> Vec<u8> {
+        let mut rng = thread_rng();
+        let words = ["hello ", "world ", "gzip ", "block ", "thread "];
+        (0..40_000)
+            .flat_map(|_| words[rng.gen_range(0..words.len())].bytes())
+            .collect()
+    }
+
+    #[test]
+    fn gz_roundtrip() {
+        let data = sample();
+        for &block_size in [1000, 50_000, 1 << 20].iter() {
+            let options = ParOptions::new().threads(3).block_size(block_size);
+            let mut e = ParGzEncoder::new_with_options(Vec::new(), Compression::default(), options)
+                .unwrap();
+            for chunk in data.chunks(7_777) {
+                e.write_all(chunk).unwrap();
+            }
+            let compressed = e.finish().unwrap();
+
+            // A single member, so a single-member decoder reads all of it.
+            let mut out = Vec::new();
+            read::GzDecoder::new(&compressed[..])
+                .read_to_end(&mut out)
+                .unwrap();
+            assert!(out == data);
+        }
+    }
+
+    #[test]
+    fn zlib_roundtrip() {
+        let data = sample();
+        for &threads in [1, 4].iter() {
+            let options = ParOptions::new().threads(threads).block_size(10_000);
+            let mut e =
+                ParZlibEncoder::new_with_options(Vec::new(), Compression::best(), options).unwrap();
+            e.write_all(&data).unwrap();
+            let compressed = e.finish().unwrap();
+
+            let mut out = Vec::new();
+            read::ZlibDecoder::new(&compressed[..])
+                .read_to_end(&mut out)
+                .unwrap();
+            assert!(out == data);
+        }
+    }
+
+    #[test]
+    fn empty_and_flush() {
+        let e = ParZlibEncoder::new(Vec::new(), Compression::fast());
+        let compressed = e.finish().unwrap();
+        let mut out = Vec::new();
+        read::ZlibDecoder::new(&compressed[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out.is_empty());
+
+        let mut e = GzBuilder::new()
+            .filename("data")
+            .par_write(Vec::new(), Compression::default(), ParOptions::new())
+            .unwrap();
+        e.write_all(b"hello ").unwrap();
+        e.flush().unwrap();
+        let flushed = e.get_ref().len();
+        e.write_all(b"world").unwrap();
+        let compressed = e.finish().unwrap();
+        assert!(flushed > 10 && flushed < compressed.len());
+
+        let mut d = read::GzDecoder::new(&compressed[..]);
+        let mut out = String::new();
+        d.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "hello world");
+        assert_eq!(d.header().unwrap().filename(), Some(&b"data"[..]));
+    }
+
+    #[test]
+    fn blocks_share_history() {
+        // Each block refers back into the previous one, so small blocks cost
+        // little over compressing on one thread.
+        let data = sample();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let serial = e.finish().unwrap().len();
+
+        let options = ParOptions::new().threads(2).block_size(4096);
+        let mut e =
+            ParGzEncoder::new_with_options(Vec::new(), Compression::default(), options).unwrap();
+        e.write_all(&data).unwrap();
+        let parallel = e.finish().unwrap().len();
+        assert!(parallel < serial + serial / 5, "{} vs {}", parallel, serial);
+    }
+
+    /// Compresses `data` as a single BGZF block.
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        let body = e.finish().unwrap();
+        let size = 18 + body.len() + 8;
+        let mut block = vec![
+            0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ];
+        block.extend(&((size - 1) as u16).to_le_bytes());
+        block.extend(body);
+        let mut crc = Crc::new();
+        crc.update(data);
+        block.extend(&crc.sum().to_le_bytes());
+        block.extend(&crc.amount().to_le_bytes());
+        block
+    }
+
+    fn par_decode(bytes: &[u8], options: ParOptions) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        read::ParMultiGzDecoder::new_with_options(bytes, options)
+            .unwrap()
+            .read_to_end(&mut out)
+            .map(|_| out)
+    }
+
+    #[test]
+    fn bgzf_decode() {
+        let data = sample();
+        let mut bytes = Vec::new();
+        for chunk in data.chunks(10_000) {
+            bytes.extend(bgzf_block(chunk));
+        }
+        bytes.extend(bgzf_block(&[]));
+
+        for &block_size in [1, 20_000, 1 << 20].iter() {
+            let options = ParOptions::new().threads(3).block_size(block_size);
+            assert!(par_decode(&bytes, options).unwrap() == data);
+        }
+
+        // Members of unknown size are decoded in order with the rest.
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data[..1000]).unwrap();
+        let plain = e.finish().unwrap();
+        let mixed = [&plain[..], &bytes[..], &plain[..], &bytes[..]].concat();
+        let expected = [&data[..1000], &data[..], &data[..1000], &data[..]].concat();
+        let options = ParOptions::new().threads(2).block_size(20_000);
+        assert!(par_decode(&mixed, options).unwrap() == expected);
+        assert!(par_decode(&plain, options).unwrap() == &data[..1000]);
+    }
+
+    #[test]
+    fn bgzf_errors() {
+        let blocks = (0..5).map(|i| bgzf_block(&[i; 1000])).collect::<Vec<_>>();
+        let mut bytes = blocks.concat();
+        let end = blocks[0].len() + blocks[1].len() + blocks[2].len();
+        bytes[end - 8] ^= 1;
+
+        let options = ParOptions::new().threads(2).block_size(1);
+        let err = par_decode(&bytes, options).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<GzError>().unwrap();
+        assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+        assert_eq!(err.member(), 2);
+        assert_eq!(err.offset(), end as u64 - 8);
+
+        bytes[end - 8] ^= 1;
+        assert!(par_decode(&bytes[..bytes.len() - 1], options).is_err());
+        assert!(par_decode(&[], options).is_err());
+    }
+
+    fn par_decode_single(bytes: &[u8], options: ParOptions) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        read::ParGzDecoder::new_with_options(bytes, options)
+            .unwrap()
+            .read_to_end(&mut out)
+            .map(|_| out)
+    }
+
+    #[test]
+    fn single_member_decode() {
+        let data = [sample(), sample(), sample()].concat();
+        for &level in [0, 1, 6, 9].iter() {
+            let mut e = GzBuilder::new()
+                .filename("data")
+                .write(Vec::new(), Compression::new(level));
+            // Flushing along the way adds stored and fixed blocks.
+            for chunk in data.chunks(100_000) {
+                e.write_all(&chunk[..10]).unwrap();
+                e.flush().unwrap();
+                e.write_all(&chunk[10..]).unwrap();
+            }
+            let bytes = e.finish().unwrap();
+
+            for &block_size in [1, 1000, 20_000, 1 << 20].iter() {
+                let options = ParOptions::new().threads(3).block_size(block_size);
+                assert!(par_decode_single(&bytes, options).unwrap() == data);
+            }
+        }
+
+        // Only the first member is decoded, as with `GzDecoder`.
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data[..1000]).unwrap();
+        let member = e.finish().unwrap();
+        let bytes = [&member[..], &member[..]].concat();
+        let mut d = read::ParGzDecoder::new(&bytes[..]);
+        let mut out = Vec::new();
+        d.read_to_end(&mut out).unwrap();
+        assert!(out == &data[..1000]);
+        assert!(d.header().is_some());
+    }
+
+    #[test]
+    fn single_member_errors() {
+        let data = sample();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let mut bytes = e.finish().unwrap();
+        let len = bytes.len();
+        bytes[len - 8] ^= 1;
+
+        let options = ParOptions::new().threads(2).block_size(5000);
+        let err = par_decode_single(&bytes, options).unwrap_err();
+        let err = err.get_ref().unwrap().downcast_ref::<GzError>().unwrap();
+        assert!(matches!(err.kind(), GzErrorKind::CrcMismatch { .. }));
+        assert_eq!(err.offset(), len as u64 - 8);
+
+        bytes[len - 8] ^= 1;
+        for &cut in [1, 8, len / 2, len - 12].iter() {
+            assert!(par_decode_single(&bytes[..cut], options).is_err());
+        }
+        assert!(par_decode_single(&[], options).is_err());
+    }
+}
@@ -0,0 +1,870 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::sync::{mpsc, Arc};
+
+use super::{wait, ParOptions, Pool};
+use crate::bgzf;
+use crate::gz::bufread::{check_trailer, read_gz_header, GzDecoder};
+use crate::gz::{GzDecoderOptions, GzHeader, GzHeaderLimits, FEXTRA};
+use crate::inflate::{self, Speculation, WINDOW_SIZE};
+use crate::{Crc, Decompress, FlushDecompress};
+
+// How much more to read at a time when input is needed.
+const READ_SIZE: usize = 64 * 1024;
+// The default amount of compressed data each thread of a `ParGzDecoder`
+// starts decoding from.
+const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The compressed input, read ahead so that whole members can be handed to
+/// the workers.
+#[derive(Debug)]
+struct Input<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    // The offset of `buf[pos]` in the stream.
+    offset: u64,
+}
+
+impl<R: Read> Input<R> {
+    /// Makes sure at least `n` bytes are available, returning whether there
+    /// are that many before the end of the stream.
+    fn fill(&mut self, n: usize) -> io::Result<bool> {
+        while self.buf.len() - self.pos < n {
+            if self.pos > 0 {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            let len = self.buf.len();
+            self.buf.resize(cmp::max(len + READ_SIZE, n), 0);
+            let result = self.inner.read(&mut self.buf[len..]);
+            let read = *result.as_ref().unwrap_or(&0);
+            self.buf.truncate(len + read);
+            match result {
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn take(&mut self, n: usize) -> Vec<u8> {
+        let data = self.buf[self.pos..self.pos + n].to_vec();
+        self.consume(n);
+        data
+    }
+
+    /// Returns the size of the BGZF block `at` bytes into the available data,
+    /// or `None` if there isn't a whole one there.
+    fn bgzf_block(&mut self, at: usize) -> io::Result<Option<usize>> {
+        if !self.fill(at + 12)? {
+            return Ok(None);
+        }
+        let header = &self.available()[at..];
+        if header[..3] != [0x1f, 0x8b, 8] || header[3] & FEXTRA == 0 {
+            return Ok(None);
+        }
+        let xlen = usize::from(header[10]) | usize::from(header[11]) << 8;
+        if !self.fill(at + 12 + xlen)? {
+            return Ok(None);
+        }
+        let size = bgzf::block_size(&self.available()[at + 12..at + 12 + xlen]);
+        match size {
+            // Leave anything inconsistent to the sequential decoder to report.
+            Some(size) if size >= 12 + xlen + 8 && self.fill(at + size)? => Ok(Some(size)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(into)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for Input<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            self.fill(1)?;
+        }
+        Ok(self.available())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+        self.offset += amt as u64;
+    }
+}
+
+/// Decodes a run of whole members, the first of which is the given one of
+/// the stream and starts at the given offset.
+fn decode_members(mut data: &[u8], mut member: u64, mut start: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let mut decoder = GzDecoder::for_member(data, member, start, GzDecoderOptions::new());
+        decoder.read_to_end(&mut out)?;
+        let rest = decoder.into_inner();
+        start += (data.len() - rest.len()) as u64;
+        member += 1;
+        data = rest;
+    }
+    Ok(out)
+}
+
+/// A gzip decoder which decodes the members of a multistream on a pool of
+/// threads
+///
+/// This produces the same output as [`MultiGzDecoder`], with the checksum of
+/// every member verified, but decodes members ahead on worker threads. That
+/// requires knowing where members end without decoding them, which is only
+/// the case for BGZF files, whose members record their size in the header.
+/// Members without that are decoded in order on the thread reading from the
+/// decoder, just as `MultiGzDecoder` would.
+///
+/// Compressed input is read ahead of the output, so the underlying reader is
+/// left at an unspecified position.
+///
+/// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::GzEncoder;
+/// use flate2::read::ParMultiGzDecoder;
+///
+/// # fn main() {
+/// #    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// #    e.write_all(b"Hello World").unwrap();
+/// #    let bytes = e.finish().unwrap();
+/// #    println!("{}", decode_reader(bytes).unwrap());
+/// # }
+/// #
+/// // Uncompresses a Gz Encoded vector of bytes and returns a string or error
+/// // Here &[u8] implements Read
+///
+/// fn decode_reader(bytes: Vec<u8>) -> io::Result<String> {
+///    let mut gz = ParMultiGzDecoder::new(&bytes[..]);
+///    let mut s = String::new();
+///    gz.read_to_string(&mut s)?;
+///    Ok(s)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ParMultiGzDecoder<R> {
+    // Exactly one of these is set, the latter while a member whose size isn't
+    // known is being decoded.
+    input: Option<Input<R>>,
+    sequential: Option<GzDecoder<Input<R>>>,
+    // Set when the next member has to be decoded sequentially, once the ones
+    // before it are out.
+    sequential_next: bool,
+    pool: Pool,
+    block_size: usize,
+    max_pending: usize,
+    pending: VecDeque<mpsc::Receiver<io::Result<Vec<u8>>>>,
+    // The index of the next member to be scanned.
+    member: u64,
+    // Decoded data which has yet to be read.
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<R: Read> ParMultiGzDecoder<R> {
+    /// Creates a new decoder from the given reader, using as many threads as
+    /// the system has available parallelism.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the threads can't be spawned.
+    pub fn new(r: R) -> ParMultiGzDecoder<R> {
+        ParMultiGzDecoder::new_with_options(r, ParOptions::new()).unwrap()
+    }
+
+    /// Creates a new decoder from the given reader, using the given number of
+    /// threads. Each thread is handed whole members amounting to about the
+    /// block size of compressed data at a time.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the threads can't be spawned.
+    pub fn new_with_options(r: R, options: ParOptions) -> io::Result<ParMultiGzDecoder<R>> {
+        Ok(ParMultiGzDecoder {
+            input: Some(Input {
+                inner: r,
+                buf: Vec::new(),
+                pos: 0,
+                offset: 0,
+            }),
+            sequential: None,
+            sequential_next: false,
+            pool: Pool::new(options.threads)?,
+            block_size: options.block_size,
+            max_pending: 2 * options.threads,
+            pending: VecDeque::new(),
+            member: 0,
+            out: Vec::new(),
+            out_pos: 0,
+        })
+    }
+
+    /// Hands runs of BGZF members to the workers until enough are in flight,
+    /// or a member of unknown size is reached.
+    fn scan(&mut self) -> io::Result<()> {
+        let input = match self.input {
+            Some(ref mut input) if !self.sequential_next => input,
+            _ => return Ok(()),
+        };
+        while self.pending.len() < self.max_pending {
+            let mut len = 0;
+            let mut members = 0;
+            while len < self.block_size {
+                match input.bgzf_block(len)? {
+                    Some(size) => len += size,
+                    None => break,
+                }
+                members += 1;
+            }
+            if len == 0 {
+                // An empty stream is left to the sequential decoder to report.
+                self.sequential_next = self.member == 0 || input.fill(1)?;
+                return Ok(());
+            }
+
+            let (member, start) = (self.member, input.offset);
+            let data = input.take(len);
+            let result = self.pool.run(move || decode_members(&data, member, start));
+            self.pending.push_back(result);
+            self.member += members;
+        }
+        Ok(())
+    }
+}
+
+impl<R> ParMultiGzDecoder<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        match self.sequential {
+            Some(ref decoder) => &decoder.get_ref().inner,
+            None => &self.input.as_ref().unwrap().inner,
+        }
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        match self.sequential {
+            Some(ref mut decoder) => &mut decoder.get_mut().inner,
+            None => &mut self.input.as_mut().unwrap().inner,
+        }
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        match self.sequential {
+            Some(decoder) => decoder.into_inner().inner,
+            None => self.input.unwrap().inner,
+        }
+    }
+}
+
+impl<R: Read> Read for ParMultiGzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.out_pos < self.out.len() {
+                let n = (&self.out[self.out_pos..]).read(into)?;
+                self.out_pos += n;
+                return Ok(n);
+            }
+            self.scan()?;
+            if let Some(result) = self.pending.pop_front() {
+                self.out = wait(&result)?;
+                self.out_pos = 0;
+                continue;
+            }
+
+            if let Some(ref mut decoder) = self.sequential {
+                let n = decoder.read(into)?;
+                if n > 0 || into.is_empty() {
+                    return Ok(n);
+                }
+                let decoder = self.sequential.take().unwrap();
+                self.input = Some(decoder.into_inner());
+                self.member += 1;
+                continue;
+            }
+            if self.sequential_next {
+                let input = self.input.take().unwrap();
+                let start = input.offset;
+                let options = GzDecoderOptions::new();
+                let decoder = GzDecoder::for_member(input, self.member, start, options);
+                self.sequential = Some(decoder);
+                self.sequential_next = false;
+                continue;
+            }
+            return Ok(0);
+        }
+    }
+}
+
+impl<R: Read + Write> Write for ParMultiGzDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+/// Compressed input read in chunks of a fixed size, which are kept until the
+/// data decoded from them has been handed out.
+#[derive(Debug)]
+struct Chunks<R> {
+    inner: R,
+    size: usize,
+    chunks: VecDeque<Arc<Vec<u8>>>,
+    // The index of the first chunk kept.
+    first: u64,
+    eof: bool,
+}
+
+impl<R: Read> Chunks<R> {
+    /// Returns the chunk with the given index, reading up to it if need be,
+    /// or `None` if the input ends before it.
+    fn get(&mut self, index: u64) -> io::Result<Option<Arc<Vec<u8>>>> {
+        while self.first + self.chunks.len() as u64 <= index && !self.eof {
+            let mut chunk = vec![0; self.size];
+            let mut len = 0;
+            while len < self.size {
+                match self.inner.read(&mut chunk[len..]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            chunk.truncate(len);
+            self.eof = len < self.size;
+            if len > 0 {
+                self.chunks.push_back(Arc::new(chunk));
+            }
+        }
+        let kept = index.checked_sub(self.first);
+        Ok(kept.and_then(|i| self.chunks.get(i as usize)).cloned())
+    }
+
+    /// Returns the input from the given offset to the end of its chunk, which
+    /// is empty at the end of the input.
+    fn bytes_at(&mut self, offset: u64) -> io::Result<&[u8]> {
+        let size = self.size as u64;
+        self.get(offset / size)?;
+        let chunk = self.chunks.get((offset / size - self.first) as usize);
+        let pos = (offset % size) as usize;
+        Ok(chunk.and_then(|chunk| chunk.get(pos..)).unwrap_or(&[]))
+    }
+
+    /// Drops the chunks before the one with the given index.
+    fn release(&mut self, index: u64) {
+        while self.first < index && self.chunks.pop_front().is_some() {
+            self.first += 1;
+        }
+    }
+}
+
+/// Reads the input of a [`Chunks`] from an offset on.
+struct ChunkReader<'a, R> {
+    chunks: &'a mut Chunks<R>,
+    offset: u64,
+}
+
+impl<'a, R: Read> Read for ChunkReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = self.chunks.bytes_at(self.offset)?.read(into)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// Fills in the bytes of the window which speculatively decoded data refers
+/// to, now that they are known.
+fn resolve(symbols: &[u16], window: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    // The window is only shorter than that at the start of the stream.
+    let missing = WINDOW_SIZE - window.len();
+    out.reserve(symbols.len());
+    for &symbol in symbols {
+        let byte = match symbol.checked_sub(256) {
+            None => symbol as u8,
+            Some(index) => match usize::from(index).checked_sub(missing) {
+                Some(index) => window[index],
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid distance too far back",
+                    ))
+                }
+            },
+        };
+        out.push(byte);
+    }
+    Ok(())
+}
+
+/// A gzip decoder which decodes a single member on a pool of threads
+///
+/// Deflate data can't be split up without decoding it, as blocks aren't
+/// aligned to bytes and refer back to the data before them. This decoder
+/// splits the compressed data into chunks anyway, and has the workers guess
+/// where the first block of each chunk starts and decode from there, with
+/// references to the unknown 32KiB before it left as placeholders. Once the
+/// chunk before has been decoded, a guess is checked against where that one
+/// ended and the placeholders are filled in. A wrong guess, which is rare, is
+/// decoded again in order with [`Decompress`].
+///
+/// The output is the same as that of [`GzDecoder`], and the checksum and size
+/// in the trailer are verified. Like `GzDecoder`, only the first member of the
+/// stream is decoded; for streams made of many small members, such as BGZF
+/// files, [`ParMultiGzDecoder`] is the better fit.
+///
+/// Compressed input is read ahead of the output, so the underlying reader is
+/// left at an unspecified position.
+///
+/// [`Decompress`]: ../struct.Decompress.html
+/// [`GzDecoder`]: struct.GzDecoder.html
+/// [`ParMultiGzDecoder`]: struct.ParMultiGzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::GzEncoder;
+/// use flate2::read::ParGzDecoder;
+///
+/// # fn main() {
+/// #    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// #    e.write_all(b"Hello World").unwrap();
+/// #    let bytes = e.finish().unwrap();
+/// #    println!("{}", decode_reader(bytes).unwrap());
+/// # }
+/// #
+/// // Uncompresses a Gz Encoded vector of bytes and returns a string or error
+/// // Here &[u8] implements Read
+///
+/// fn decode_reader(bytes: Vec<u8>) -> io::Result<String> {
+///    let mut gz = ParGzDecoder::new(&bytes[..]);
+///    let mut s = String::new();
+///    gz.read_to_string(&mut s)?;
+///    Ok(s)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ParGzDecoder<R> {
+    input: Chunks<R>,
+    pool: Pool,
+    max_pending: usize,
+    // The guesses for the chunks after the one being decoded, in order, along
+    // with the index of their chunk.
+    pending: VecDeque<(u64, mpsc::Receiver<Option<Speculation>>)>,
+    header: Option<GzHeader>,
+    // The bit of the input at which decoding continues.
+    pos: u64,
+    done: bool,
+    // The last 32KiB of output.
+    window: Vec<u8>,
+    crc: Crc,
+    // Decoded data which has yet to be read.
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<R: Read> ParGzDecoder<R> {
+    /// Creates a new decoder from the given reader, using as many threads as
+    /// the system has available parallelism, and chunks of 4 MiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the threads can't be spawned.
+    pub fn new(r: R) -> ParGzDecoder<R> {
+        let options = ParOptions::new().block_size(DEFAULT_CHUNK_SIZE);
+        ParGzDecoder::new_with_options(r, options).unwrap()
+    }
+
+    /// Creates a new decoder from the given reader, using the given number of
+    /// threads. The block size is the amount of compressed data in each chunk
+    /// a thread decodes, which should be large compared to the deflate blocks
+    /// in the stream.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the threads can't be spawned.
+    pub fn new_with_options(r: R, options: ParOptions) -> io::Result<ParGzDecoder<R>> {
+        Ok(ParGzDecoder {
+            input: Chunks {
+                inner: r,
+                size: options.block_size,
+                chunks: VecDeque::new(),
+                first: 0,
+                eof: false,
+            },
+            pool: Pool::new(options.threads)?,
+            max_pending: options.threads,
+            pending: VecDeque::new(),
+            header: None,
+            pos: 0,
+            done: false,
+            window: Vec::with_capacity(WINDOW_SIZE),
+            crc: Crc::new(),
+            out: Vec::new(),
+            out_pos: 0,
+        })
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut reader = ChunkReader {
+            chunks: &mut self.input,
+            offset: 0,
+        };
+        let header = read_gz_header(&mut reader, 0, 0, GzHeaderLimits::new())?;
+        self.pos = reader.offset * 8;
+        self.header = Some(header);
+        Ok(())
+    }
+
+    /// Hands the chunks following the one being decoded to the workers until
+    /// enough are in flight.
+    fn scan(&mut self) -> io::Result<()> {
+        let current = self.pos / 8 / self.input.size as u64;
+        // Guesses for chunks a block has been decoded across are of no use.
+        while let Some(&(index, _)) = self.pending.front() {
+            if index >= current {
+                break;
+            }
+            self.pending.pop_front();
+        }
+        let mut next = self.pending.back().map_or(current, |&(index, _)| index) + 1;
+        while self.pending.len() < self.max_pending {
+            let chunk = match self.input.get(next)? {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            // Decoding goes on into the following chunk up to the end of the
+            // block reaching past this one.
+            let following = self.input.get(next + 1)?;
+            let stop = self.input.size * 8;
+            let result = self.pool.run(move || {
+                let mut data = chunk.to_vec();
+                if let Some(following) = following {
+                    data.extend_from_slice(&following);
+                }
+                inflate::speculate(&data, stop)
+            });
+            self.pending.push_back((next, result));
+            next += 1;
+        }
+        Ok(())
+    }
+
+    /// Decodes the blocks up to the end of the first one reaching into the
+    /// next chunk, or to the end of the stream.
+    fn decode(&mut self) -> io::Result<()> {
+        let size = self.input.size as u64;
+        let current = self.pos / 8 / size;
+        let speculation = match self.pending.front() {
+            Some(&(index, _)) if index == current => {
+                let (_, result) = self.pending.pop_front().unwrap();
+                // A panicking worker only costs the guess.
+                result.recv().unwrap_or(None)
+            }
+            _ => None,
+        };
+
+        self.out.clear();
+        self.out_pos = 0;
+        let chunk_start = current * size * 8;
+        let (end, last) = match speculation {
+            Some(ref guess) if guess.starts.contains(&((self.pos - chunk_start) as usize)) => {
+                resolve(&guess.symbols, &self.window, &mut self.out)?;
+                (chunk_start + guess.end as u64, guess.last)
+            }
+            _ => self.decode_exact((current + 1) * size * 8)?,
+        };
+
+        self.crc.update(&self.out);
+        if self.out.len() >= WINDOW_SIZE {
+            self.window.clear();
+            self.window
+                .extend_from_slice(&self.out[self.out.len() - WINDOW_SIZE..]);
+        } else {
+            self.window.extend_from_slice(&self.out);
+            let excess = self.window.len().saturating_sub(WINDOW_SIZE);
+            self.window.drain(..excess);
+        }
+        self.pos = end;
+        self.input.release(end / 8 / size);
+
+        if last {
+            // The trailer starts at the byte after the final block.
+            let offset = end / 8 + u64::from(end % 8 != 0);
+            let mut trailer = [0; 8];
+            ChunkReader {
+                chunks: &mut self.input,
+                offset,
+            }
+            .read_exact(&mut trailer)?;
+            check_trailer(&trailer, &self.crc, 0, offset)?;
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    /// Decodes in order with `Decompress` up to the end of the first block
+    /// ending at or after bit `stop`, returning where decoding stopped and
+    /// whether that was the final block.
+    fn decode_exact(&mut self, stop: u64) -> io::Result<(u64, bool)> {
+        let mut decompress = Decompress::new(false);
+        let mut offset = self.pos / 8;
+        let skip = (self.pos % 8) as u8;
+        if skip > 0 {
+            let byte = match self.input.bytes_at(offset)?.first() {
+                Some(&byte) => byte,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            decompress.prime(8 - skip, u16::from(byte >> skip))?;
+            offset += 1;
+        }
+        if !self.window.is_empty() {
+            decompress.set_dictionary(&self.window)?;
+        }
+
+        loop {
+            if self.out.capacity() - self.out.len() < READ_SIZE {
+                self.out.reserve(cmp::max(self.out.len(), READ_SIZE));
+            }
+            let before = (decompress.total_in(), decompress.total_out());
+            let input = self.input.bytes_at(offset + before.0)?;
+            let eof = input.is_empty();
+            decompress.decompress_vec(input, &mut self.out, FlushDecompress::Block)?;
+
+            if decompress.at_block_boundary() {
+                let read = offset + decompress.total_in();
+                let end = read * 8 - u64::from(decompress.unused_bits());
+                if decompress.last_block() || end >= stop {
+                    return Ok((end, decompress.last_block()));
+                }
+            }
+            if eof && before == (decompress.total_in(), decompress.total_out()) {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Returns the header associated with this stream, once it has been read.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+}
+
+impl<R> ParGzDecoder<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.input.inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.input.inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.input.inner
+    }
+}
+
+impl<R: Read> Read for ParGzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.out_pos < self.out.len() {
+                let n = (&self.out[self.out_pos..]).read(into)?;
+                self.out_pos += n;
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            if self.header.is_none() {
+                self.read_header()?;
+            }
+            self.scan()?;
+            self.decode()?;
+        }
+    }
+}
+
+impl<R: Read + Write> Write for ParGzDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::{resolve, ParGzDecoder};
+    use crate::inflate::{speculate, WINDOW_SIZE};
+    use crate::write::{DeflateEncoder, GzEncoder};
+    use crate::{Compression, Decompress, FlushDecompress, ParOptions};
+    use rand::{thread_rng, Rng};
+
+    /// Text made of a few hundred made-up words, which compresses into many
+    /// dynamic blocks full of matches.
+    fn sample() -> Vec<u8> {
+        let mut rng = thread_rng();
+        let words = (0..300)
+            .map(|_| {
+                let len = rng.gen_range(2..10);
+                let mut word = (0..len)
+                    .map(|_| rng.gen_range(b'a'..=b'z'))
+                    .collect::<Vec<_>>();
+                word.push(b' ');
+                word
+            })
+            .collect::<Vec<_>>();
+        (0..300_000)
+            .flat_map(|_| words[rng.gen_range(0..words.len())].clone())
+            .collect()
+    }
+
+    /// Decodes `data` in order, returning the bit at which each block ends
+    /// along with the amount of output up to there.
+    fn block_ends(data: &[u8], size: usize) -> Vec<(usize, usize)> {
+        let mut d = Decompress::new(false);
+        let mut out = Vec::with_capacity(size);
+        let mut ends = vec![(0, 0)];
+        loop {
+            let before = (d.total_in(), d.total_out());
+            let input = &data[d.total_in() as usize..];
+            d.decompress_vec(input, &mut out, FlushDecompress::Block)
+                .unwrap();
+            if d.at_block_boundary() {
+                let end = d.total_in() as usize * 8 - usize::from(d.unused_bits());
+                if end != ends[ends.len() - 1].0 {
+                    ends.push((end, out.len()));
+                }
+                if d.last_block() {
+                    return ends;
+                }
+            }
+            assert!(before != (d.total_in(), d.total_out()));
+        }
+    }
+
+    #[test]
+    fn speculate_across_blocks() {
+        let data = sample();
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let compressed = e.finish().unwrap();
+        let ends = block_ends(&compressed, data.len());
+        assert!(ends.len() > 8, "{} blocks", ends.len() - 1);
+
+        // Split the stream in the middle of a block, far enough in for a full
+        // window before it, and speculate over several blocks after that.
+        let split = compressed.len() / 3;
+        let stop = (compressed.len() - split) * 8 / 2;
+        let guess = speculate(&compressed[split..], stop).unwrap();
+        let first = ends.iter().position(|&(bit, _)| bit >= split * 8).unwrap();
+        let (start, out_start) = ends[first];
+        assert!(guess.starts.contains(&(start - split * 8)));
+        let last = ends
+            .iter()
+            .position(|&(bit, _)| bit == split * 8 + guess.end)
+            .unwrap();
+        assert!(last > first + 2, "{} blocks", last - first);
+        assert!(!guess.last);
+
+        // Matches reaching back across the split are left for later.
+        assert!(guess.symbols.iter().any(|&symbol| symbol >= 256));
+        let window = &data[out_start - WINDOW_SIZE..out_start];
+        let mut out = Vec::new();
+        resolve(&guess.symbols, window, &mut out).unwrap();
+        assert!(out == &data[out_start..ends[last].1]);
+
+        // Speculating up to the end finds the final block.
+        let guess = speculate(&compressed[split..], usize::MAX).unwrap();
+        assert!(guess.last);
+        assert_eq!(split * 8 + guess.end, ends[ends.len() - 1].0);
+    }
+
+    #[test]
+    fn resolve_window() {
+        let mut out = Vec::new();
+        resolve(
+            &[b'a'.into(), 256 + WINDOW_SIZE as u16 - 1],
+            b"xy",
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(out, b"ay");
+
+        // At the start of the stream, nothing comes before the output.
+        let err = resolve(&[256 + WINDOW_SIZE as u16 - 3], b"xy", &mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    fn decode(bytes: &[u8], block_size: usize) -> std::io::Result<Vec<u8>> {
+        let options = ParOptions::new().threads(3).block_size(block_size);
+        let mut out = Vec::new();
+        ParGzDecoder::new_with_options(bytes, options)
+            .unwrap()
+            .read_to_end(&mut out)
+            .map(|_| out)
+    }
+
+    #[test]
+    fn corrupt_speculated_chunk() {
+        let data = sample();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let bytes = e.finish().unwrap();
+        let block_size = bytes.len() / 8;
+        assert!(decode(&bytes, block_size).unwrap() == data);
+
+        // Damage well inside a chunk past the first, which the workers decode
+        // ahead of time, whether that breaks the block structure or only
+        // changes some of the data.
+        for &(at, len) in [(5, 1), (5, 200), (6, 3000)].iter() {
+            let mut corrupt = bytes.clone();
+            let start = at * block_size + block_size / 2;
+            for byte in &mut corrupt[start..start + len] {
+                *byte ^= 0x5a;
+            }
+            assert!(decode(&corrupt, block_size).is_err());
+        }
+    }
+}
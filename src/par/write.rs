@@ -0,0 +1,528 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use super::{wait, ParOptions, Pool};
+use crate::adler::Adler32;
+use crate::crc::Crc;
+use crate::{Compress, CompressOptions, Compression, FlushCompress, Format, GzBuilder, Status};
+
+// The furthest deflate can refer back, so each block is primed with this much
+// of the data before it.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// The checksum in the trailer of a stream, put together from the checksums
+/// of its blocks.
+#[derive(Debug)]
+enum Check {
+    Crc(Crc),
+    Adler(Adler32),
+}
+
+impl Check {
+    fn new(format: Format) -> Check {
+        match format {
+            Format::Zlib => Check::Adler(Adler32::new()),
+            _ => Check::Crc(Crc::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Check::Crc(crc) => crc.update(data),
+            Check::Adler(adler) => adler.update(data),
+        }
+    }
+
+    fn combine(&mut self, other: &Check) {
+        match (self, other) {
+            (Check::Crc(crc), Check::Crc(other)) => crc.combine(other),
+            (Check::Adler(adler), Check::Adler(other)) => adler.combine(other),
+            _ => unreachable!(),
+        }
+    }
+
+    fn trailer(&self) -> Vec<u8> {
+        match self {
+            Check::Crc(crc) => {
+                let mut trailer = crc.sum().to_le_bytes().to_vec();
+                trailer.extend(&crc.amount().to_le_bytes());
+                trailer
+            }
+            Check::Adler(adler) => adler.sum().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    data: Vec<u8>,
+    check: Check,
+}
+
+/// Compresses `input` as a part of a raw deflate stream, ending it with the
+/// given flush.
+fn deflate(compress: &mut Compress, input: &[u8], flush: FlushCompress) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() + input.len() / 16 + 64);
+    let start = compress.total_in();
+    loop {
+        let consumed = (compress.total_in() - start) as usize;
+        let status = compress.compress_vec(&input[consumed..], &mut out, flush)?;
+        let done = compress.total_in() - start == input.len() as u64;
+        match status {
+            Status::StreamEnd => return Ok(out),
+            // Room left over means the flush is complete.
+            _ if done && flush != FlushCompress::Finish && out.len() < out.capacity() => {
+                return Ok(out)
+            }
+            _ => out.reserve(out.len() + 64),
+        }
+    }
+}
+
+/// The stream-independent part of the parallel encoders.
+#[derive(Debug)]
+struct ParEncoder<W: Write> {
+    obj: Option<W>,
+    pool: Pool,
+    // Compressors that are done with a block, for the next ones to reuse.
+    compressors: Arc<Mutex<Vec<Compress>>>,
+    compress_options: CompressOptions,
+    format: Format,
+    block_size: usize,
+    max_pending: usize,
+    // The data of the block being filled, and the data before it which the
+    // block can refer back to.
+    block: Vec<u8>,
+    window: Vec<u8>,
+    pending: VecDeque<mpsc::Receiver<io::Result<Block>>>,
+    check: Check,
+    // Output which has yet to be written out, starting with the header.
+    buf: Vec<u8>,
+    finished: bool,
+    done: bool,
+}
+
+impl<W: Write> ParEncoder<W> {
+    fn new(
+        w: W,
+        header: Vec<u8>,
+        compress_options: CompressOptions,
+        options: ParOptions,
+        format: Format,
+    ) -> io::Result<ParEncoder<W>> {
+        // Check the options up front rather than on a worker.
+        let compress = Compress::new_with_options(compress_options.clone())?;
+        Ok(ParEncoder {
+            obj: Some(w),
+            pool: Pool::new(options.threads)?,
+            compressors: Arc::new(Mutex::new(vec![compress])),
+            compress_options,
+            format,
+            block_size: options.block_size,
+            max_pending: 2 * options.threads,
+            block: Vec::with_capacity(options.block_size),
+            window: Vec::new(),
+            pending: VecDeque::new(),
+            check: Check::new(format),
+            buf: header,
+            finished: false,
+            done: false,
+        })
+    }
+
+    /// Hands the block being filled to a worker.
+    fn submit(&mut self, last: bool) {
+        let input = mem::replace(&mut self.block, Vec::with_capacity(self.block_size));
+        let dictionary = self.window.clone();
+        if input.len() >= WINDOW_SIZE {
+            self.window = input[input.len() - WINDOW_SIZE..].to_vec();
+        } else {
+            self.window.extend(&input);
+            let excess = self.window.len().saturating_sub(WINDOW_SIZE);
+            self.window.drain(..excess);
+        }
+
+        let compressors = self.compressors.clone();
+        let options = self.compress_options.clone();
+        let format = self.format;
+        let result = self.pool.run(move || {
+            let reused = compressors.lock().unwrap().pop();
+            let mut compress = match reused {
+                Some(compress) => compress,
+                None => Compress::new_with_options(options)?,
+            };
+            compress.reset();
+            if !dictionary.is_empty() {
+                compress.set_dictionary(&dictionary)?;
+            }
+            // Every block but the last ends on a byte boundary, so that the
+            // next one can simply be appended.
+            let flush = if last {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::Sync
+            };
+            let data = deflate(&mut compress, &input, flush)?;
+            let mut check = Check::new(format);
+            check.update(&input);
+            compressors.lock().unwrap().push(compress);
+            Ok(Block { data, check })
+        });
+        self.pending.push_back(result);
+    }
+
+    /// Writes out the blocks which have been compressed, in order, waiting for
+    /// all of them if `all` is set and otherwise only for as many as needed to
+    /// bound the number in flight.
+    fn collect(&mut self, all: bool) -> io::Result<()> {
+        loop {
+            let result = match self.pending.front() {
+                None => break,
+                Some(result) if all || self.pending.len() > self.max_pending => wait(result),
+                Some(result) => match result.try_recv() {
+                    Ok(result) => result,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => wait(result),
+                },
+            };
+            self.pending.pop_front();
+            let block = result?;
+            self.check.combine(&block.check);
+            if self.buf.is_empty() {
+                self.buf = block.data;
+            } else {
+                self.buf.extend(block.data);
+            }
+            self.dump()?;
+        }
+        self.dump()
+    }
+
+    fn dump(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let n = self.obj.as_mut().unwrap().write(&self.buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.submit(true);
+            self.finished = true;
+        }
+        self.collect(true)?;
+        if !self.done {
+            let trailer = self.check.trailer();
+            self.buf.extend(trailer);
+            self.done = true;
+        }
+        self.dump()?;
+        self.obj.as_mut().unwrap().flush()
+    }
+
+    fn take_inner(&mut self) -> W {
+        self.obj.take().unwrap()
+    }
+
+    fn get_ref(&self) -> &W {
+        self.obj.as_ref().unwrap()
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        self.obj.as_mut().unwrap()
+    }
+}
+
+impl<W: Write> Write for ParEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished, "writing to a finished encoder");
+        self.collect(false)?;
+        let n = data.len().min(self.block_size - self.block.len());
+        self.block.extend(&data[..n]);
+        if self.block.len() == self.block_size {
+            self.submit(false);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.finished && !self.block.is_empty() {
+            self.submit(false);
+        }
+        self.collect(true)?;
+        self.obj.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for ParEncoder<W> {
+    fn drop(&mut self) {
+        if self.obj.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A gzip encoder which compresses its input on a pool of threads
+///
+/// The input is split into blocks, which are compressed in parallel and
+/// written out in order as a single gzip member, just like the output of
+/// [`GzEncoder`]. Each block is primed with the 32 KiB of data before it and
+/// ends with a sync flush, so the output is only slightly larger than when
+/// compressing on one thread. This is the approach taken by `pigz`.
+///
+/// Compressed blocks are written to the underlying writer `W` as they are
+/// done, on the thread calling into the encoder.
+///
+/// [`GzEncoder`]: struct.GzEncoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::Compression;
+/// use flate2::write::ParGzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut e = ParGzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World")?;
+/// let compressed = e.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ParGzEncoder<W: Write> {
+    inner: ParEncoder<W>,
+}
+
+pub(crate) fn par_gz_encoder<W: Write>(
+    header: Vec<u8>,
+    w: W,
+    compress_options: CompressOptions,
+    options: ParOptions,
+) -> io::Result<ParGzEncoder<W>> {
+    Ok(ParGzEncoder {
+        inner: ParEncoder::new(w, header, compress_options, options, Format::Gzip)?,
+    })
+}
+
+impl<W: Write> ParGzEncoder<W> {
+    /// Creates a new encoder which will use the given compression level and as
+    /// many threads as the system has available parallelism.
+    ///
+    /// The encoder is not configured specially for the emitted header. For
+    /// header configuration, see the `GzBuilder` type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the threads can't be spawned.
+    pub fn new(w: W, level: Compression) -> ParGzEncoder<W> {
+        ParGzEncoder::new_with_options(w, level, ParOptions::new()).unwrap()
+    }
+
+    /// Creates a new encoder which will use the given compression level and
+    /// the given number of threads and block size.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `level`, or the threads
+    /// can't be spawned.
+    pub fn new_with_options(
+        w: W,
+        level: Compression,
+        options: ParOptions,
+    ) -> io::Result<ParGzEncoder<W>> {
+        GzBuilder::new().par_write(w, level, options)
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this encoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Attempt to finish this output stream, waiting for all blocks to be
+    /// compressed and writing out the trailer.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to finish the stream, returning any
+    /// errors which happen.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.inner.try_finish()
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.try_finish()?;
+        Ok(self.inner.take_inner())
+    }
+}
+
+impl<W: Write> Write for ParGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Read + Write> Read for ParGzEncoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+/// A zlib encoder which compresses its input on a pool of threads
+///
+/// This is the zlib counterpart of [`ParGzEncoder`], producing a single zlib
+/// stream just like [`ZlibEncoder`].
+///
+/// [`ParGzEncoder`]: struct.ParGzEncoder.html
+/// [`ZlibEncoder`]: struct.ZlibEncoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, ParOptions};
+/// use flate2::write::ParZlibEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let options = ParOptions::new().threads(4);
+/// let mut e = ParZlibEncoder::new_with_options(Vec::new(), Compression::default(), options)?;
+/// e.write_all(b"Hello World")?;
+/// let compressed = e.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ParZlibEncoder<W: Write> {
+    inner: ParEncoder<W>,
+}
+
+impl<W: Write> ParZlibEncoder<W> {
+    /// Creates a new encoder which will use the given compression level and as
+    /// many threads as the system has available parallelism.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the threads can't be spawned.
+    pub fn new(w: W, level: Compression) -> ParZlibEncoder<W> {
+        ParZlibEncoder::new_with_options(w, level, ParOptions::new()).unwrap()
+    }
+
+    /// Creates a new encoder which will use the given compression level and
+    /// the given number of threads and block size.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the compressor can't be set up for `level`, or the threads
+    /// can't be spawned.
+    pub fn new_with_options(
+        w: W,
+        level: Compression,
+        options: ParOptions,
+    ) -> io::Result<ParZlibEncoder<W>> {
+        // The level is only advertised in the header, as zlib does.
+        let flevel = match level.level() {
+            0..=1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        };
+        let cmf = 0x78;
+        let mut flg = flevel << 6;
+        flg |= 31 - (cmf << 8 | flg) % 31;
+        let header = vec![cmf as u8, flg as u8];
+
+        let compress_options = CompressOptions::new(level).format(Format::Raw);
+        Ok(ParZlibEncoder {
+            inner: ParEncoder::new(w, header, compress_options, options, Format::Zlib)?,
+        })
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this encoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Attempt to finish this output stream, waiting for all blocks to be
+    /// compressed and writing out the trailer.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to finish the stream, returning any
+    /// errors which happen.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.inner.try_finish()
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.try_finish()?;
+        Ok(self.inner.take_inner())
+    }
+}
+
+impl<W: Write> Write for ParZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Read + Write> Read for ParZlibEncoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
@@ -2,12 +2,20 @@ use std::io;
 use std::io::prelude::*;
 use std::mem;
 
-use crate::{Compress, Decompress, DecompressError, FlushCompress, FlushDecompress, Status};
+use crate::mem::decompress_truncated;
+use crate::{
+    Compress, Decompress, DecompressError, DecompressLimits, FlushCompress, FlushDecompress, Status,
+};
 
 #[derive(Debug)]
 pub struct Writer<W: Write, D: Ops> {
     obj: Option<W>,
     pub data: D,
+    pub limits: DecompressLimits,
+    // The totals of earlier streams decoded by this writer, which count
+    // towards the limits as well.
+    pub base_in: u64,
+    pub base_out: u64,
     buf: Vec<u8>,
 }
 
@@ -15,18 +23,13 @@ pub trait Ops {
     type Flush: Flush;
     fn total_in(&self) -> u64;
     fn total_out(&self) -> u64;
-    fn run(
-        &mut self,
-        input: &[u8],
-        output: &mut [u8],
-        flush: Self::Flush,
-    ) -> Result<Status, DecompressError>;
+    fn run(&mut self, input: &[u8], output: &mut [u8], flush: Self::Flush) -> io::Result<Status>;
     fn run_vec(
         &mut self,
         input: &[u8],
         output: &mut Vec<u8>,
         flush: Self::Flush,
-    ) -> Result<Status, DecompressError>;
+    ) -> io::Result<Status>;
 }
 
 impl Ops for Compress {
@@ -37,21 +40,17 @@ impl Ops for Compress {
     fn total_out(&self) -> u64 {
         self.total_out()
     }
-    fn run(
-        &mut self,
-        input: &[u8],
-        output: &mut [u8],
-        flush: FlushCompress,
-    ) -> Result<Status, DecompressError> {
-        Ok(self.compress(input, output, flush).unwrap())
+    fn run(&mut self, input: &[u8], output: &mut [u8], flush: FlushCompress) -> io::Result<Status> {
+        self.compress(input, output, flush).map_err(io::Error::from)
     }
     fn run_vec(
         &mut self,
         input: &[u8],
         output: &mut Vec<u8>,
         flush: FlushCompress,
-    ) -> Result<Status, DecompressError> {
-        Ok(self.compress_vec(input, output, flush).unwrap())
+    ) -> io::Result<Status> {
+        self.compress_vec(input, output, flush)
+            .map_err(io::Error::from)
     }
 }
 
@@ -68,16 +67,16 @@ impl Ops for Decompress {
         input: &[u8],
         output: &mut [u8],
         flush: FlushDecompress,
-    ) -> Result<Status, DecompressError> {
-        self.decompress(input, output, flush)
+    ) -> io::Result<Status> {
+        self.decompress(input, output, flush).map_err(corrupt)
     }
     fn run_vec(
         &mut self,
         input: &[u8],
         output: &mut Vec<u8>,
         flush: FlushDecompress,
-    ) -> Result<Status, DecompressError> {
-        self.decompress_vec(input, output, flush)
+    ) -> io::Result<Status> {
+        self.decompress_vec(input, output, flush).map_err(corrupt)
     }
 }
 
@@ -121,23 +120,7 @@ where
     D: Ops,
 {
     loop {
-        let (read, consumed, ret, eof);
-        {
-            let input = obj.fill_buf()?;
-            eof = input.is_empty();
-            let before_out = data.total_out();
-            let before_in = data.total_in();
-            let flush = if eof {
-                D::Flush::finish()
-            } else {
-                D::Flush::none()
-            };
-            ret = data.run(input, dst, flush);
-            read = (data.total_out() - before_out) as usize;
-            consumed = (data.total_in() - before_in) as usize;
-        }
-        obj.consume(consumed);
-
+        let (read, eof, ret) = step(obj, data, dst)?;
         match ret {
             // If we haven't ready any data and we haven't hit EOF yet,
             // then we need to keep asking for more data because if we
@@ -148,31 +131,144 @@ where
             }
             Ok(Status::Ok) | Ok(Status::BufError) | Ok(Status::StreamEnd) => return Ok(read),
 
-            Err(..) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "corrupt deflate stream",
-                ))
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Like `read`, but instead of failing on corrupt data this skips ahead to
+/// the next full flush point and carries on from there.
+pub fn read_recovering<R: BufRead>(
+    obj: &mut R,
+    data: &mut Decompress,
+    dst: &mut [u8],
+) -> io::Result<usize> {
+    loop {
+        let (read, eof, ret) = step(obj, data, dst)?;
+        match ret {
+            Ok(Status::Ok) | Ok(Status::BufError) if read == 0 && !eof && !dst.is_empty() => {
+                continue
+            }
+            Ok(Status::Ok) | Ok(Status::BufError) | Ok(Status::StreamEnd) => return Ok(read),
+
+            Err(..) if !eof => {
+                sync(obj, data)?;
+                if read > 0 {
+                    return Ok(read);
+                }
             }
+            Err(err) => return Err(err),
         }
     }
 }
 
+/// Like `read`, or `read_recovering` if `recovering` is set, but fails once
+/// the output of `data` exceeds `limits`.
+pub fn read_limited<R: BufRead>(
+    obj: &mut R,
+    data: &mut Decompress,
+    dst: &mut [u8],
+    limits: &DecompressLimits,
+    recovering: bool,
+) -> io::Result<usize> {
+    let dst = limits.clamp(data.total_out(), dst);
+    let n = if recovering {
+        read_recovering(obj, data, dst)?
+    } else {
+        read(obj, data, dst)?
+    };
+    Ok(limits.check_read(data.total_in(), data.total_out(), n)?)
+}
+
+/// Runs `data` once over what `obj` has buffered, returning the number of
+/// bytes produced and whether the input has reached EOF.
+fn step<R, D>(
+    obj: &mut R,
+    data: &mut D,
+    dst: &mut [u8],
+) -> io::Result<(usize, bool, io::Result<Status>)>
+where
+    R: BufRead,
+    D: Ops,
+{
+    let (read, consumed, ret, eof);
+    {
+        let input = obj.fill_buf()?;
+        eof = input.is_empty();
+        let before_out = data.total_out();
+        let before_in = data.total_in();
+        let flush = if eof {
+            D::Flush::finish()
+        } else {
+            D::Flush::none()
+        };
+        ret = data.run(input, dst, flush);
+        read = (data.total_out() - before_out) as usize;
+        consumed = (data.total_in() - before_in) as usize;
+    }
+    obj.consume(consumed);
+    Ok((read, eof, ret))
+}
+
+/// Consumes input until `data` has found a full flush point to resume from.
+fn sync<R: BufRead>(obj: &mut R, data: &mut Decompress) -> io::Result<()> {
+    loop {
+        let (ret, consumed, eof);
+        {
+            let input = obj.fill_buf()?;
+            eof = input.is_empty();
+            let before_in = data.total_in();
+            ret = data.sync(input);
+            consumed = (data.total_in() - before_in) as usize;
+        }
+        obj.consume(consumed);
+
+        match ret {
+            Ok(Status::Ok) => return Ok(()),
+            Ok(..) if !eof => {}
+            Ok(..) => return Err(decompress_truncated(data.total_in()).into()),
+            Err(err) => return Err(corrupt(err)),
+        }
+    }
+}
+
+fn corrupt(err: DecompressError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
 impl<W: Write, D: Ops> Writer<W, D> {
     pub fn new(w: W, d: D) -> Writer<W, D> {
         Writer {
             obj: Some(w),
             data: d,
+            limits: DecompressLimits::new(),
+            base_in: 0,
+            base_out: 0,
             buf: Vec::with_capacity(32 * 1024),
         }
     }
 
+    /// Runs `data` over `input`, appending the output to the buffer. Output
+    /// which exceeds the limits is discarded instead.
+    fn run(&mut self, input: &[u8], flush: D::Flush) -> io::Result<Status> {
+        let status = self.data.run_vec(input, &mut self.buf, flush)?;
+        let checked = self.limits.check(
+            self.base_in + self.data.total_in(),
+            self.base_out + self.data.total_out(),
+        );
+        if let Err(err) = checked {
+            self.buf.clear();
+            return Err(err.into());
+        }
+        Ok(status)
+    }
+
     pub fn finish(&mut self) -> io::Result<()> {
         loop {
             self.dump()?;
 
             let before = self.data.total_out();
-            self.data.run_vec(&[], &mut self.buf, D::Flush::finish())?;
+            self.run(&[], D::Flush::finish())?;
             if before == self.data.total_out() {
                 return Ok(());
             }
@@ -216,7 +312,7 @@ impl<W: Write, D: Ops> Writer<W, D> {
             self.dump()?;
 
             let before_in = self.data.total_in();
-            let ret = self.data.run_vec(buf, &mut self.buf, D::Flush::none());
+            let ret = self.run(buf, D::Flush::none());
             let written = (self.data.total_in() - before_in) as usize;
             let is_stream_end = matches!(ret, Ok(Status::StreamEnd));
 
@@ -227,10 +323,7 @@ impl<W: Write, D: Ops> Writer<W, D> {
                 Ok(st) => match st {
                     Status::Ok | Status::BufError | Status::StreamEnd => Ok((written, st)),
                 },
-                Err(..) => Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "corrupt deflate stream",
-                )),
+                Err(err) => Err(err),
             };
         }
     }
@@ -255,9 +348,7 @@ impl<W: Write, D: Ops> Write for Writer<W, D> {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.data
-            .run_vec(&[], &mut self.buf, D::Flush::sync())
-            .unwrap();
+        self.run(&[], D::Flush::sync())?;
 
         // Unfortunately miniz doesn't actually tell us when we're done with
         // pulling out all the data from the internal stream. To remedy this we
@@ -267,9 +358,7 @@ impl<W: Write, D: Ops> Write for Writer<W, D> {
         loop {
             self.dump()?;
             let before = self.data.total_out();
-            self.data
-                .run_vec(&[], &mut self.buf, D::Flush::none())
-                .unwrap();
+            self.run(&[], D::Flush::none())?;
             if before == self.data.total_out() {
                 break;
             }
@@ -3,7 +3,7 @@ use std::io::prelude::*;
 use std::mem;
 
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{Compress, Decompress, DecompressLimits};
 
 /// A ZLIB encoder, or compressor.
 ///
@@ -47,6 +47,33 @@ impl<R: BufRead> ZlibEncoder<R> {
             data: Compress::new(level, true),
         }
     }
+
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: crate::Compression) -> io::Result<ZlibEncoder<R>> {
+        Ok(ZlibEncoder {
+            obj: r,
+            data: Compress::try_new(level, true)?,
+        })
+    }
+
+    /// Creates a new encoder which will read uncompressed data from the given
+    /// stream and emit the compressed stream using the given `Compress`.
+    ///
+    /// This allows the compression strategy and other parameters of the
+    /// underlying [`Compress`] to be configured. The `Compress` must have been
+    /// created for a zlib stream, with `zlib_header` set to `true`.
+    ///
+    /// [`Compress`]: ../struct.Compress.html
+    pub fn new_with_compress(r: R, compression: Compress) -> ZlibEncoder<R> {
+        ZlibEncoder {
+            obj: r,
+            data: compression,
+        }
+    }
 }
 
 pub fn reset_encoder_data<R>(zlib: &mut ZlibEncoder<R>) {
@@ -154,15 +181,31 @@ impl<R: BufRead + Write> Write for ZlibEncoder<R> {
 pub struct ZlibDecoder<R> {
     obj: R,
     data: Decompress,
+    recovering: bool,
+    limits: DecompressLimits,
 }
 
 impl<R: BufRead> ZlibDecoder<R> {
     /// Creates a new decoder which will decompress data read from the given
     /// stream.
     pub fn new(r: R) -> ZlibDecoder<R> {
+        ZlibDecoder::new_with_decompress(r, Decompress::new(true))
+    }
+
+    /// Creates a new decoder which will decompress data read from the given
+    /// stream using the given `Decompress`.
+    ///
+    /// The `Decompress` must have been created for a zlib stream, for
+    /// example with [`Decompress::try_new`] to handle setup failures without
+    /// panicking.
+    ///
+    /// [`Decompress::try_new`]: ../struct.Decompress.html#method.try_new
+    pub fn new_with_decompress(r: R, decompression: Decompress) -> ZlibDecoder<R> {
         ZlibDecoder {
             obj: r,
-            data: Decompress::new(true),
+            data: decompression,
+            recovering: false,
+            limits: DecompressLimits::new(),
         }
     }
 }
@@ -184,6 +227,29 @@ impl<R> ZlibDecoder<R> {
         mem::replace(&mut self.obj, r)
     }
 
+    /// Configures whether corrupt data is skipped rather than reported as an
+    /// error.
+    ///
+    /// When enabled, a damaged region of the stream is skipped up to the next
+    /// point where it was flushed with [`FlushCompress::Full`], and decoding
+    /// resumes from there as described for [`Decompress::sync`]. The data in
+    /// the skipped region is lost. An error is still returned if no such point
+    /// follows the damage.
+    ///
+    /// [`FlushCompress::Full`]: ../enum.FlushCompress.html#variant.Full
+    /// [`Decompress::sync`]: ../struct.Decompress.html#method.sync
+    pub fn set_recovering(&mut self, recovering: bool) {
+        self.recovering = recovering;
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+
     /// Acquires a reference to the underlying stream
     pub fn get_ref(&self) -> &R {
         &self.obj
@@ -218,7 +284,13 @@ impl<R> ZlibDecoder<R> {
 
 impl<R: BufRead> Read for ZlibDecoder<R> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
-        zio::read(&mut self.obj, &mut self.data, into)
+        zio::read_limited(
+            &mut self.obj,
+            &mut self.data,
+            into,
+            &self.limits,
+            self.recovering,
+        )
     }
 }
 
@@ -10,7 +10,7 @@ mod tests {
     use rand::{thread_rng, Rng};
 
     use crate::zlib::{read, write};
-    use crate::Compression;
+    use crate::{Compression, FlushCompress};
 
     #[test]
     fn roundtrip() {
@@ -156,4 +156,39 @@ mod tests {
             v == w.finish().unwrap().finish().unwrap()
         }
     }
+
+    #[test]
+    fn recovering() {
+        // Three parts separated by full flush points, with the middle one
+        // destroyed.
+        let mut c = crate::Compress::new(Compression::default(), true);
+        let mut encoded = Vec::with_capacity(1024);
+        let mut ends = Vec::new();
+        let parts = [
+            (&b"first part "[..], FlushCompress::Full),
+            (&b"second part "[..], FlushCompress::Full),
+            (&b"third part"[..], FlushCompress::Finish),
+        ];
+        for &(part, flush) in parts.iter() {
+            c.compress_vec(part, &mut encoded, flush).unwrap();
+            ends.push(encoded.len());
+        }
+        for b in &mut encoded[ends[0]..ends[1] - 4] {
+            *b = 0xff;
+        }
+
+        let mut r = read::ZlibDecoder::new(&encoded[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+
+        let mut r = read::ZlibDecoder::new(&encoded[..]);
+        r.set_recovering(true);
+        let mut decoded = Vec::new();
+        r.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"first part third part");
+
+        // Without a later flush point the damage is still an error.
+        let mut r = read::ZlibDecoder::new(&encoded[..ends[1] - 1]);
+        r.set_recovering(true);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+    }
 }
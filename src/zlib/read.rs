@@ -42,6 +42,31 @@ impl<R: Read> ZlibEncoder<R> {
             inner: bufread::ZlibEncoder::new(BufReader::new(r), level),
         }
     }
+
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(r: R, level: crate::Compression) -> io::Result<ZlibEncoder<R>> {
+        Ok(ZlibEncoder {
+            inner: bufread::ZlibEncoder::try_new(BufReader::new(r), level)?,
+        })
+    }
+
+    /// Creates a new encoder which will read uncompressed data from the given
+    /// stream and emit the compressed stream using the given `Compress`.
+    ///
+    /// This allows the compression strategy and other parameters of the
+    /// underlying [`Compress`] to be configured. The `Compress` must have been
+    /// created for a zlib stream, with `zlib_header` set to `true`.
+    ///
+    /// [`Compress`]: ../struct.Compress.html
+    pub fn new_with_compress(r: R, compression: crate::Compress) -> ZlibEncoder<R> {
+        ZlibEncoder {
+            inner: bufread::ZlibEncoder::new_with_compress(BufReader::new(r), compression),
+        }
+    }
 }
 
 impl<R> ZlibEncoder<R> {
@@ -169,6 +194,23 @@ impl<R: Read> ZlibDecoder<R> {
             inner: bufread::ZlibDecoder::new(BufReader::with_buf(buf, r)),
         }
     }
+
+    /// Creates a new decoder which will decompress data read from the given
+    /// stream using the given `Decompress`.
+    ///
+    /// The `Decompress` must have been created for a zlib stream, for
+    /// example with [`Decompress::try_new`] to handle setup failures without
+    /// panicking.
+    ///
+    /// [`Decompress::try_new`]: ../struct.Decompress.html#method.try_new
+    pub fn new_with_decompress(r: R, decompression: crate::Decompress) -> ZlibDecoder<R> {
+        ZlibDecoder {
+            inner: bufread::ZlibDecoder::new_with_decompress(
+                BufReader::with_buf(vec![0; 32 * 1024], r),
+                decompression,
+            ),
+        }
+    }
 }
 
 impl<R> ZlibDecoder<R> {
@@ -187,6 +229,29 @@ impl<R> ZlibDecoder<R> {
         self.inner.get_mut().reset(r)
     }
 
+    /// Configures whether corrupt data is skipped rather than reported as an
+    /// error.
+    ///
+    /// When enabled, a damaged region of the stream is skipped up to the next
+    /// point where it was flushed with [`FlushCompress::Full`], and decoding
+    /// resumes from there as described for [`Decompress::sync`]. The data in
+    /// the skipped region is lost. An error is still returned if no such point
+    /// follows the damage.
+    ///
+    /// [`FlushCompress::Full`]: ../enum.FlushCompress.html#variant.Full
+    /// [`Decompress::sync`]: ../struct.Decompress.html#method.sync
+    pub fn set_recovering(&mut self, recovering: bool) {
+        self.inner.set_recovering(recovering);
+    }
+
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: crate::DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
     /// Acquires a reference to the underlying stream
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
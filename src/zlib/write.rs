@@ -2,7 +2,7 @@ use std::io;
 use std::io::prelude::*;
 
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{Compress, Decompress, DecompressLimits};
 
 /// A ZLIB encoder, or compressor.
 ///
@@ -44,6 +44,34 @@ impl<W: Write> ZlibEncoder<W> {
         }
     }
 
+    /// Like [`new`], but returns an error instead of panicking if the
+    /// compressor can't be set up, for example because `level` isn't
+    /// supported by the backend in use.
+    ///
+    /// [`new`]: #method.new
+    pub fn try_new(w: W, level: crate::Compression) -> io::Result<ZlibEncoder<W>> {
+        Ok(ZlibEncoder {
+            inner: zio::Writer::new(w, Compress::try_new(level, true)?),
+        })
+    }
+
+    /// Creates a new encoder which will write compressed data to the stream
+    /// using the given `Compress`.
+    ///
+    /// This allows the compression strategy and other parameters of the
+    /// underlying [`Compress`] to be configured. The `Compress` must have been
+    /// created for a zlib stream, with `zlib_header` set to `true`.
+    ///
+    /// When this encoder is dropped or unwrapped the final pieces of data will
+    /// be flushed.
+    ///
+    /// [`Compress`]: ../struct.Compress.html
+    pub fn new_with_compress(w: W, compression: Compress) -> ZlibEncoder<W> {
+        ZlibEncoder {
+            inner: zio::Writer::new(w, compression),
+        }
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -213,11 +241,30 @@ impl<W: Write> ZlibDecoder<W> {
     /// When this decoder is dropped or unwrapped the final pieces of data will
     /// be flushed.
     pub fn new(w: W) -> ZlibDecoder<W> {
+        ZlibDecoder::new_with_decompress(w, Decompress::new(true))
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream using the given `Decompress`.
+    ///
+    /// The `Decompress` must have been created for a zlib stream, for
+    /// example with [`Decompress::try_new`] to handle setup failures without
+    /// panicking.
+    ///
+    /// [`Decompress::try_new`]: ../struct.Decompress.html#method.try_new
+    pub fn new_with_decompress(w: W, decompression: Decompress) -> ZlibDecoder<W> {
         ZlibDecoder {
-            inner: zio::Writer::new(w, Decompress::new(true)),
+            inner: zio::Writer::new(w, decompression),
         }
     }
 
+    /// Configures limits on the output of this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.limits = limits;
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()